@@ -1,65 +1,425 @@
+use std::sync::{mpsc, Arc};
+
 use anyhow::Context;
+#[cfg(any(test, not(target_arch = "wasm32")))]
+use pollster::FutureExt;
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::WindowEvent,
+    event_loop::EventLoopWindowTarget,
     keyboard::KeyCode,
-    window::{Fullscreen, Window},
+    window::{CursorGrabMode, CursorIcon, Fullscreen, Icon, Window, WindowBuilder, WindowId},
 };
 
 use crate::{
+    input::{Action, GamepadState, InputBindings, InputState},
     pipelines::{
-        debug::{DebugPipeline, DebugVertex},
-        fur::Fur,
+        fur::{grid_transforms, CullMode, LightData, Winding},
+        post_process::Tonemap,
     },
     resources::{
-        camera::{Camera, CameraBinder, CameraBinding},
+        camera::{Camera, CameraMode, Projection},
+        load_binary,
+        material::MaterialBinder,
         model::Model,
         texture::Texture,
     },
+    timing::{time_until_next_frame, Debounce, FrameTimer},
 };
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+mod headless;
+mod renderer;
+mod viewport;
+
+pub use headless::Headless;
+use renderer::{RenderTarget, Renderer};
+use viewport::Viewport;
+
+/// Shown when no model is configured, or the configured one won't load.
+/// The `embed-assets` feature builds it in under this path.
+const DEFAULT_MODEL_PATH: &str = "res/walking.glb";
+/// Built in so there's an icon wherever the app is run from.
+const DEFAULT_ICON: &[u8] = include_bytes!("../res/icon.png");
+
+/// Backgrounds that can be cycled through at runtime.
+const CLEAR_COLORS: [[f32; 4]; 4] = [
+    [0.0, 0.0, 0.0, 1.0],
+    [0.2, 0.2, 0.2, 1.0],
+    [1.0, 1.0, 1.0, 1.0],
+    [1.0, 0.0, 1.0, 1.0],
+];
+
+const EXPOSURE_STEP: f32 = 1.1;
+pub(crate) const MIN_EXPOSURE: f32 = 0.01;
+pub(crate) const MAX_EXPOSURE: f32 = 100.0;
+
+/// Radians
+const DEFAULT_FOVY: f32 = 1.0;
+/// How much one line of scrolling scales the fov or orbit radius by.
+const ZOOM_STEP: f32 = 1.1;
+/// Fraction of the orbit radius a pixel of middle-drag moves the target.
+const PAN_SPEED: f32 = 0.002;
+/// Radians per second while a roll key is held.
+const ROLL_SPEED: f32 = 1.5;
+
+/// Largest grid whose copies all fit in the fur instance buffer.
+const MAX_INSTANCE_GRID: u32 = 16;
+/// Space between copies, as a fraction of the model's size.
+const INSTANCE_SPACING: f32 = 1.5;
+
+/// Nice to have GPU features. Without `POLYGON_MODE_LINE` wireframes need
+/// extra index buffers, and without
+/// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` MSAA is limited to the sample
+/// counts every adapter supports.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE
+    .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+/// Seconds of frames averaged for the fps counter.
+const FPS_WINDOW: f32 = 0.5;
+/// How often to check on a model that's loading, when nothing else wakes
+/// the event loop.
+const MODEL_LOAD_POLL: instant::Duration = instant::Duration::from_millis(50);
+
+const SENSITIVITY_STEP: f32 = 1.25;
+pub(crate) const MIN_SENSITIVITY: f32 = 0.01;
+pub(crate) const MAX_SENSITIVITY: f32 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct GameConfig {
     fullscreen: bool,
+    fullscreen_mode: FullscreenMode,
     monitor: Option<String>,
+    /// Position in the monitor list. Takes priority over `monitor`, which
+    /// can't tell identical displays apart.
+    monitor_index: Option<usize>,
+    video_mode: Option<VideoModeConfig>,
     mouse_sensitivity: f32,
     width: u32,
     height: u32,
+    show_grid: bool,
+    grid_spacing: f32,
+    grid_extent: f32,
+    scene_format: SceneFormat,
+    exposure: f32,
+    tonemap: Tonemap,
+    clear_color: [f32; 4],
+    reverse_z: bool,
+    show_ui: bool,
+    near: f32,
+    far: f32,
+    sample_count: u32,
+    present_mode: PresentMode,
+    /// Frames per second to stay under, 0 for no limit.
+    max_fps: u32,
+    camera_position: Option<[f32; 3]>,
+    camera_target: Option<[f32; 3]>,
+    camera_mode: CameraMode,
+    projection: Projection,
+    show_normals: bool,
+    show_axes: bool,
+    /// Debug lines ignore depth and draw over the model.
+    debug_on_top: bool,
+    /// Line grid drawn through the debug pipeline, unlike `show_grid`.
+    show_debug_grid: bool,
+    /// Shown in the window title.
+    show_fps: bool,
+    normal_length: f32,
+    normal_color: [f32; 3],
+    bindings: InputBindings,
+    move_speed: f32,
+    sprint_multiplier: f32,
+    gamepad_deadzone: f32,
+    /// Radians per second at full stick.
+    stick_sensitivity: f32,
+    fur_layers: u32,
+    /// Fades out the outer shells instead of keeping them opaque.
+    translucent_fur: bool,
+    /// Writes fur normals to a second target. Nothing reads them yet.
+    gbuffer_normals: bool,
+    /// Draws the model's edges instead of its fur.
+    wireframe: bool,
+    cull_mode: CullMode,
+    /// Which way round front faces go. glTF models are counter clockwise.
+    winding: Winding,
+    /// Draws this many by this many copies of the model.
+    instance_grid: u32,
+    /// How fast morph targets get blended in and out, in radians per
+    /// second. 0 leaves the model in its rest pose.
+    morph_speed: f32,
+    /// The way the light travels, doesn't need to be normalized.
+    light_direction: [f32; 3],
+    light_color: [f32; 3],
+    ambient_color: [f32; 3],
+    /// Width and height of the shadow map in texels.
+    shadow_map_size: u32,
+    model_path: String,
+    /// Cubemap faces in +X, -X, +Y, -Y, +Z, -Z order. The clear color is
+    /// used when this is `None`.
+    skybox: Option<[String; 6]>,
+    /// PNG or JPEG to use instead of the built in window icon.
+    icon_path: Option<String>,
+    /// Seconds a changed setting has to stay put before it's saved, 0 only
+    /// saves on exit.
+    autosave_secs: f32,
+}
+
+impl GameConfig {
+    pub fn set_show_ui(&mut self, show_ui: bool) {
+        self.show_ui = show_ui;
+    }
+
+    /// A broken icon isn't worth failing over, so this logs and returns
+    /// `None` instead.
+    pub async fn window_icon(&self) -> Option<Icon> {
+        let result = match &self.icon_path {
+            Some(path) => load_binary(path)
+                .await
+                .and_then(|bytes| decode_icon(&bytes))
+                .with_context(|| format!("Unable to load icon {}", path)),
+            None => decode_icon(DEFAULT_ICON),
+        };
+        result
+            .map_err(|e| eprintln!("No window icon: {:#}", e))
+            .ok()
+    }
 }
 
 impl Default for GameConfig {
     fn default() -> Self {
         Self {
             fullscreen: false,
+            fullscreen_mode: FullscreenMode::Borderless,
             monitor: None,
+            monitor_index: None,
+            video_mode: None,
             mouse_sensitivity: 0.1,
             width: 1920,
             height: 1080,
+            show_grid: true,
+            grid_spacing: 1.0,
+            grid_extent: 20.0,
+            scene_format: SceneFormat::Rgba16Float,
+            exposure: 1.0,
+            tonemap: Tonemap::None,
+            clear_color: CLEAR_COLORS[0],
+            reverse_z: false,
+            show_ui: false,
+            near: 0.1,
+            far: 100.0,
+            sample_count: 4,
+            present_mode: PresentMode::Fifo,
+            max_fps: 0,
+            camera_position: None,
+            camera_target: None,
+            camera_mode: CameraMode::Fly,
+            projection: Projection::Perspective,
+            show_normals: false,
+            show_axes: true,
+            debug_on_top: false,
+            show_debug_grid: false,
+            show_fps: false,
+            normal_length: 0.05,
+            normal_color: [1.0, 1.0, 0.0],
+            bindings: InputBindings::default(),
+            move_speed: 0.5,
+            sprint_multiplier: 4.0,
+            gamepad_deadzone: 0.15,
+            stick_sensitivity: 2.0,
+            fur_layers: 32,
+            translucent_fur: false,
+            gbuffer_normals: false,
+            wireframe: false,
+            cull_mode: CullMode::Back,
+            winding: Winding::CounterClockwise,
+            instance_grid: 1,
+            morph_speed: 1.0,
+            light_direction: LightData::default().direction().into(),
+            light_color: LightData::default().color().into(),
+            ambient_color: LightData::default().ambient().into(),
+            shadow_map_size: 2048,
+            model_path: DEFAULT_MODEL_PATH.to_string(),
+            skybox: None,
+            icon_path: None,
+            autosave_secs: 2.0,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum FullscreenMode {
+    Borderless,
+    Exclusive,
+}
+
+/// Which video mode to use for exclusive fullscreen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct VideoModeConfig {
+    width: u32,
+    height: u32,
+    refresh_rate_millihertz: u32,
+}
+
+impl VideoModeConfig {
+    /// The resolution has to match exactly, but the refresh rate only needs
+    /// to be close. Monitors report things like 59940 instead of 60000.
+    /// `mode_of` gives the size and refresh rate in millihertz of a mode.
+    fn find_closest<T>(
+        &self,
+        modes: &[T],
+        mode_of: impl Fn(&T) -> (PhysicalSize<u32>, u32),
+    ) -> Option<usize> {
+        modes
+            .iter()
+            .map(mode_of)
+            .enumerate()
+            .filter(|(_, (size, _))| *size == PhysicalSize::new(self.width, self.height))
+            .min_by_key(|(_, (_, refresh_rate))| {
+                refresh_rate.abs_diff(self.refresh_rate_millihertz)
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+/// Formats the scene can be rendered to before post processing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum SceneFormat {
+    Rgba8Unorm,
+    Rgb10a2Unorm,
+    Rgba16Float,
+}
+
+impl SceneFormat {
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            Self::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            Self::Rgb10a2Unorm => wgpu::TextureFormat::Rgb10a2Unorm,
+            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+/// How frames get presented, mirroring the common `wgpu::PresentMode`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum PresentMode {
+    /// VSync, supported everywhere.
+    Fifo,
+    /// VSync without blocking, newer frames replace queued ones.
+    Mailbox,
+    /// No VSync, may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn present_mode(self) -> wgpu::PresentMode {
+        match self {
+            Self::Fifo => wgpu::PresentMode::Fifo,
+            Self::Mailbox => wgpu::PresentMode::Mailbox,
+            Self::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Fifo => Self::Mailbox,
+            Self::Mailbox => Self::Immediate,
+            Self::Immediate => Self::Fifo,
+        }
+    }
+}
+
+/// Extra rendering that runs after the frame has been post processed.
+///
+/// The callback is stored on the `Game`, so it can't borrow from it. The
+/// device and queue are handed in instead of being captured. The encoder is
+/// submitted once the callback returns, so don't submit it yourself. The view
+/// is the surface texture and uses `Game::surface_format`.
+pub type RenderCallback =
+    Box<dyn FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::CommandEncoder, &wgpu::TextureView)>;
+
 pub struct Game {
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    /// Kept around to make surfaces for new viewports.
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
     surface: wgpu::Surface,
     surf_config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
+    present_mode: PresentMode,
+    max_fps: u32,
     running: bool,
-    model: Model,
-    depth_texture: Texture,
-    fur: Fur,
-    debug: DebugPipeline,
+    /// Set while the window is unfocused. Nothing renders or moves.
+    paused: bool,
+    /// The surface can't be 0x0, so while minimized it keeps the last real
+    /// size and nothing gets drawn.
+    minimized: bool,
+    /// The main window gets hidden instead of closed while other viewports
+    /// are still open.
+    hidden: bool,
+    viewports: Vec<Viewport>,
+    /// `None` until the first model finishes loading.
+    model: Option<Model>,
+    model_path: String,
+    model_loader: ModelLoader,
+    /// Set if neither the configured nor the default model would load.
+    model_error: Option<String>,
+    /// Frame the model once it's loaded, unless the config had a camera
+    /// position.
+    fit_on_load: bool,
+    skybox: Option<[String; 6]>,
+    icon_path: Option<String>,
+    autosave_secs: f32,
+    autosave: Debounce<GameConfig>,
+    instance_grid: u32,
+    morph_speed: f32,
+    /// Phase of the morph animation.
+    morph_time: f32,
+    renderer: Renderer,
+    render_target: RenderTarget,
+    render_callback: Option<RenderCallback>,
+    #[cfg(feature = "ui")]
+    ui: crate::ui::Ui,
+    show_ui: bool,
+    show_normals: bool,
+    show_axes: bool,
+    show_debug_grid: bool,
+    debug_on_top: bool,
+    show_fps: bool,
+    frame_timer: FrameTimer,
+    /// What the window was called before the fps got added to it.
+    title: String,
+    normal_length: f32,
+    normal_color: glam::Vec3,
     window: Window,
+    monitor: Option<String>,
+    monitor_index: Option<usize>,
+    fullscreen_mode: FullscreenMode,
+    video_mode: Option<VideoModeConfig>,
     camera: Camera,
-    camera_binding: CameraBinding,
     last_time: Option<instant::Instant>,
+    scale_factor: f64,
+    cursor_position: PhysicalPosition<f64>,
+    look_start: PhysicalPosition<f64>,
+    /// Radians per unit of mouse motion.
     mouse_sensitivity: f32,
     lmb_pressed: bool,
-    forward: f32,
-    backward: f32,
-    left: f32,
-    right: f32,
-    up: f32,
-    down: f32,
+    mmb_pressed: bool,
+    /// Mouse motion in here gets applied once per frame in `render`, see
+    /// `handle_axis`.
+    input: InputState,
+    bindings: InputBindings,
+    /// Units per second.
+    move_speed: f32,
+    sprint_multiplier: f32,
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<crate::input::Gamepads>,
+    gamepad_deadzone: f32,
+    stick_sensitivity: f32,
+    #[cfg(feature = "hot-reload")]
+    fur_shader: crate::pipelines::hot_reload::ShaderWatcher,
+    #[cfg(feature = "hot-reload")]
+    debug_shader: crate::pipelines::hot_reload::ShaderWatcher,
 }
 
 impl Game {
@@ -69,32 +429,17 @@ impl Game {
         // Safety: surface and window are owned by game
         let surface = unsafe { instance.create_surface(&window)? };
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .await
-            .context("No valid adapter")?;
-
-        println!("{:?}", adapter.get_info());
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults(),
-                },
-                None,
-            )
-            .await?;
+        let (adapter, device, queue) = request_device(&instance, Some(&surface)).await?;
+        let (device, queue) = (Arc::new(device), Arc::new(queue));
 
         if config.fullscreen {
-            window.set_fullscreen(Some(Fullscreen::Borderless(find_or_first(
-                window.available_monitors(),
-                |m| m.name() == config.monitor,
-            ))))
+            window.set_fullscreen(choose_fullscreen(
+                &window,
+                &config.monitor,
+                config.monitor_index,
+                config.fullscreen_mode,
+                config.video_mode,
+            ))
         } else {
             let _ = window.request_inner_size(PhysicalSize {
                 width: config.width,
@@ -103,16 +448,18 @@ impl Game {
         }
 
         let caps = surface.get_capabilities(&adapter);
-        let format = caps.formats[0];
+        let format = choose_surface_format(&caps.formats)
+            .context("Surface isn't compatible with the adapter")?;
 
         println!("caps: {:?}", caps);
 
+        let present_mode = supported_present_mode(&caps.present_modes, config.present_mode);
         let surf_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: window.inner_size().width,
             height: window.inner_size().height,
-            present_mode: caps.present_modes[0],
+            present_mode: present_mode.present_mode(),
             alpha_mode: caps.alpha_modes[0],
             view_formats: Vec::new(),
         };
@@ -120,87 +467,144 @@ impl Game {
 
         println!("format: {:?}", format);
 
-        let depth_texture = Texture::depth_texture(&device, surf_config.width, surf_config.height);
+        let camera = camera_from_config(&config, surf_config.width, surf_config.height);
+        let renderer = Renderer::new(&adapter, &device, &queue, &config, surf_config.format).await;
 
-        let camera_binder = CameraBinder::new(&device);
-        let camera = Camera::look_at(
-            glam::vec3(0.0, 1.0, 4.0),
-            glam::vec3(0.0, 1.0, 0.0),
-            surf_config.width as _,
-            surf_config.height as _,
-            1.0,
-            0.1,
-            100.0,
-        );
-        let camera_binding = camera_binder.bind(&device, &camera);
-
-        let fur = Fur::new(
-            &device,
-            32,
-            surf_config.format,
-            depth_texture.format(),
-            &camera_binder,
-        );
-
-        let debug = DebugPipeline::new(&device, surf_config.format, &camera_binder);
+        #[cfg(feature = "ui")]
+        let ui = crate::ui::Ui::new(&device, surf_config.format, &window);
+        #[cfg(not(feature = "ui"))]
+        if config.show_ui {
+            eprintln!("Built without the ui feature, no ui will be shown");
+        }
 
-        let model = Model::load(&device, &queue, "res/walking.glb").await?;
+        // Big models take a while, so the window shows up without one
+        let mut model_loader = ModelLoader::default();
+        model_loader.start(
+            device.clone(),
+            queue.clone(),
+            renderer.material_binder.clone(),
+            config.model_path.clone(),
+            true,
+        );
+        let render_target =
+            renderer.create_target(&device, surf_config.width, surf_config.height, &camera);
 
-        Ok(Self {
+        let mut game = Self {
+            instance,
+            adapter,
             device,
             queue,
             surface,
             surf_config,
+            present_modes: caps.present_modes,
+            present_mode,
+            max_fps: config.max_fps,
             running: true,
+            paused: false,
+            minimized: false,
+            hidden: false,
+            viewports: Vec::new(),
             mouse_sensitivity: config.mouse_sensitivity,
-            depth_texture,
-            fur,
-            debug,
-            model,
+            renderer,
+            render_target,
+            render_callback: None,
+            #[cfg(feature = "ui")]
+            ui,
+            show_ui: config.show_ui,
+            show_normals: config.show_normals,
+            show_axes: config.show_axes,
+            show_debug_grid: config.show_debug_grid,
+            debug_on_top: config.debug_on_top,
+            show_fps: config.show_fps,
+            frame_timer: FrameTimer::new(FPS_WINDOW),
+            title: window.title(),
+            normal_length: config.normal_length,
+            normal_color: config.normal_color.into(),
+            monitor: config.monitor,
+            monitor_index: config.monitor_index,
+            fullscreen_mode: config.fullscreen_mode,
+            video_mode: config.video_mode,
+            model: None,
+            model_path: config.model_path.clone(),
+            model_loader,
+            model_error: None,
+            // A saved position means the user already picked a view they like
+            fit_on_load: config.camera_position.is_none(),
+            skybox: config.skybox,
+            icon_path: config.icon_path,
+            autosave_secs: config.autosave_secs,
+            // Replaced with what the game actually ended up with below
+            autosave: Debounce::new(instant::Duration::ZERO, GameConfig::default()),
+            instance_grid: 1,
+            morph_speed: config.morph_speed,
+            morph_time: 0.0,
             camera,
-            camera_binding,
             last_time: None,
+            scale_factor: window.scale_factor(),
+            cursor_position: PhysicalPosition::default(),
+            look_start: PhysicalPosition::default(),
             lmb_pressed: false,
+            mmb_pressed: false,
             window,
-            forward: 0.0,
-            backward: 0.0,
-            left: 0.0,
-            right: 0.0,
-            up: 0.0,
-            down: 0.0,
-        })
+            input: InputState::default(),
+            bindings: config.bindings,
+            move_speed: config.move_speed,
+            sprint_multiplier: config.sprint_multiplier,
+            #[cfg(feature = "gamepad")]
+            gamepads: crate::input::Gamepads::new(),
+            gamepad_deadzone: config.gamepad_deadzone,
+            stick_sensitivity: config.stick_sensitivity,
+            #[cfg(feature = "hot-reload")]
+            fur_shader: crate::pipelines::hot_reload::ShaderWatcher::new("src/pipelines/fur.wgsl"),
+            #[cfg(feature = "hot-reload")]
+            debug_shader: crate::pipelines::hot_reload::ShaderWatcher::new(
+                "src/pipelines/debug.wgsl",
+            ),
+        };
+        game.set_instance_grid(config.instance_grid);
+        game.update_title();
+        game.autosave = Debounce::new(
+            instant::Duration::from_secs_f32(game.autosave_secs.max(0.0)),
+            game.export_config(),
+        );
+        Ok(game)
     }
 
+    /// Does nothing if the size hasn't changed, so it's safe to call from
+    /// both `Resized` and `ScaleFactorChanged`.
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.surf_config.width = width;
-        self.surf_config.height = height;
+        let minimized = width == 0 || height == 0;
+        if self.minimized && !minimized {
+            // Same as unpausing, the time spent minimized isn't a frame
+            self.last_time = None;
+        }
+        self.minimized = minimized;
+        if !resize_view(&mut self.surf_config, &mut self.camera, width, height) {
+            return;
+        }
+
         self.surface.configure(&self.device, &self.surf_config);
-        self.camera
-            .resize(self.surf_config.width, self.surf_config.height);
-        self.depth_texture = Texture::depth_texture(&self.device, width, height);
+        self.renderer
+            .resize_target(&self.device, &mut self.render_target, width, height);
+    }
+
+    /// Keeps the logical size of the window when moving to a monitor with
+    /// different scaling. The surface gets reconfigured here, so the
+    /// `Resized` event winit sends afterwards won't reconfigure it again.
+    pub fn rescale(&mut self, scale_factor: f64) -> PhysicalSize<u32> {
+        let size = PhysicalSize::new(self.surf_config.width, self.surf_config.height)
+            .to_logical::<f64>(self.scale_factor)
+            .to_physical(scale_factor);
+        self.scale_factor = scale_factor;
+        self.resize(size.width, size.height);
+        size
     }
 
     pub fn render(&mut self) {
-        if !self.is_running() {
+        if !self.is_running() || self.paused {
             return;
         }
 
-        self.window.request_redraw();
-
-        let target = match self.surface.get_current_texture() {
-            Ok(target) => target,
-            Err(wgpu::SurfaceError::Outdated) => {
-                println!("Outdated");
-                self.surface.configure(&self.device, &self.surf_config);
-                return;
-            }
-            Err(e) => {
-                eprintln!("{}", e);
-                self.running = false;
-                return;
-            }
-        };
-
         let current_time = instant::Instant::now();
         let dt = if let Some(last_time) = self.last_time.as_mut() {
             current_time - *last_time
@@ -210,172 +614,1722 @@ impl Game {
         .as_secs_f32();
         self.last_time = Some(current_time);
 
-        self.camera
-            .walk_forward((self.forward - self.backward) * dt);
-        self.camera.walk_right((self.right - self.left) * dt);
-        self.camera.levitate_up((self.up - self.down) * dt);
-        self.camera_binding.update(&self.queue, &self.camera);
+        if self.frame_timer.tick(dt) && self.show_fps {
+            self.update_title();
+        }
 
+        if self.morph_speed != 0.0 {
+            self.morph_time = (self.morph_time + dt * self.morph_speed) % std::f32::consts::TAU;
+            // Blends the first target in and out, then the second
+            let phase = self.morph_time.sin();
+            self.renderer
+                .fur
+                .set_morph_weights(&self.queue, [phase.max(0.0), (-phase).max(0.0)]);
+        }
+
+        #[cfg(feature = "hot-reload")]
+        self.reload_shaders(false);
+
+        let gamepad = self.poll_gamepad();
+        let mut speed = self.move_speed;
+        if self.bindings.is_sprinting(&self.input) {
+            speed *= self.sprint_multiplier;
+        }
+        // Keys and sticks add up, but using both shouldn't be faster
+        let axis = |action, stick: f32| {
+            (self.bindings.value(action, &self.input) + stick).clamp(-1.0, 1.0)
+        };
+        let direction = glam::vec3(
+            axis(Action::Forward, gamepad.forward),
+            axis(Action::Right, gamepad.right),
+            axis(Action::Up, gamepad.up),
+        );
+        walk(&mut self.camera, direction, speed, dt);
+        self.look(
+            gamepad.look_right * self.stick_sensitivity * dt,
+            gamepad.look_up * self.stick_sensitivity * dt,
+        );
+        self.camera
+            .rotate_roll(self.bindings.value(Action::Roll, &self.input) * ROLL_SPEED * dt);
+        self.apply_mouse_delta();
         {
-            self.debug.clear();
-            self.debug
-                .batch(&self.device, &self.queue)
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(0.0, 0.0, 0.0),
-                    glam::vec3(0.5, 0.0, 0.0),
-                ))
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(1.0, 0.0, 0.0),
-                    glam::vec3(1.0, 0.0, 0.0),
-                ))
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(0.0, 0.0, 0.0),
-                    glam::vec3(0.0, 0.5, 0.0),
-                ))
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(0.0, 1.0, 0.0),
-                    glam::vec3(0.0, 1.0, 0.0),
-                ))
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(0.0, 0.0, 0.0),
-                    glam::vec3(0.0, 0.0, 0.5),
-                ))
-                .push_vertex(DebugVertex::new(
-                    glam::vec3(0.0, 0.0, 1.0),
-                    glam::vec3(0.0, 0.0, 1.0),
-                ));
+            let debug = &mut self.renderer.debug;
+            debug.clear();
+            let mut batch = debug.batch(&self.device, &self.queue);
+            if self.show_axes {
+                batch.push_axes(glam::Vec3::ZERO, 1.0);
+                let aabb = self.model.as_ref().map(Model::aabb);
+                if let Some(aabb) = aabb.filter(|aabb| aabb.is_valid()) {
+                    batch.push_axes(aabb.center(), aabb.size().max_element() * 0.5);
+                }
+            }
+
+            if let (true, Some(model)) = (self.show_normals, &self.model) {
+                batch.push_normals(model, self.normal_length, self.normal_color);
+            }
+
+            if self.show_debug_grid {
+                let result = batch.push_grid(
+                    self.renderer.grid.extent(),
+                    self.renderer.grid.spacing(),
+                    glam::Vec3::splat(0.3),
+                    Some(glam::Vec3::splat(0.6)),
+                );
+                // Would fail the same way every frame
+                if let Err(e) = result {
+                    eprintln!("Unable to draw debug grid: {}", e);
+                    self.show_debug_grid = false;
+                }
+            }
         }
 
-        let view = target.texture.create_view(&Default::default());
+        if !self.minimized && !self.hidden {
+            self.render_window();
+        }
+
+        for viewport in &mut self.viewports {
+            let result = viewport.render(
+                &self.device,
+                &self.queue,
+                &mut self.renderer,
+                self.model.as_ref(),
+            );
+            if let Err(e) = result {
+                eprintln!("{}, exiting", e);
+                self.running = false;
+            }
+        }
+    }
 
+    fn render_window(&mut self) {
+        let target = match current_texture(&self.surface, &self.device, &self.surf_config) {
+            Ok(Some(target)) => target,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("{}, exiting", e);
+                self.running = false;
+                return;
+            }
+        };
+
+        let view = target.texture.create_view(&Default::default());
         let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.renderer.render(
+            &self.queue,
+            &mut encoder,
+            &mut self.render_target,
+            &view,
+            &self.camera,
+            self.model.as_ref(),
+        );
 
-        {
-            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        store: wgpu::StoreOp::Store,
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    },
-                })],
-                // depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                //     view: self.depth_texture.view(),
-                //     depth_ops: Some(wgpu::Operations {
-                //         store: wgpu::StoreOp::Store,
-                //         load: wgpu::LoadOp::Clear(1.0),
-                //     }),
-                //     stencil_ops: None,
-                // }),
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        #[cfg(feature = "ui")]
+        if self.show_ui {
+            let mut controls = self.controls();
+            self.ui.draw(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &view,
+                &self.window,
+                [self.surf_config.width, self.surf_config.height],
+                |ctx| controls.show(ctx),
+            );
+            self.apply_controls(controls);
+        }
 
-            // self.fur.draw(&mut pass, &self.model, &self.camera_binding);
-            self.debug.draw_lines(&mut pass, &self.camera_binding);
+        if let Some(callback) = &mut self.render_callback {
+            callback(&self.device, &self.queue, &mut encoder, &view);
         }
 
         self.queue.submit([encoder.finish()]);
         target.present();
     }
 
+    /// Requests the next frame, unless that would go over `max_fps`. Then
+    /// it returns when to try again instead.
+    pub fn schedule_redraw(&self) -> Option<instant::Instant> {
+        // Whatever was last presented stays on screen
+        if self.paused {
+            return None;
+        }
+        // Any open window can drive the frame, as long as only one does
+        let window = if !self.minimized && !self.hidden {
+            &self.window
+        } else {
+            self.viewports.first()?.window()
+        };
+        let wait = self.last_time.map_or(instant::Duration::ZERO, |last_time| {
+            time_until_next_frame(last_time.elapsed(), self.max_fps)
+        });
+        if wait.is_zero() {
+            window.request_redraw();
+            None
+        } else {
+            Some(instant::Instant::now() + wait)
+        }
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.surf_config.format
+    }
+
+    pub fn depth_format(&self) -> wgpu::TextureFormat {
+        self.render_target.depth_texture.format()
+    }
+
+    /// Replaces any previous callback. See [`RenderCallback`].
+    pub fn set_render_callback(
+        &mut self,
+        callback: impl FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::CommandEncoder, &wgpu::TextureView)
+            + 'static,
+    ) {
+        self.render_callback = Some(Box::new(callback));
+    }
+
     pub fn show(&self) {
-        self.window.set_visible(true);
+        if !self.hidden {
+            self.window.set_visible(true);
+        }
+        for viewport in &self.viewports {
+            viewport.show();
+        }
+    }
+
+    pub fn window_id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Opens another window looking at the model from a quarter turn further
+    /// around than the last one.
+    pub fn open_viewport(&mut self, target: &EventLoopWindowTarget<()>) -> anyhow::Result<()> {
+        let window = WindowBuilder::new()
+            .with_title(format!("{} ({})", self.title, self.viewports.len() + 2))
+            .with_visible(false)
+            .build(target)?;
+
+        let size = window.inner_size();
+        let mut camera = Camera::look_at(
+            self.camera.eye(),
+            self.camera.target(),
+            size.width.max(1) as _,
+            size.height.max(1) as _,
+            self.camera.fovy(),
+            self.camera.near(),
+            self.camera.far(),
+        );
+        camera.set_reverse_z(self.renderer.format.depth().reverse_z());
+        camera.set_mode(CameraMode::Orbit);
+        camera.set_projection(self.camera.projection());
+        camera.orbit(
+            std::f32::consts::FRAC_PI_2 * (self.viewports.len() + 1) as f32,
+            0.0,
+        );
+
+        let viewport = Viewport::new(
+            &self.instance,
+            &self.adapter,
+            &self.device,
+            &self.renderer,
+            &self.surf_config,
+            window,
+            camera,
+        )?;
+        self.viewports.push(viewport);
+        Ok(())
+    }
+
+    /// Resizes whichever window `id` belongs to.
+    pub fn resize_window(&mut self, id: WindowId, width: u32, height: u32) {
+        if id == self.window.id() {
+            self.resize(width, height);
+        } else if let Some(viewport) = self.viewports.iter_mut().find(|v| v.id() == id) {
+            viewport.resize(&self.device, &self.renderer, width, height);
+        }
+    }
+
+    /// Stops running once the last window is closed. Until then the main
+    /// window just gets hidden, since it owns the state the others share.
+    pub fn close_window(&mut self, id: WindowId) {
+        if id == self.window.id() {
+            self.hidden = true;
+            self.window.set_visible(false);
+        } else {
+            self.viewports.retain(|v| v.id() != id);
+        }
+        if self.hidden && self.viewports.is_empty() {
+            self.running = false;
+        }
     }
 
     pub fn toggle_fullscreen(&mut self) {
         if self.is_fullscreen() {
             self.window.set_fullscreen(None);
         } else {
-            self.window
-                .set_fullscreen(Some(Fullscreen::Borderless(None)));
-            // self.window.set_fullscreen(Some(Fullscreen::Exclusive(self.window.current_monitor().unwrap().video_modes().next().unwrap())));
+            // Go fullscreen on whatever monitor the window is on now
+            self.monitor = self.window.current_monitor().and_then(|m| m.name());
+            self.monitor_index = self.current_monitor_index();
+            self.window.set_fullscreen(choose_fullscreen(
+                &self.window,
+                &self.monitor,
+                self.monitor_index,
+                self.fullscreen_mode,
+                self.video_mode,
+            ));
         }
+        // Some platforms change the size without sending `Resized`. If they
+        // do send it, this makes it a no-op.
+        let size = self.window.inner_size();
+        self.resize(size.width, size.height);
     }
 
     fn is_fullscreen(&mut self) -> bool {
         self.window.fullscreen().is_some()
     }
 
+    fn current_monitor_index(&self) -> Option<usize> {
+        let current = self.window.current_monitor()?;
+        self.window.available_monitors().position(|m| m == current)
+    }
+
+    /// The config, once it's changed and stayed that way for
+    /// `autosave_secs`. Where to save it is up to the caller.
+    pub fn poll_autosave(&mut self) -> Option<GameConfig> {
+        let now = instant::Instant::now();
+        if self.autosave_secs <= 0.0 || !self.autosave.is_due(now) {
+            return None;
+        }
+        let config = self.export_config();
+        self.autosave.settle(now, config).cloned()
+    }
+
     pub fn export_config(&self) -> GameConfig {
         let size = self.window.inner_size();
         GameConfig {
             fullscreen: self.window.fullscreen().is_some(),
-            monitor: self.window.current_monitor().map(|m| m.name()).flatten(),
+            fullscreen_mode: self.fullscreen_mode,
+            monitor: self.window.current_monitor().and_then(|m| m.name()),
+            monitor_index: self.current_monitor_index(),
+            video_mode: self.video_mode,
             mouse_sensitivity: self.mouse_sensitivity,
             width: size.width,
             height: size.height,
+            show_grid: self.renderer.show_grid,
+            grid_spacing: self.renderer.grid.spacing(),
+            grid_extent: self.renderer.grid.extent(),
+            scene_format: self.renderer.scene_format,
+            exposure: self.renderer.post_process.exposure(),
+            tonemap: self.renderer.post_process.tonemap(),
+            clear_color: self.renderer.clear_color,
+            reverse_z: self.renderer.format.depth().reverse_z(),
+            show_ui: self.show_ui,
+            near: self.camera.near(),
+            far: self.camera.far(),
+            sample_count: self.renderer.format.sample_count(),
+            present_mode: self.present_mode,
+            max_fps: self.max_fps,
+            camera_position: Some(self.camera.eye().into()),
+            camera_target: Some(self.camera.target().into()),
+            camera_mode: self.camera.mode(),
+            projection: self.camera.projection(),
+            show_normals: self.show_normals,
+            show_axes: self.show_axes,
+            show_debug_grid: self.show_debug_grid,
+            show_fps: self.show_fps,
+            debug_on_top: self.debug_on_top,
+            normal_length: self.normal_length,
+            normal_color: self.normal_color.into(),
+            bindings: self.bindings.clone(),
+            move_speed: self.move_speed,
+            sprint_multiplier: self.sprint_multiplier,
+            gamepad_deadzone: self.gamepad_deadzone,
+            stick_sensitivity: self.stick_sensitivity,
+            fur_layers: self.renderer.fur.num_layers(),
+            translucent_fur: self.renderer.translucent_fur,
+            gbuffer_normals: self.renderer.format.targets().len() > 1,
+            wireframe: self.renderer.wireframe,
+            cull_mode: self.renderer.fur.cull_mode(),
+            winding: self.renderer.fur.winding(),
+            instance_grid: self.instance_grid,
+            morph_speed: self.morph_speed,
+            light_direction: self.renderer.fur.light().direction().into(),
+            light_color: self.renderer.fur.light().color().into(),
+            ambient_color: self.renderer.fur.light().ambient().into(),
+            shadow_map_size: self.renderer.shadow.shadow_map().size().width,
+            model_path: self.model_path.clone(),
+            skybox: self.skybox.clone(),
+            icon_path: self.icon_path.clone(),
+            autosave_secs: self.autosave_secs,
+        }
+    }
+
+    /// Gives the ui first pick of window events. Returns true if the ui
+    /// consumed the event and the game should ignore it.
+    #[cfg_attr(not(feature = "ui"), allow(unused_variables))]
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        #[cfg(feature = "ui")]
+        if self.show_ui {
+            return self.ui.handle_event(&self.window, event);
+        }
+        false
+    }
+
+    #[cfg(feature = "ui")]
+    fn controls(&self) -> crate::ui::Controls {
+        crate::ui::Controls {
+            exposure: self.renderer.post_process.exposure(),
+            tonemap: self.renderer.post_process.tonemap(),
+            show_grid: self.renderer.show_grid,
+            clear_color: self.renderer.clear_color,
+            mouse_sensitivity: self.mouse_sensitivity,
+            move_speed: self.move_speed,
+            present_mode: self.present_mode,
+            present_modes: [
+                PresentMode::Fifo,
+                PresentMode::Mailbox,
+                PresentMode::Immediate,
+            ]
+            .into_iter()
+            .filter(|mode| self.present_modes.contains(&mode.present_mode()))
+            .collect(),
+            fur: self.renderer.fur.params(),
+            fur_layers: self.renderer.fur.num_layers(),
+            light_direction: self.renderer.fur.light().direction().into(),
+            light_color: self.renderer.fur.light().color().into(),
+            ambient_color: self.renderer.fur.light().ambient().into(),
+            reload_model: false,
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn apply_controls(&mut self, controls: crate::ui::Controls) {
+        if controls.exposure != self.renderer.post_process.exposure() {
+            self.renderer
+                .post_process
+                .set_exposure(&self.queue, controls.exposure);
+        }
+        if controls.tonemap != self.renderer.post_process.tonemap() {
+            self.renderer
+                .post_process
+                .set_tonemap(&self.queue, controls.tonemap);
+        }
+        self.renderer.show_grid = controls.show_grid;
+        self.renderer.clear_color = controls.clear_color;
+        self.mouse_sensitivity = controls.mouse_sensitivity;
+        self.move_speed = controls.move_speed;
+        if controls.present_mode != self.present_mode {
+            self.set_present_mode(controls.present_mode);
+        }
+
+        let fur = &mut self.renderer.fur;
+        if controls.fur != fur.params() {
+            fur.set_params(&self.queue, controls.fur);
+        }
+        if controls.fur_layers != fur.num_layers() {
+            fur.set_num_layers(&self.queue, controls.fur_layers);
+        }
+        let light = LightData::new(
+            controls.light_direction.into(),
+            controls.light_color.into(),
+            controls.ambient_color.into(),
+        );
+        if light != fur.light() {
+            fur.set_light_data(&self.queue, light);
+        }
+
+        if controls.reload_model {
+            self.reload_model();
         }
     }
 
+    /// Rebuilds pipelines whose shaders changed on disk, or all of them with
+    /// `force`. Errors get printed and the old pipeline keeps running.
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self, force: bool) {
+        if let Some(source) = self.fur_shader.poll(force) {
+            match self.renderer.fur.reload(&self.device, &source) {
+                Ok(()) => println!("Reloaded fur.wgsl"),
+                Err(e) => eprintln!("Unable to reload fur.wgsl: {}", e),
+            }
+        }
+        if let Some(source) = self.debug_shader.poll(force) {
+            match self.renderer.debug.reload(&self.device, &source) {
+                Ok(()) => println!("Reloaded debug.wgsl"),
+                Err(e) => eprintln!("Unable to reload debug.wgsl: {}", e),
+            }
+        }
+    }
+
+    /// All zeros if there's no gamepad.
+    fn poll_gamepad(&mut self) -> GamepadState {
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepads) = &mut self.gamepads {
+            return gamepads.poll(self.gamepad_deadzone);
+        }
+        GamepadState::default()
+    }
+
+    /// Pauses while unfocused. Keys released in another window never reach
+    /// this one, so all input gets dropped on the way out.
+    pub fn handle_focused(&mut self, focused: bool) {
+        self.paused = !focused;
+        if self.paused {
+            self.clear_inputs();
+        } else {
+            // Don't count the time spent paused as one long frame
+            self.last_time = None;
+        }
+    }
+
+    /// Releases every held key and mouse button, giving the cursor back if
+    /// it was grabbed for looking around.
+    pub fn clear_inputs(&mut self) {
+        self.input.clear();
+        if self.lmb_pressed {
+            self.handle_mouse_button(winit::event::MouseButton::Left, false);
+        }
+        self.mmb_pressed = false;
+    }
+
+    /// Only collects the motion, `render` applies it once per frame with
+    /// `apply_mouse_delta`. Motion while no button is held is dropped.
     pub fn handle_axis(&mut self, axis: u32, value: f32) {
+        if !self.paused && (self.lmb_pressed || self.mmb_pressed) {
+            self.input.add_mouse_motion(axis, value);
+        }
+    }
+
+    /// Looks or pans with the mouse motion since the last frame.
+    fn apply_mouse_delta(&mut self) {
+        let (x, y) = self.input.take_mouse_delta();
         if self.lmb_pressed {
-            match axis {
-                0 => self.camera.rotate_right(value * self.mouse_sensitivity),
-                1 => self.camera.rotate_up(-value * self.mouse_sensitivity),
-                _ => (),
+            self.look(x * self.mouse_sensitivity, -y * self.mouse_sensitivity);
+        } else if self.mmb_pressed && self.camera.mode() == CameraMode::Orbit {
+            // Drag the scene with the cursor
+            self.camera.pan(-x * PAN_SPEED, y * PAN_SPEED);
+        }
+    }
+
+    /// Turns the camera in fly mode, and circles the target in orbit mode.
+    fn look(&mut self, right: f32, up: f32) {
+        match self.camera.mode() {
+            CameraMode::Fly => {
+                self.camera.rotate_right(right);
+                self.camera.rotate_up(up);
             }
+            CameraMode::Orbit => self.camera.orbit(-right, -up),
+        }
+    }
+
+    /// `delta` is in lines, positive when scrolling up/away, which zooms in.
+    /// Scaling instead of adding keeps the speed consistent at every zoom
+    /// level.
+    pub fn handle_scroll(&mut self, delta: f32) {
+        let scale = ZOOM_STEP.powf(-delta);
+        match self.camera.mode() {
+            CameraMode::Fly => self.camera.set_fovy(self.camera.fovy() * scale),
+            CameraMode::Orbit => self
+                .camera
+                .set_orbit_radius(self.camera.orbit_radius() * scale),
         }
     }
 
+    /// Positions are kept in physical pixels, the same space as the surface,
+    /// so they can be used for picking regardless of the scale factor.
+    pub fn handle_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor_position = position;
+    }
+
+    pub fn cursor_position(&self) -> PhysicalPosition<f64> {
+        self.cursor_position
+    }
+
     pub fn handle_mouse_button(&mut self, button: winit::event::MouseButton, pressed: bool) {
         match button {
             winit::event::MouseButton::Left => {
                 self.lmb_pressed = pressed;
                 if self.lmb_pressed {
+                    self.look_start = self.cursor_position;
                     self.window.set_cursor_visible(false);
+                    self.grab_cursor(true);
                 } else {
+                    self.grab_cursor(false);
+                    // Confined cursors still move, so put it back where the
+                    // drag started.
+                    let _ = self.window.set_cursor_position(self.look_start);
                     self.window.set_cursor_visible(true);
                 }
             }
             winit::event::MouseButton::Right => (),
-            winit::event::MouseButton::Middle => (),
+            winit::event::MouseButton::Middle => self.mmb_pressed = pressed,
             winit::event::MouseButton::Back => (),
             winit::event::MouseButton::Forward => (),
             winit::event::MouseButton::Other(_) => (),
         }
     }
 
+    fn grab_cursor(&self, grab: bool) {
+        if let Err(e) = set_cursor_grab(grab, |mode| self.window.set_cursor_grab(mode)) {
+            eprintln!("Unable to change cursor grab: {}", e);
+        }
+    }
+
     pub fn handle_keyboard(&mut self, key: KeyCode, pressed: bool) {
+        // Held keys are read through the bindings every frame
+        self.input.set_pressed(key, pressed);
+
         match (key, pressed) {
-            (KeyCode::Escape, true) => self.running = false,
+            (KeyCode::Escape, true) => {
+                self.clear_inputs();
+                self.running = false;
+            }
             (KeyCode::F11, true) => self.toggle_fullscreen(),
-            (KeyCode::KeyW, true) => self.forward = 0.5,
-            (KeyCode::KeyW, false) => self.forward = 0.0,
-            (KeyCode::KeyS, true) => self.backward = 0.5,
-            (KeyCode::KeyS, false) => self.backward = 0.0,
-            (KeyCode::KeyD, true) => self.right = 0.5,
-            (KeyCode::KeyD, false) => self.right = 0.0,
-            (KeyCode::KeyA, true) => self.left = 0.5,
-            (KeyCode::KeyA, false) => self.left = 0.0,
-            (KeyCode::Space, true) => self.up = 0.5,
-            (KeyCode::Space, false) => self.up = 0.0,
-            (KeyCode::ShiftLeft, true) => self.down = 0.5,
-            (KeyCode::ShiftLeft, false) => self.down = 0.0,
+            (KeyCode::F1, true) => self.show_ui = !self.show_ui,
+            (KeyCode::KeyG, true) => self.renderer.show_grid = !self.renderer.show_grid,
+            (KeyCode::KeyL, true) => self.renderer.wireframe = !self.renderer.wireframe,
+            (KeyCode::KeyK, true) => {
+                let cull_mode = self.renderer.fur.cull_mode().next();
+                self.renderer.fur.set_cull_mode(&self.device, cull_mode);
+                println!("cull mode: {:?}", cull_mode);
+            }
+            (KeyCode::KeyZ, true) => self.camera.set_fovy(DEFAULT_FOVY),
+            (KeyCode::KeyR, true) => self.camera.level(),
+            (KeyCode::KeyC, true) => {
+                let mode = self.camera.mode().next();
+                self.camera.set_mode(mode);
+                println!("camera mode: {:?}", mode);
+            }
+            (KeyCode::KeyP, true) => {
+                let projection = self.camera.projection().next();
+                self.camera.set_projection(projection);
+                println!("projection: {:?}", projection);
+            }
+            (KeyCode::KeyN, true) => self.show_normals = !self.show_normals,
+            #[cfg(feature = "hot-reload")]
+            (KeyCode::F5, true) => self.reload_shaders(true),
+            (KeyCode::PageUp, true) => self.step_fur_layers(1),
+            (KeyCode::PageDown, true) => self.step_fur_layers(-1),
+            (KeyCode::KeyI, true) => {
+                let n = self.instance_grid % MAX_INSTANCE_GRID + 1;
+                self.set_instance_grid(n);
+                println!("instances: {}x{}", n, n);
+            }
+            (KeyCode::KeyX, true) => self.show_axes = !self.show_axes,
+            (KeyCode::KeyH, true) => self.show_debug_grid = !self.show_debug_grid,
+            (KeyCode::F3, true) => self.toggle_fps(),
+            (KeyCode::F9, true) => self.reload_model(),
+            // Reading the frame back blocks, which the browser doesn't allow
+            #[cfg(not(target_arch = "wasm32"))]
+            (KeyCode::F12, true) => self.take_screenshot(),
+            (KeyCode::KeyF, true) => {
+                if let Some(model) = &self.model {
+                    self.camera.fit_to(model.aabb());
+                }
+            }
+            (KeyCode::Equal, true) => self.scale_exposure(EXPOSURE_STEP),
+            (KeyCode::Minus, true) => self.scale_exposure(1.0 / EXPOSURE_STEP),
+            (KeyCode::KeyB, true) => self.cycle_clear_color(),
+            (KeyCode::BracketRight, true) => self.scale_sensitivity(SENSITIVITY_STEP),
+            (KeyCode::BracketLeft, true) => self.scale_sensitivity(1.0 / SENSITIVITY_STEP),
+            (KeyCode::KeyV, true) => self.cycle_present_mode(),
+            (KeyCode::KeyT, true) => {
+                let tonemap = self.renderer.post_process.tonemap().next();
+                self.renderer.post_process.set_tonemap(&self.queue, tonemap);
+                println!("tonemap: {:?}", tonemap);
+            }
             _ => (),
         }
     }
 
+    /// Skips over modes the surface doesn't support.
+    fn cycle_present_mode(&mut self) {
+        let mut mode = self.present_mode.next();
+        while !self.present_modes.contains(&mode.present_mode()) {
+            mode = mode.next();
+        }
+        self.set_present_mode(mode);
+        println!("present mode: {:?}", mode);
+    }
+
+    /// `mode` needs to be one the surface supports.
+    fn set_present_mode(&mut self, mode: PresentMode) {
+        self.present_mode = mode;
+        self.surf_config.present_mode = mode.present_mode();
+        self.surface.configure(&self.device, &self.surf_config);
+    }
+
+    fn cycle_clear_color(&mut self) {
+        // A custom color from the config starts back at the first preset
+        let next = CLEAR_COLORS
+            .iter()
+            .position(|c| *c == self.renderer.clear_color)
+            .map(|i| (i + 1) % CLEAR_COLORS.len())
+            .unwrap_or(0);
+        self.renderer.clear_color = CLEAR_COLORS[next];
+        println!("clear color: {:?}", self.renderer.clear_color);
+    }
+
+    fn scale_exposure(&mut self, amount: f32) {
+        let exposure =
+            (self.renderer.post_process.exposure() * amount).clamp(MIN_EXPOSURE, MAX_EXPOSURE);
+        self.renderer
+            .post_process
+            .set_exposure(&self.queue, exposure);
+        println!("exposure: {}", exposure);
+    }
+
+    /// Swaps in the model once the loader is done with it. Finishing a load
+    /// doesn't wake the event loop, so this gets called from `AboutToWait`,
+    /// and returns when to check again if it's still loading.
+    pub fn poll_model_loader(&mut self) -> Option<instant::Instant> {
+        let Some(result) = self.model_loader.poll() else {
+            return self
+                .model_loader
+                .is_loading()
+                .then(|| instant::Instant::now() + MODEL_LOAD_POLL);
+        };
+
+        match result {
+            Ok((model, path)) => {
+                if self.fit_on_load {
+                    self.camera.fit_to(model.aabb());
+                }
+                // The old model's buffers and textures get dropped here
+                self.model = Some(model);
+                self.model_path = path;
+                self.model_error = None;
+                // Spacing depends on the model's size
+                self.set_instance_grid(self.instance_grid);
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                self.model_error = Some(format!("{:#}", e));
+            }
+        }
+        self.update_title();
+        // Shows the new model even if paused
+        self.window.request_redraw();
+        None
+    }
+
+    /// Starts replacing the current model, which gets swapped in and
+    /// framed by `poll_model_loader` once it's loaded. The current model
+    /// stays if the new one fails, and the error shows up in the title.
+    /// Whatever was still loading gets replaced.
+    pub fn load_model(&mut self, path: &str) {
+        self.model_loader.start(
+            self.device.clone(),
+            self.queue.clone(),
+            self.renderer.material_binder.clone(),
+            path.to_string(),
+            false,
+        );
+        self.fit_on_load = true;
+        self.update_title();
+    }
+
+    /// Saves the last rendered frame as a PNG. This redoes post processing
+    /// into its own texture, so the ui and render callback won't be in it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn screenshot(&self, path: &str) -> anyhow::Result<()> {
+        let target = Texture::color_target(
+            &self.device,
+            self.surf_config.width,
+            self.surf_config.height,
+            self.surf_config.format,
+        );
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.renderer
+            .post_process_into(&mut encoder, &self.render_target, target.view());
+        self.queue.submit([encoder.finish()]);
+
+        let image = target.read_to_image(&self.device, &self.queue)?;
+        image
+            .save(path)
+            .with_context(|| format!("Unable to save screenshot to {}", path))?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn take_screenshot(&self) {
+        let seconds = instant::SystemTime::now()
+            .duration_since(instant::SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = format!("screenshot-{}.png", seconds);
+        match self.screenshot(&path) {
+            Ok(()) => println!("Saved {}", path),
+            Err(e) => eprintln!("{:#}", e),
+        }
+    }
+
+    /// Shows whether the file being dragged over the window can be dropped.
+    /// `None` once it leaves.
+    pub fn handle_hovered_file(&mut self, path: Option<&std::path::Path>) {
+        let icon = match path {
+            Some(path) if is_model_file(path) => {
+                println!("Drop to load {}", path.display());
+                CursorIcon::Copy
+            }
+            Some(_) => CursorIcon::NotAllowed,
+            None => CursorIcon::Default,
+        };
+        self.window.set_cursor_icon(icon);
+    }
+
+    pub fn handle_dropped_file(&mut self, path: &std::path::Path) {
+        self.window.set_cursor_icon(CursorIcon::Default);
+        if !is_model_file(path) {
+            eprintln!(
+                "Unable to load {}, only .glb, .gltf and .obj files are supported",
+                path.display()
+            );
+            return;
+        }
+        self.load_model(&path.to_string_lossy());
+    }
+
+    /// Reads the model from disk again, keeping the current one if that
+    /// fails.
+    fn reload_model(&mut self) {
+        let path = self.model_path.clone();
+        self.load_model(&path);
+    }
+
+    /// Draws `n` by `n` copies of the model, spaced so they don't overlap.
+    /// Clamped between 1 and `MAX_INSTANCE_GRID`.
+    pub fn set_instance_grid(&mut self, n: u32) {
+        self.instance_grid = n.clamp(1, MAX_INSTANCE_GRID);
+        // Gets called again once there's a model
+        let Some(model) = &self.model else {
+            return;
+        };
+        let aabb = model.aabb();
+        let size = if aabb.is_valid() {
+            aabb.size().x.max(aabb.size().z)
+        } else {
+            1.0
+        };
+        let transforms = grid_transforms(self.instance_grid, size * INSTANCE_SPACING);
+        self.renderer.fur.set_instances(&self.queue, &transforms);
+    }
+
+    fn toggle_fps(&mut self) {
+        self.show_fps = !self.show_fps;
+        self.update_title();
+    }
+
+    /// There's no text rendering, so the fps and loading status go in the
+    /// window title.
+    fn update_title(&self) {
+        let mut title = self.title.clone();
+        if self.model_loader.is_loading() {
+            title += &format!(" - loading {}", self.model_path);
+        } else if let Some(error) = &self.model_error {
+            title += &format!(" - {}", error);
+        }
+        if let (true, Some(frame_time)) = (self.show_fps, self.frame_timer.frame_time()) {
+            title += &format!(
+                " - {:.1} fps ({:.2} ms)",
+                1.0 / frame_time,
+                frame_time * 1000.0
+            );
+        }
+        self.window.set_title(&title);
+    }
+
+    fn step_fur_layers(&mut self, amount: i32) {
+        let num_layers = self.renderer.fur.num_layers().saturating_add_signed(amount);
+        self.renderer.fur.set_num_layers(&self.queue, num_layers);
+        println!("fur layers: {}", self.renderer.fur.num_layers());
+    }
+
+    fn scale_sensitivity(&mut self, amount: f32) {
+        self.mouse_sensitivity = scaled_sensitivity(self.mouse_sensitivity, amount);
+        println!("mouse sensitivity: {}", self.mouse_sensitivity);
+    }
+
     pub fn is_running(&self) -> bool {
         self.running
     }
 }
 
-fn find_or_first<T>(
-    mut iter: impl Iterator<Item = T>,
-    predicate: impl Fn(&T) -> bool,
-) -> Option<T> {
-    let mut found = iter.next();
+/// `set` is `Window::set_cursor_grab`. Not every platform supports
+/// locking, but confining the cursor still keeps it from leaving the
+/// window.
+fn set_cursor_grab<E>(grab: bool, set: impl Fn(CursorGrabMode) -> Result<(), E>) -> Result<(), E> {
+    if grab {
+        set(CursorGrabMode::Locked).or_else(|_| set(CursorGrabMode::Confined))
+    } else {
+        set(CursorGrabMode::None)
+    }
+}
+
+fn decode_icon(bytes: &[u8]) -> anyhow::Result<Icon> {
+    let (rgba, width, height) = icon_rgba(bytes)?;
+    Ok(Icon::from_rgba(rgba, width, height)?)
+}
+
+/// Tightly packed RGBA pixels and their width and height, as
+/// `Icon::from_rgba` wants them.
+fn icon_rgba(bytes: &[u8]) -> anyhow::Result<(Vec<u8>, u32, u32)> {
+    let image = image::load_from_memory(bytes)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok((image.into_raw(), width, height))
+}
+
+/// Takes a new window size into `surf_config` and the camera's aspect.
+/// Returns false if the surface doesn't need configuring again, either
+/// because nothing changed or because a zero width or height would make it
+/// invalid, in which case the last valid size is kept.
+fn resize_view(
+    surf_config: &mut wgpu::SurfaceConfiguration,
+    camera: &mut Camera,
+    width: u32,
+    height: u32,
+) -> bool {
+    if width == 0 || height == 0 || width == surf_config.width && height == surf_config.height {
+        return false;
+    }
+    surf_config.width = width;
+    surf_config.height = height;
+    camera.resize(width, height);
+    true
+}
+
+/// Gets the next texture to draw to. `None` means skip this frame, the
+/// surface has been reconfigured if that would help. Only running out of
+/// memory is an error.
+fn current_texture(
+    surface: &wgpu::Surface,
+    device: &wgpu::Device,
+    surf_config: &wgpu::SurfaceConfiguration,
+) -> Result<Option<wgpu::SurfaceTexture>, wgpu::SurfaceError> {
+    let error = match surface.get_current_texture() {
+        Ok(target) => return Ok(Some(target)),
+        Err(error) => error,
+    };
+    match surface_error_action(&error) {
+        // The next frame tries again with a fresh surface
+        SurfaceErrorAction::Reconfigure => {
+            eprintln!("{}, reconfiguring", error);
+            surface.configure(device, surf_config);
+            Ok(None)
+        }
+        SurfaceErrorAction::Skip => {
+            eprintln!("{}, skipping a frame", error);
+            Ok(None)
+        }
+        SurfaceErrorAction::Exit => Err(error),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SurfaceErrorAction {
+    Reconfigure,
+    Skip,
+    Exit,
+}
+
+/// What [`current_texture`] does about each error.
+fn surface_error_action(error: &wgpu::SurfaceError) -> SurfaceErrorAction {
+    match error {
+        wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost => SurfaceErrorAction::Reconfigure,
+        wgpu::SurfaceError::Timeout => SurfaceErrorAction::Skip,
+        wgpu::SurfaceError::OutOfMemory => SurfaceErrorAction::Exit,
+    }
+}
+
+/// `compatible_surface` is `None` when rendering headless.
+async fn request_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> anyhow::Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface,
+            ..Default::default()
+        })
+        .await
+        .context("No valid adapter")?;
+
+    println!("{:?}", adapter.get_info());
+
+    let features = negotiate_features(OPTIONAL_FEATURES, adapter.features());
+    let limits = negotiate_limits(&adapter.limits());
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features,
+                limits,
+            },
+            None,
+        )
+        .await?;
+
+    Ok((adapter, device, queue))
+}
+
+/// Moves `speed` units per second along each axis of `direction`, which
+/// are forward, right and up, from -1 to 1.
+fn walk(camera: &mut Camera, direction: glam::Vec3, speed: f32, dt: f32) {
+    let distance = speed * dt;
+    camera.walk_forward(direction.x * distance);
+    camera.walk_right(direction.y * distance);
+    camera.levitate_up(direction.z * distance);
+}
+
+/// `sensitivity` times `amount`, kept between `MIN_SENSITIVITY` and
+/// `MAX_SENSITIVITY`.
+fn scaled_sensitivity(sensitivity: f32, amount: f32) -> f32 {
+    (sensitivity * amount).clamp(MIN_SENSITIVITY, MAX_SENSITIVITY)
+}
+
+/// For tests that need a GPU. `None` if there's no adapter, in which case
+/// they should skip their checks rather than fail.
+#[cfg(test)]
+pub(crate) fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let (_, device, queue) = test_backend_device()?;
+    Some((device, queue))
+}
+
+/// `test_device`, plus the backend it's on, for tests of things some
+/// backends can't do.
+#[cfg(test)]
+pub(crate) fn test_backend_device() -> Option<(wgpu::Backend, wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(Default::default());
+    let (adapter, device, queue) = request_device(&instance, None).block_on().ok()?;
+    Some((adapter.get_info().backend, device, queue))
+}
+
+/// Takes whatever of `wanted` the adapter has. Everything in here needs a
+/// fallback, since it won't be there on every adapter.
+fn negotiate_features(wanted: wgpu::Features, available: wgpu::Features) -> wgpu::Features {
+    let missing = wanted - available;
+    if !missing.is_empty() {
+        eprintln!(
+            "Adapter doesn't support {:?}, some things may be slower",
+            missing
+        );
+    }
+    wanted & available
+}
+
+/// Starts from limits any device can meet and raises the ones big models
+/// run into as far as the adapter allows. Loaders check sizes against
+/// `device.limits()`.
+fn negotiate_limits(adapter: &wgpu::Limits) -> wgpu::Limits {
+    wgpu::Limits {
+        max_buffer_size: adapter.max_buffer_size,
+        ..wgpu::Limits::downlevel_defaults()
+    }
+    .using_resolution(adapter.clone())
+}
+
+fn camera_from_config(config: &GameConfig, width: u32, height: u32) -> Camera {
+    let mut camera = Camera::look_at(
+        config
+            .camera_position
+            .map_or(glam::vec3(0.0, 1.0, 4.0), glam::Vec3::from),
+        config
+            .camera_target
+            .map_or(glam::vec3(0.0, 1.0, 0.0), glam::Vec3::from),
+        width as _,
+        height as _,
+        DEFAULT_FOVY,
+        config.near,
+        config.far,
+    );
+    camera.set_reverse_z(config.reverse_z);
+    camera.set_mode(config.camera_mode);
+    camera.set_projection(config.projection);
+    camera
+}
+
+fn is_model_file(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["glb", "gltf", "obj"]
+                .iter()
+                .any(|supported| ext.eq_ignore_ascii_case(supported))
+        })
+}
+
+/// Loads models on their own thread, so the window can show up and keep
+/// drawing in the meantime. In the browser it's a task on the event loop
+/// instead, since there are no threads to block. Only the latest load
+/// counts, starting another one drops whatever was still loading.
+#[derive(Default)]
+struct ModelLoader {
+    receiver: Option<mpsc::Receiver<anyhow::Result<(Model, String)>>>,
+    /// Threads that might still be running, replaced loads included.
+    #[cfg(not(target_arch = "wasm32"))]
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl ModelLoader {
+    /// With `fallback` the default model gets loaded if `path` fails.
+    fn start(
+        &mut self,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        material_binder: Arc<MaterialBinder>,
+        path: String,
+        fallback: bool,
+    ) {
+        let (sender, receiver) = mpsc::channel();
+        let load = async move {
+            let result =
+                load_model_or_default(&device, &queue, &material_binder, &path, fallback).await;
+            // Nobody's listening if another load replaced this one
+            let _ = sender.send(result);
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.threads.retain(|thread| !thread.is_finished());
+            self.threads
+                .push(std::thread::spawn(move || load.block_on()));
+        }
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(load);
+        self.receiver = Some(receiver);
+    }
+
+    fn is_loading(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// The latest load's result, once it's done. Each result only comes
+    /// out once.
+    fn poll(&mut self) -> Option<anyhow::Result<(Model, String)>> {
+        let result = match self.receiver.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("Model loader stopped without a model"))
+            }
+        };
+        self.receiver = None;
+        Some(result)
+    }
+
+    /// Waits for every load to finish, replaced ones included, so none of
+    /// them are still using the device.
+    #[cfg(test)]
+    fn join(&mut self) {
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap();
+        }
+    }
+}
+
+/// Loads `path`, or the default model if that fails and `fallback` is set.
+/// Returns the path that actually got loaded.
+async fn load_model_or_default(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material_binder: &MaterialBinder,
+    path: &str,
+    fallback: bool,
+) -> anyhow::Result<(Model, String)> {
+    let error = match Model::load(device, queue, material_binder, path).await {
+        Ok(model) => return Ok((model, path.to_string())),
+        Err(e) if fallback && path != DEFAULT_MODEL_PATH => e,
+        Err(e) => return Err(e.context(format!("Unable to load {}", path))),
+    };
+    eprintln!(
+        "Unable to load {}, using {} instead: {:#}",
+        path, DEFAULT_MODEL_PATH, error
+    );
+    let model = Model::load(device, queue, material_binder, DEFAULT_MODEL_PATH).await?;
+    Ok((model, DEFAULT_MODEL_PATH.to_string()))
+}
+
+/// Falls back to the primary monitor if the configured one isn't connected
+/// anymore, and to borderless if there's no monitor or video mode to go
+/// exclusive with.
+fn choose_fullscreen(
+    window: &Window,
+    monitor: &Option<String>,
+    monitor_index: Option<usize>,
+    mode: FullscreenMode,
+    video_mode: Option<VideoModeConfig>,
+) -> Option<Fullscreen> {
+    let mut monitors = window.available_monitors().collect::<Vec<_>>();
+    let handle = match find_monitor(&monitors, monitor_index, monitor, |m| m.name()) {
+        Some(i) => Some(monitors.swap_remove(i)),
+        None => window
+            .primary_monitor()
+            .or_else(|| monitors.into_iter().next()),
+    };
+    println!("Using monitor {:?}", handle.as_ref().and_then(|h| h.name()));
+
+    if mode == FullscreenMode::Exclusive {
+        let video_mode = handle.as_ref().and_then(|h| {
+            let mut modes = h.video_modes().collect::<Vec<_>>();
+            match video_mode {
+                Some(config) => {
+                    match config.find_closest(&modes, |m| (m.size(), m.refresh_rate_millihertz())) {
+                        Some(i) => Some(modes.swap_remove(i)),
+                        None => {
+                            eprintln!("Monitor has no {}x{} mode", config.width, config.height);
+                            None
+                        }
+                    }
+                }
+                // Default to the biggest, fastest mode
+                None => modes.into_iter().max_by_key(|m| {
+                    (
+                        m.size().width * m.size().height,
+                        m.refresh_rate_millihertz(),
+                    )
+                }),
+            }
+        });
+
+        match video_mode {
+            Some(video_mode) => {
+                println!("Using video mode {}", video_mode);
+                return Some(Fullscreen::Exclusive(video_mode));
+            }
+            None => eprintln!("No video mode available, using borderless fullscreen"),
+        }
+    }
+
+    Some(Fullscreen::Borderless(handle))
+}
+
+/// Fifo is the only mode every surface has to support.
+fn supported_present_mode(supported: &[wgpu::PresentMode], requested: PresentMode) -> PresentMode {
+    if supported.contains(&requested.present_mode()) {
+        requested
+    } else {
+        eprintln!("{:?} isn't supported, using Fifo", requested);
+        PresentMode::Fifo
+    }
+}
+
+/// Prefers an sRGB format so colors come out right, otherwise takes
+/// whatever the surface likes best.
+fn choose_surface_format(formats: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+    let preferred = [
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ];
+    let format = preferred
+        .into_iter()
+        .find(|f| formats.contains(f))
+        .or_else(|| formats.first().copied())?;
+
+    if !format.is_srgb() {
+        eprintln!("No sRGB surface format available, colors may look off");
+    }
+
+    Some(format)
+}
+
+/// Picks the highest sample count up to `requested` that every format
+/// supports. The device only allows more than the guaranteed counts if
+/// it was created with `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`.
+fn choose_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    formats: &[wgpu::TextureFormat],
+    requested: u32,
+) -> u32 {
+    let adapter_specific = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let sample_count = [8, 4, 2, 1]
+        .into_iter()
+        .filter(|count| *count <= requested)
+        .find(|count| {
+            formats.iter().all(|f| {
+                let features = if adapter_specific {
+                    adapter.get_texture_format_features(*f)
+                } else {
+                    f.guaranteed_format_features(device.features())
+                };
+                features.flags.sample_count_supported(*count)
+            })
+        })
+        .unwrap_or(1);
+
+    if sample_count != requested {
+        eprintln!(
+            "{} samples aren't supported, using {}",
+            requested, sample_count
+        );
+    }
+
+    sample_count
+}
+
+/// Index wins over name, since names can be missing or shared by identical
+/// displays. `None` means neither matched and the caller should fall back
+/// to the primary monitor.
+fn find_monitor<T>(
+    monitors: &[T],
+    index: Option<usize>,
+    name: &Option<String>,
+    name_of: impl Fn(&T) -> Option<String>,
+) -> Option<usize> {
+    if let Some(index) = index {
+        if index < monitors.len() {
+            return Some(index);
+        }
+        eprintln!("There's no monitor {}, only {}", index, monitors.len());
+    }
 
-    for item in iter {
-        if predicate(&item) {
-            found = Some(item);
-            break;
+    if let Some(name) = name {
+        let found = monitors
+            .iter()
+            .position(|m| name_of(m).as_ref() == Some(name));
+        if found.is_some() {
+            return found;
         }
+        eprintln!("Monitor {:?} not found", name);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::look_at(
+            glam::Vec3::ZERO,
+            glam::Vec3::NEG_Z,
+            1.0,
+            1.0,
+            DEFAULT_FOVY,
+            0.1,
+            100.0,
+        )
+    }
+
+    #[test]
+    fn walking_covers_speed_times_dt() {
+        let mut forward = camera();
+        walk(&mut forward, glam::vec3(1.0, 0.0, 0.0), 2.0, 0.25);
+        assert!(forward.eye().abs_diff_eq(glam::vec3(0.0, 0.0, -0.5), 1e-6));
+
+        let mut strafe = camera();
+        walk(&mut strafe, glam::vec3(0.0, -1.0, 1.0), 3.0, 0.5);
+        assert!(strafe.eye().abs_diff_eq(glam::vec3(-1.5, 1.5, 0.0), 1e-6));
+    }
+
+    #[test]
+    fn walking_distance_doesnt_depend_on_frame_rate() {
+        let mut fast = camera();
+        for _ in 0..60 {
+            walk(&mut fast, glam::Vec3::X, 1.5, 1.0 / 60.0);
+        }
+        let mut slow = camera();
+        for _ in 0..15 {
+            walk(&mut slow, glam::Vec3::X, 1.5, 1.0 / 15.0);
+        }
+        assert!(fast.eye().abs_diff_eq(slow.eye(), 1e-4));
+        assert!((fast.eye().length() - 1.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sensitivity_stops_at_the_bounds() {
+        assert_eq!(scaled_sensitivity(0.5, 2.0), 1.0);
+        assert_eq!(scaled_sensitivity(1.8, SENSITIVITY_STEP), MAX_SENSITIVITY);
+        assert_eq!(
+            scaled_sensitivity(0.011, 1.0 / SENSITIVITY_STEP),
+            MIN_SENSITIVITY
+        );
+        // Stepping back off a bound works straight away
+        let lowest = scaled_sensitivity(MIN_SENSITIVITY, 1.0 / SENSITIVITY_STEP);
+        assert!(scaled_sensitivity(lowest, SENSITIVITY_STEP) > MIN_SENSITIVITY);
+    }
+
+    #[test]
+    fn camera_pose_survives_a_round_trip() {
+        let config = GameConfig {
+            camera_position: Some([1.0, 2.0, 3.0]),
+            camera_target: Some([-1.0, 0.5, 0.0]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let loaded: GameConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, config);
+
+        let camera = camera_from_config(&loaded, 16, 9);
+        let target = glam::vec3(-1.0, 0.5, 0.0);
+        assert!(camera.eye().abs_diff_eq(glam::vec3(1.0, 2.0, 3.0), 1e-6));
+        assert!(camera.target().abs_diff_eq(target, 1e-5));
+    }
+
+    #[test]
+    fn old_configs_get_the_default_pose() {
+        let loaded: GameConfig = serde_json::from_str(r#"{ "width": 800 }"#).unwrap();
+        assert_eq!(loaded.camera_position, None);
+        assert_eq!(loaded.camera_target, None);
+
+        let camera = camera_from_config(&loaded, 16, 9);
+        assert!(camera.eye().abs_diff_eq(glam::vec3(0.0, 1.0, 4.0), 1e-6));
+        assert!(camera.target().abs_diff_eq(glam::vec3(0.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn present_modes_map_by_name() {
+        use wgpu::PresentMode as Wgpu;
+        for (name, mode, wgpu_mode) in [
+            ("\"Fifo\"", PresentMode::Fifo, Wgpu::Fifo),
+            ("\"Mailbox\"", PresentMode::Mailbox, Wgpu::Mailbox),
+            ("\"Immediate\"", PresentMode::Immediate, Wgpu::Immediate),
+        ] {
+            assert_eq!(serde_json::from_str::<PresentMode>(name).unwrap(), mode);
+            assert_eq!(serde_json::to_string(&mode).unwrap(), name);
+            assert_eq!(mode.present_mode(), wgpu_mode);
+        }
+        assert!(serde_json::from_str::<PresentMode>("\"Vsync\"").is_err());
+    }
+
+    #[test]
+    fn unsupported_present_modes_fall_back_to_fifo() {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+        assert_eq!(
+            supported_present_mode(&supported, PresentMode::Mailbox),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            supported_present_mode(&supported, PresentMode::Immediate),
+            PresentMode::Fifo
+        );
+    }
+
+    fn surf_config(width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        }
+    }
+
+    #[test]
+    fn zero_sized_resizes_keep_the_last_size() {
+        let mut config = surf_config(800, 600);
+        let mut camera = camera();
+        camera.resize(800, 600);
+
+        assert!(!resize_view(&mut config, &mut camera, 0, 600));
+        assert!(!resize_view(&mut config, &mut camera, 800, 0));
+        assert_eq!((config.width, config.height), (800, 600));
+        assert_eq!(camera.aspect(), 800.0 / 600.0);
+
+        assert!(resize_view(&mut config, &mut camera, 400, 100));
+        assert_eq!((config.width, config.height), (400, 100));
+        assert_eq!(camera.aspect(), 4.0);
+    }
+
+    /// A renderer drawing into `Headless::FORMAT`, or `None` without a GPU.
+    fn renderer(config: &GameConfig) -> Option<(wgpu::Device, wgpu::Queue, Renderer)> {
+        test_device()?;
+        let instance = wgpu::Instance::new(Default::default());
+        let (adapter, device, queue) = request_device(&instance, None).block_on().ok()?;
+        let renderer =
+            Renderer::new(&adapter, &device, &queue, config, Headless::FORMAT).block_on();
+        Some((device, queue, renderer))
+    }
+
+    #[test]
+    fn viewports_resize_on_their_own() {
+        let config = GameConfig {
+            sample_count: 1,
+            ..Default::default()
+        };
+        let Some((device, _, renderer)) = renderer(&config) else {
+            return;
+        };
+        let mut views = [(800, 600), (320, 240)].map(|(width, height)| {
+            let mut camera = camera();
+            camera.resize(width, height);
+            let target = renderer.create_target(&device, width, height, &camera);
+            (surf_config(width, height), camera, target)
+        });
+
+        let (config, camera, target) = &mut views[1];
+        assert!(resize_view(config, camera, 640, 160));
+        renderer.resize_target(&device, target, 640, 160);
+        // Minimizing keeps the last size
+        assert!(!resize_view(config, camera, 0, 0));
+
+        let sizes = views
+            .iter()
+            .map(|(config, camera, target)| {
+                let depth = target.depth_texture.size();
+                (
+                    (config.width, config.height),
+                    (depth.width, depth.height),
+                    camera.aspect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [
+                ((800, 600), (800, 600), 800.0 / 600.0),
+                ((640, 160), (640, 160), 4.0),
+            ]
+        );
     }
 
-    found
+    #[test]
+    fn nothing_draws_before_the_model_loads() {
+        let config = GameConfig {
+            sample_count: 1,
+            show_grid: false,
+            clear_color: [0.0, 0.0, 1.0, 1.0],
+            ..Default::default()
+        };
+        let Some((device, queue, mut renderer)) = renderer(&config) else {
+            return;
+        };
+        let mut camera = camera();
+        camera.resize(16, 16);
+        let mut target = renderer.create_target(&device, 16, 16, &camera);
+        let output = Texture::color_target(&device, 16, 16, Headless::FORMAT);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        renderer.render(
+            &queue,
+            &mut encoder,
+            &mut target,
+            output.view(),
+            &camera,
+            None,
+        );
+        queue.submit([encoder.finish()]);
+
+        // Just the background, tonemapped
+        let image = output.read_to_image(&device, &queue).unwrap();
+        let first = *image.get_pixel(0, 0);
+        assert!(image.pixels().all(|p| *p == first));
+        assert!(first[2] > first[0] && first[2] > first[1]);
+    }
+
+    #[test]
+    fn missing_models_fall_back_to_the_default() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let material_binder = MaterialBinder::new(&device, &queue);
+        let load = |path, fallback| {
+            load_model_or_default(&device, &queue, &material_binder, path, fallback).block_on()
+        };
+
+        let (_, path) = load("res/missing.glb", true).unwrap();
+        assert_eq!(path, DEFAULT_MODEL_PATH);
+        // Errors name the path that was asked for
+        let Err(error) = load("res/missing.glb", false) else {
+            panic!("expected the missing model to fail");
+        };
+        assert!(format!("{:#}", error).contains("res/missing.glb"));
+
+        let (_, path) = load("res/spherical-cube.glb", true).unwrap();
+        assert_eq!(path, "res/spherical-cube.glb");
+    }
+
+    /// Polls `loader` until its latest load is done.
+    fn wait_for(loader: &mut ModelLoader) -> anyhow::Result<(Model, String)> {
+        loop {
+            if let Some(result) = loader.poll() {
+                return result;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn a_second_load_replaces_the_first() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let device = Arc::new(device);
+        let queue = Arc::new(queue);
+        let material_binder = Arc::new(MaterialBinder::new(&device, &queue));
+        let mut loader = ModelLoader::default();
+        let start = |loader: &mut ModelLoader, path: &str| {
+            loader.start(
+                device.clone(),
+                queue.clone(),
+                material_binder.clone(),
+                path.to_string(),
+                false,
+            )
+        };
+
+        start(&mut loader, "res/walking.glb");
+        assert!(loader.is_loading());
+        let (first, path) = wait_for(&mut loader).unwrap();
+        assert_eq!(path, "res/walking.glb");
+        assert!(!loader.is_loading());
+
+        // The second starts while the first is still pending, like
+        // `load_model` does
+        start(&mut loader, "res/glowy-rock.glb");
+        start(&mut loader, "res/spherical-cube.glb");
+        let (second, path) = wait_for(&mut loader).unwrap();
+        assert_eq!(path, "res/spherical-cube.glb");
+        assert_ne!(first.aabb(), second.aabb());
+
+        // The replaced load never shows up, even once it's done
+        loader.join();
+        assert!(loader.poll().is_none());
+        assert!(!loader.is_loading());
+    }
+
+    #[test]
+    fn icons_decode_to_rgba() {
+        let pixels =
+            image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        pixels
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let (rgba, width, height) = icon_rgba(&png).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(rgba, pixels.into_raw());
+        assert!(decode_icon(&png).is_ok());
+        assert!(decode_icon(DEFAULT_ICON).is_ok());
+    }
+
+    #[test]
+    fn bad_icons_are_errors() {
+        assert!(decode_icon(b"not an image").is_err());
+        assert!(decode_icon(&[]).is_err());
+        // Cut off partway through the pixels
+        assert!(decode_icon(&DEFAULT_ICON[..DEFAULT_ICON.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn surface_format_prefers_srgb() {
+        use wgpu::TextureFormat::*;
+        assert_eq!(
+            choose_surface_format(&[Bgra8Unorm, Rgba8UnormSrgb]),
+            Some(Rgba8UnormSrgb)
+        );
+        assert_eq!(
+            choose_surface_format(&[Rgba8UnormSrgb, Bgra8UnormSrgb]),
+            Some(Bgra8UnormSrgb)
+        );
+        assert_eq!(
+            choose_surface_format(&[Rgba16Float, Bgra8Unorm]),
+            Some(Rgba16Float)
+        );
+        assert_eq!(choose_surface_format(&[]), None);
+    }
+
+    #[test]
+    fn features_are_narrowed_to_the_adapter() {
+        let wanted = OPTIONAL_FEATURES;
+        assert_eq!(
+            negotiate_features(wanted, wgpu::Features::POLYGON_MODE_LINE),
+            wgpu::Features::POLYGON_MODE_LINE
+        );
+        assert_eq!(negotiate_features(wanted, wgpu::Features::all()), wanted);
+        assert!(negotiate_features(wanted, wgpu::Features::empty()).is_empty());
+    }
+
+    #[test]
+    fn limits_take_the_adapter_buffer_size() {
+        let adapter = wgpu::Limits {
+            max_buffer_size: 1 << 40,
+            max_texture_dimension_2d: 16384,
+            ..Default::default()
+        };
+        let limits = negotiate_limits(&adapter);
+        assert_eq!(limits.max_buffer_size, 1 << 40);
+        assert_eq!(limits.max_texture_dimension_2d, 16384);
+        assert!(limits.check_limits(&adapter));
+    }
+
+    #[test]
+    fn only_running_out_of_memory_exits() {
+        use wgpu::SurfaceError::*;
+        let action = surface_error_action;
+        assert_eq!(action(&Outdated), SurfaceErrorAction::Reconfigure);
+        assert_eq!(action(&Lost), SurfaceErrorAction::Reconfigure);
+        assert_eq!(action(&Timeout), SurfaceErrorAction::Skip);
+        assert_eq!(action(&OutOfMemory), SurfaceErrorAction::Exit);
+    }
+
+    #[test]
+    fn monitor_index_beats_name() {
+        let monitors = ["left", "right", "right"];
+        let name_of = |m: &&str| Some(m.to_string());
+        let find = |index, name: Option<&str>| {
+            find_monitor(&monitors, index, &name.map(String::from), name_of)
+        };
+        assert_eq!(find(Some(2), Some("left")), Some(2));
+        assert_eq!(find(None, Some("right")), Some(1));
+        // A stale index falls through to the name, then to the primary
+        assert_eq!(find(Some(5), Some("left")), Some(0));
+        assert_eq!(find(Some(5), Some("middle")), None);
+        assert_eq!(find(None, None), None);
+    }
+
+    #[test]
+    fn video_mode_takes_the_nearest_refresh_rate() {
+        let config = VideoModeConfig {
+            width: 1920,
+            height: 1080,
+            refresh_rate_millihertz: 60000,
+        };
+        let modes = [
+            (1920, 1080, 144000),
+            (1280, 720, 60000),
+            (1920, 1080, 59940),
+            (1920, 1080, 50000),
+        ];
+        let find = |modes: &[(u32, u32, u32)]| {
+            config.find_closest(modes, |&(w, h, r)| (PhysicalSize::new(w, h), r))
+        };
+        assert_eq!(find(&modes), Some(2));
+        // The resolution has to match no matter how close the refresh rate is
+        assert_eq!(find(&modes[1..2]), None);
+    }
+
+    #[test]
+    fn cursor_grab_falls_back_to_confined() {
+        let tried = std::cell::RefCell::new(Vec::new());
+        let set = |mode| {
+            tried.borrow_mut().push(mode);
+            match mode {
+                CursorGrabMode::Locked => Err(()),
+                _ => Ok(()),
+            }
+        };
+        assert_eq!(set_cursor_grab(true, set), Ok(()));
+        assert_eq!(
+            tried.take(),
+            [CursorGrabMode::Locked, CursorGrabMode::Confined]
+        );
+        assert_eq!(set_cursor_grab(false, set), Ok(()));
+        assert_eq!(tried.take(), [CursorGrabMode::None]);
+        // Only the last error gets reported
+        assert_eq!(set_cursor_grab(true, |_| Err(())), Err(()));
+    }
 }
@@ -0,0 +1,131 @@
+use super::{
+    camera_from_config, load_model_or_default,
+    renderer::{RenderTarget, Renderer},
+    GameConfig,
+};
+use crate::resources::{camera::Camera, model::Model, texture::Texture};
+
+/// Renders the same scene as `Game`, but into an offscreen texture instead
+/// of a window. Useful for tests and thumbnails.
+pub struct Headless {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    renderer: Renderer,
+    render_target: RenderTarget,
+    camera: Camera,
+    model: Model,
+    target: Texture,
+}
+
+impl Headless {
+    /// The same as the window's format on most platforms, and what
+    /// `image` expects.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub async fn new(config: GameConfig, width: u32, height: u32) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(Default::default());
+        let (adapter, device, queue) = super::request_device(&instance, None).await?;
+
+        let mut camera = camera_from_config(&config, width, height);
+        let renderer = Renderer::new(&adapter, &device, &queue, &config, Self::FORMAT).await;
+        let target = Texture::color_target(&device, width, height, Self::FORMAT);
+
+        let (model, _) = load_model_or_default(
+            &device,
+            &queue,
+            &renderer.material_binder,
+            &config.model_path,
+            true,
+        )
+        .await?;
+        if config.camera_position.is_none() {
+            camera.fit_to(model.aabb());
+        }
+        let render_target = renderer.create_target(&device, width, height, &camera);
+
+        Ok(Self {
+            device,
+            queue,
+            renderer,
+            render_target,
+            camera,
+            model,
+            target,
+        })
+    }
+
+    /// A zero width or height is ignored, the same as `Game` does for a
+    /// minimized window.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.camera.resize(width, height);
+        self.renderer
+            .resize_target(&self.device, &mut self.render_target, width, height);
+        self.target = Texture::color_target(&self.device, width, height, Self::FORMAT);
+    }
+
+    /// Draws a frame and reads it back. This waits on the gpu.
+    pub fn render_to_image(&mut self) -> anyhow::Result<image::RgbaImage> {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        self.renderer.render(
+            &self.queue,
+            &mut encoder,
+            &mut self.render_target,
+            self.target.view(),
+            &self.camera,
+            Some(&self.model),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        self.target.read_to_image(&self.device, &self.queue)
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn renders_the_model() {
+        if crate::game::test_device().is_none() {
+            return;
+        }
+        // Software adapters don't always resolve multisampled targets
+        let config = GameConfig {
+            sample_count: 1,
+            ..Default::default()
+        };
+        let mut headless = Headless::new(config, 64, 48).block_on().unwrap();
+        let image = headless.render_to_image().unwrap();
+        assert_eq!(image.dimensions(), (64, 48));
+        // Anything other than a clear color everywhere means something drew
+        let first = image.get_pixel(0, 0);
+        assert!(image.pixels().any(|p| p != first));
+
+        headless.resize(0, 10);
+        headless.resize(32, 0);
+        headless.resize(32, 24);
+        let image = headless.render_to_image().unwrap();
+        assert_eq!(image.dimensions(), (32, 24));
+    }
+}
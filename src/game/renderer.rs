@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use super::{choose_sample_count, GameConfig, SceneFormat};
+use crate::{
+    pipelines::{
+        debug::DebugPipeline,
+        depth::DepthConfig,
+        fur::{Fur, LightData},
+        grid::GridPipeline,
+        post_process::PostProcess,
+        render_format::RenderFormat,
+        shadow::ShadowPass,
+        skybox::Skybox,
+    },
+    resources::{
+        camera::{Camera, CameraBinder, CameraBinding},
+        material::MaterialBinder,
+        model::Model,
+        texture::Texture,
+    },
+};
+
+/// Second target when writing normals out for later passes.
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Everything needed to draw the scene, without caring whether the output
+/// ends up in a window or an offscreen texture. Pipelines are shared, each
+/// output gets its own `RenderTarget`.
+pub(super) struct Renderer {
+    /// What the scene gets drawn into before post processing.
+    pub(super) format: RenderFormat,
+    pub(super) scene_format: SceneFormat,
+    pub(super) post_process: PostProcess,
+    pub(super) camera_binder: CameraBinder,
+    /// Shared with the thread that loads the first model.
+    pub(super) material_binder: Arc<MaterialBinder>,
+    pub(super) fur: Fur,
+    pub(super) shadow: ShadowPass,
+    pub(super) debug: DebugPipeline,
+    pub(super) grid: GridPipeline,
+    pub(super) skybox: Skybox,
+    pub(super) translucent_fur: bool,
+    pub(super) wireframe: bool,
+    pub(super) show_grid: bool,
+    pub(super) clear_color: [f32; 4],
+}
+
+/// The size dependent textures for one output, and the camera it's seen
+/// through.
+pub(super) struct RenderTarget {
+    pub(super) depth_texture: Texture,
+    /// One per `RenderFormat` target, the first is the scene color.
+    attachments: Vec<Attachment>,
+    post_process_input: wgpu::BindGroup,
+    camera_binding: CameraBinding,
+}
+
+/// A color target, and where it gets drawn with MSAA before being
+/// resolved into `texture`.
+struct Attachment {
+    texture: Texture,
+    msaa_texture: Option<Texture>,
+}
+
+impl Attachment {
+    /// The views to draw to and resolve into.
+    fn views(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.msaa_texture {
+            Some(msaa_texture) => (msaa_texture.view(), Some(self.texture.view())),
+            None => (self.texture.view(), None),
+        }
+    }
+}
+
+impl Renderer {
+    /// `output_format` is the format of the views passed to `render`.
+    pub(super) async fn new(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &GameConfig,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let scene_format = config.scene_format.texture_format();
+        let sample_count = choose_sample_count(
+            adapter,
+            device,
+            &[scene_format, NORMAL_FORMAT, Texture::DEPTH_FORMAT],
+            config.sample_count,
+        );
+        let depth = DepthConfig::new(Texture::DEPTH_FORMAT, config.reverse_z);
+        let mut format = RenderFormat::new(scene_format, depth, sample_count);
+        if config.gbuffer_normals {
+            format = format.with_target(NORMAL_FORMAT);
+        }
+
+        let post_process = PostProcess::new(device, output_format, config.exposure, config.tonemap);
+
+        let camera_binder = CameraBinder::new(device);
+
+        let material_binder = Arc::new(MaterialBinder::new(device, queue));
+
+        let shadow_map_size = config
+            .shadow_map_size
+            .clamp(1, device.limits().max_texture_dimension_2d);
+        let shadow = ShadowPass::new(device, shadow_map_size, &camera_binder, &material_binder);
+
+        let mut fur = Fur::new(
+            device,
+            queue,
+            config.fur_layers,
+            &if config.translucent_fur {
+                format.clone().alpha_blend()
+            } else {
+                format.clone()
+            },
+            &camera_binder,
+            &material_binder,
+            &shadow,
+        );
+        // Each of these rebuilds the pipelines, so skip them if they'd do
+        // nothing
+        if config.cull_mode != fur.cull_mode() {
+            fur.set_cull_mode(device, config.cull_mode);
+        }
+        if config.winding != fur.winding() {
+            fur.set_winding(device, config.winding);
+        }
+        fur.set_light_data(
+            queue,
+            LightData::new(
+                config.light_direction.into(),
+                config.light_color.into(),
+                config.ambient_color.into(),
+            ),
+        );
+
+        let debug = DebugPipeline::new(device, &format, !config.debug_on_top, &camera_binder);
+
+        let grid = GridPipeline::new(
+            device,
+            queue,
+            &format,
+            &camera_binder,
+            config.grid_spacing,
+            config.grid_extent,
+        );
+
+        let mut skybox = Skybox::new(device, &format, &camera_binder);
+        // The clear color still works as a background
+        if let Some(paths) = &config.skybox {
+            match Texture::cubemap_from_files(device, queue, paths).await {
+                Ok(cubemap) => skybox.set_cubemap(device, Some(&cubemap)),
+                Err(e) => eprintln!("Unable to load skybox: {:#}", e),
+            }
+        }
+
+        Self {
+            format,
+            scene_format: config.scene_format,
+            post_process,
+            camera_binder,
+            material_binder,
+            fur,
+            shadow,
+            debug,
+            grid,
+            skybox,
+            translucent_fur: config.translucent_fur,
+            wireframe: config.wireframe,
+            show_grid: config.show_grid,
+            clear_color: config.clear_color,
+        }
+    }
+
+    pub(super) fn create_target(
+        &self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        camera: &Camera,
+    ) -> RenderTarget {
+        let (depth_texture, attachments) = self.create_textures(device, width, height);
+        RenderTarget {
+            depth_texture,
+            post_process_input: self
+                .post_process
+                .bind_input(device, &attachments[0].texture),
+            attachments,
+            camera_binding: self.camera_binder.bind(device, camera),
+        }
+    }
+
+    pub(super) fn resize_target(
+        &self,
+        device: &wgpu::Device,
+        target: &mut RenderTarget,
+        width: u32,
+        height: u32,
+    ) {
+        let (depth_texture, attachments) = self.create_textures(device, width, height);
+        target.post_process_input = self
+            .post_process
+            .bind_input(device, &attachments[0].texture);
+        target.depth_texture = depth_texture;
+        target.attachments = attachments;
+    }
+
+    fn create_textures(
+        &self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (Texture, Vec<Attachment>) {
+        let sample_count = self.format.sample_count();
+        let depth_texture = Texture::depth_texture(device, width, height, sample_count);
+        // The scene is drawn offscreen first, then copied to the output
+        let attachments = self
+            .format
+            .targets()
+            .iter()
+            .flatten()
+            .map(|target| Attachment {
+                texture: Texture::color_target(device, width, height, target.format),
+                msaa_texture: (sample_count > 1).then(|| {
+                    Texture::msaa_color_target(device, width, height, target.format, sample_count)
+                }),
+            })
+            .collect();
+        (depth_texture, attachments)
+    }
+
+    /// Draws the scene and post processes it into `view`. Whatever's in the
+    /// debug pipeline gets drawn too, even if there's no model yet.
+    pub(super) fn render(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &mut RenderTarget,
+        view: &wgpu::TextureView,
+        camera: &Camera,
+        model: Option<&Model>,
+    ) {
+        target.camera_binding.update(queue, camera);
+
+        // Cheap enough to redo every frame, and keeps up with the light and
+        // the instances moving
+        if let Some(model) = model {
+            let bounds = self.fur.bounds(model);
+            self.shadow
+                .update(queue, self.fur.light().direction(), bounds);
+        }
+        self.fur.draw_shadow(encoder, &self.shadow, model);
+
+        let clear_color = wgpu::Color {
+            r: self.clear_color[0] as f64,
+            g: self.clear_color[1] as f64,
+            b: self.clear_color[2] as f64,
+            a: self.clear_color[3] as f64,
+        };
+        let color_attachments = target
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(i, attachment)| {
+                let (view, resolve_target) = attachment.views();
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        store: wgpu::StoreOp::Store,
+                        // Only the scene color gets the background
+                        load: wgpu::LoadOp::Clear(if i == 0 {
+                            clear_color
+                        } else {
+                            wgpu::Color::TRANSPARENT
+                        }),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth_texture.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        store: wgpu::StoreOp::Store,
+                        load: wgpu::LoadOp::Clear(self.format.depth().clear_value()),
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let camera_binding = &target.camera_binding;
+            self.skybox.draw(&mut pass, camera_binding);
+
+            if let Some(model) = model {
+                let frustum = camera.frustum();
+                if self.wireframe {
+                    self.fur
+                        .draw_wireframe(&mut pass, model, camera_binding, &frustum);
+                } else {
+                    self.fur.draw(&mut pass, model, camera_binding, &frustum);
+                }
+            }
+
+            if self.show_grid {
+                self.grid.draw(&mut pass, camera_binding);
+            }
+
+            self.debug.draw_triangles(&mut pass, camera_binding);
+            self.debug.draw_lines(&mut pass, camera_binding);
+        }
+
+        self.post_process_into(encoder, target, view);
+    }
+
+    /// Only redoes post processing, using whatever the last `render` left
+    /// in the scene texture.
+    pub(super) fn post_process_into(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+        view: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    store: wgpu::StoreOp::Store,
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        self.post_process
+            .draw(&mut pass, &target.post_process_input);
+    }
+}
@@ -0,0 +1,130 @@
+use winit::window::{Window, WindowId};
+
+use super::{
+    current_texture,
+    renderer::{RenderTarget, Renderer},
+    resize_view,
+};
+use crate::resources::{camera::Camera, model::Model};
+
+/// An extra window showing the scene through its own camera. Everything
+/// else, including the pipelines and the model, is shared with the main
+/// window.
+pub(super) struct Viewport {
+    // Declared before the window so it's dropped first
+    surface: wgpu::Surface,
+    surf_config: wgpu::SurfaceConfiguration,
+    render_target: RenderTarget,
+    camera: Camera,
+    minimized: bool,
+    window: Window,
+}
+
+impl Viewport {
+    /// `surf_config` is the main window's, the new surface uses the same
+    /// format so the post process pipeline can be shared.
+    pub(super) fn new(
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        renderer: &Renderer,
+        surf_config: &wgpu::SurfaceConfiguration,
+        window: Window,
+        mut camera: Camera,
+    ) -> anyhow::Result<Self> {
+        // Safety: surface and window are owned by the viewport
+        let surface = unsafe { instance.create_surface(&window)? };
+        let caps = surface.get_capabilities(adapter);
+        if !caps.formats.contains(&surf_config.format) {
+            anyhow::bail!("New window doesn't support {:?}", surf_config.format);
+        }
+
+        let size = window.inner_size();
+        let surf_config = wgpu::SurfaceConfiguration {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: if caps.present_modes.contains(&surf_config.present_mode) {
+                surf_config.present_mode
+            } else {
+                wgpu::PresentMode::Fifo
+            },
+            alpha_mode: caps.alpha_modes[0],
+            ..surf_config.clone()
+        };
+        surface.configure(device, &surf_config);
+
+        camera.resize(surf_config.width, surf_config.height);
+        let render_target =
+            renderer.create_target(device, surf_config.width, surf_config.height, &camera);
+
+        Ok(Self {
+            surface,
+            surf_config,
+            render_target,
+            camera,
+            minimized: false,
+            window,
+        })
+    }
+
+    pub(super) fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub(super) fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub(super) fn show(&self) {
+        self.window.set_visible(true);
+    }
+
+    /// Same as `Game::resize`, a 0x0 window keeps its last size and stops
+    /// drawing.
+    pub(super) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        renderer: &Renderer,
+        width: u32,
+        height: u32,
+    ) {
+        self.minimized = width == 0 || height == 0;
+        if !resize_view(&mut self.surf_config, &mut self.camera, width, height) {
+            return;
+        }
+
+        self.surface.configure(device, &self.surf_config);
+        renderer.resize_target(device, &mut self.render_target, width, height);
+    }
+
+    /// Only fails if the gpu is out of memory.
+    pub(super) fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut Renderer,
+        model: Option<&Model>,
+    ) -> Result<(), wgpu::SurfaceError> {
+        if self.minimized {
+            return Ok(());
+        }
+        let Some(target) = current_texture(&self.surface, device, &self.surf_config)? else {
+            return Ok(());
+        };
+
+        let view = target.texture.create_view(&Default::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        renderer.render(
+            queue,
+            &mut encoder,
+            &mut self.render_target,
+            &view,
+            &self.camera,
+            model,
+        );
+        queue.submit([encoder.finish()]);
+        target.present();
+
+        Ok(())
+    }
+}
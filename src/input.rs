@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+
+use winit::keyboard::KeyCode;
+
+/// Keys that are currently held down, and mouse motion that hasn't been
+/// applied yet.
+#[derive(Debug, Default)]
+pub struct InputState {
+    pressed: HashSet<KeyCode>,
+    mouse_delta: MouseDelta,
+}
+
+impl InputState {
+    pub fn set_pressed(&mut self, key: KeyCode, pressed: bool) {
+        if pressed {
+            self.pressed.insert(key);
+        } else {
+            self.pressed.remove(&key);
+        }
+    }
+
+    pub fn is_pressed(&self, key: KeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// See `MouseDelta::add`.
+    pub fn add_mouse_motion(&mut self, axis: u32, value: f32) {
+        self.mouse_delta.add(axis, value);
+    }
+
+    /// See `MouseDelta::take`.
+    pub fn take_mouse_delta(&mut self) -> (f32, f32) {
+        self.mouse_delta.take()
+    }
+
+    /// Releases every key and drops pending mouse motion. Key up events get
+    /// lost when the window loses focus, so this keeps keys from staying
+    /// held forever.
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+        self.mouse_delta = MouseDelta::default();
+    }
+}
+
+/// Mouse motion collected between frames. Platforms send motion in
+/// differently sized chunks, so it gets added up here and applied once
+/// per frame instead of per event.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MouseDelta {
+    x: f32,
+    y: f32,
+}
+
+impl MouseDelta {
+    /// `axis` 0 is horizontal and 1 vertical, like `DeviceEvent::Motion`.
+    /// Other axes are ignored.
+    pub fn add(&mut self, axis: u32, value: f32) {
+        match axis {
+            0 => self.x += value,
+            1 => self.y += value,
+            _ => (),
+        }
+    }
+
+    /// The motion since the last call, in the mouse's own units. Those
+    /// don't depend on the window size or frame rate, so they shouldn't be
+    /// scaled by either.
+    pub fn take(&mut self) -> (f32, f32) {
+        let Self { x, y } = std::mem::take(self);
+        (x, y)
+    }
+}
+
+/// Something that resolves to a value between -1 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Axis {
+    /// Positive key, then negative key. Holding both cancels out.
+    Keys(KeyCode, KeyCode),
+}
+
+impl Axis {
+    pub fn value(&self, input: &InputState) -> f32 {
+        match *self {
+            Self::Keys(pos, neg) => {
+                let pos = if input.is_pressed(pos) { 1.0 } else { 0.0 };
+                let neg = if input.is_pressed(neg) { 1.0 } else { 0.0 };
+                pos - neg
+            }
+        }
+    }
+}
+
+/// Continuous actions that can be bound to an `Axis`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Forward,
+    Right,
+    Up,
+    Roll,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct InputBindings {
+    forward: Axis,
+    right: Axis,
+    up: Axis,
+    /// Positive rolls to the right.
+    roll: Axis,
+    sprint: KeyCode,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            forward: Axis::Keys(KeyCode::KeyW, KeyCode::KeyS),
+            right: Axis::Keys(KeyCode::KeyD, KeyCode::KeyA),
+            up: Axis::Keys(KeyCode::Space, KeyCode::ControlLeft),
+            roll: Axis::Keys(KeyCode::KeyE, KeyCode::KeyQ),
+            sprint: KeyCode::ShiftLeft,
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn value(&self, action: Action, input: &InputState) -> f32 {
+        let axis = match action {
+            Action::Forward => &self.forward,
+            Action::Right => &self.right,
+            Action::Up => &self.up,
+            Action::Roll => &self.roll,
+        };
+        axis.value(input)
+    }
+
+    pub fn is_sprinting(&self, input: &InputState) -> bool {
+        input.is_pressed(self.sprint)
+    }
+}
+
+/// Reads 0 inside the deadzone and rescales the rest so the stick still
+/// reaches 1 at the edge.
+pub fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= deadzone || deadzone >= 1.0 {
+        return 0.0;
+    }
+    value.signum() * ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0)
+}
+
+/// Stick and trigger values of the first connected gamepad, with the
+/// deadzone applied.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GamepadState {
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+    pub look_right: f32,
+    pub look_up: f32,
+}
+
+#[cfg(feature = "gamepad")]
+pub struct Gamepads {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    /// `None` if the platform has no gamepad support.
+    pub fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs }),
+            Err(e) => {
+                eprintln!("Gamepads unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Needs to be called every frame to keep the gamepad state current.
+    pub fn poll(&mut self, deadzone: f32) -> GamepadState {
+        while self.gilrs.next_event().is_some() {}
+
+        let Some((_, gamepad)) = self.gilrs.gamepads().next() else {
+            return GamepadState::default();
+        };
+
+        let stick = |axis| apply_deadzone(gamepad.value(axis), deadzone);
+        let trigger = |button| {
+            gamepad
+                .button_data(button)
+                .map(|b| apply_deadzone(b.value(), deadzone))
+                .unwrap_or(0.0)
+        };
+
+        GamepadState {
+            forward: stick(gilrs::Axis::LeftStickY),
+            right: stick(gilrs::Axis::LeftStickX),
+            up: trigger(gilrs::Button::RightTrigger2) - trigger(gilrs::Button::LeftTrigger2),
+            look_right: stick(gilrs::Axis::RightStickX),
+            look_up: stick(gilrs::Axis::RightStickY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_reads_zero() {
+        assert_eq!(apply_deadzone(0.1, 0.15), 0.0);
+        assert_eq!(apply_deadzone(-0.15, 0.15), 0.0);
+    }
+
+    #[test]
+    fn deadzone_rescales_the_rest() {
+        assert!((apply_deadzone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert!((apply_deadzone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+        assert_eq!(apply_deadzone(1.0, 0.2), 1.0);
+        assert_eq!(apply_deadzone(-1.0, 0.2), -1.0);
+    }
+
+    #[test]
+    fn full_deadzone_never_moves() {
+        assert_eq!(apply_deadzone(1.0, 1.0), 0.0);
+        assert_eq!(apply_deadzone(1.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn mouse_delta_adds_up_until_taken() {
+        let mut delta = MouseDelta::default();
+        delta.add(0, 1.5);
+        delta.add(1, -2.0);
+        delta.add(0, 0.5);
+        delta.add(2, 9.0);
+        assert_eq!(delta.take(), (2.0, -2.0));
+        assert_eq!(delta.take(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn key_axes_resolve_from_held_keys() {
+        let bindings = InputBindings::default();
+        let mut input = InputState::default();
+        assert_eq!(bindings.value(Action::Forward, &input), 0.0);
+
+        input.set_pressed(KeyCode::KeyW, true);
+        assert_eq!(bindings.value(Action::Forward, &input), 1.0);
+        // Holding both cancels out
+        input.set_pressed(KeyCode::KeyS, true);
+        assert_eq!(bindings.value(Action::Forward, &input), 0.0);
+        input.set_pressed(KeyCode::KeyW, false);
+        assert_eq!(bindings.value(Action::Forward, &input), -1.0);
+        // Other actions don't see it
+        assert_eq!(bindings.value(Action::Right, &input), 0.0);
+    }
+
+    #[test]
+    fn sprint_follows_its_key() {
+        let bindings = InputBindings::default();
+        let mut input = InputState::default();
+        assert!(!bindings.is_sprinting(&input));
+        input.set_pressed(KeyCode::ShiftLeft, true);
+        assert!(bindings.is_sprinting(&input));
+        input.set_pressed(KeyCode::ShiftLeft, false);
+        assert!(!bindings.is_sprinting(&input));
+    }
+
+    #[test]
+    fn losing_focus_drops_keys_and_motion() {
+        let mut input = InputState::default();
+        input.set_pressed(KeyCode::KeyW, true);
+        input.set_pressed(KeyCode::KeyD, true);
+        input.add_mouse_motion(0, 3.0);
+        input.add_mouse_motion(1, -1.0);
+
+        input.clear();
+        assert!(!input.is_pressed(KeyCode::KeyW));
+        assert!(!input.is_pressed(KeyCode::KeyD));
+        assert_eq!(input.take_mouse_delta(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn clearing_zeroes_every_action() {
+        let bindings = InputBindings::default();
+        let mut input = InputState::default();
+        // Only the positive key of each axis, so none of them cancel out
+        for key in [
+            KeyCode::KeyW,
+            KeyCode::KeyD,
+            KeyCode::Space,
+            KeyCode::KeyE,
+            KeyCode::ShiftLeft,
+        ] {
+            input.set_pressed(key, true);
+        }
+        let actions = [Action::Forward, Action::Right, Action::Up, Action::Roll];
+        for action in actions {
+            assert_eq!(bindings.value(action, &input), 1.0);
+        }
+
+        // What Escape and losing focus do through `Game::clear_inputs`
+        input.clear();
+        for action in actions {
+            assert_eq!(bindings.value(action, &input), 0.0);
+        }
+        assert!(!bindings.is_sprinting(&input));
+    }
+}
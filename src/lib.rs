@@ -0,0 +1,7 @@
+pub mod game;
+pub mod input;
+pub mod pipelines;
+pub mod resources;
+pub mod timing;
+#[cfg(feature = "ui")]
+pub mod ui;
\ No newline at end of file
@@ -1,37 +1,158 @@
-mod game;
-mod resources;
-mod pipelines;
-
-use game::Game;
+use heightmap_navmesh::{
+    game::{Game, GameConfig},
+    resources::{load_json, save_json},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+#[cfg(not(target_arch = "wasm32"))]
 use pollster::FutureExt;
-use resources::{load_json, save_json};
 use winit::{
-    event::{ElementState, Event, WindowEvent, KeyEvent, DeviceEvent},
-    event_loop::EventLoop,
-    window::WindowBuilder, keyboard::PhysicalKey,
+    event::{ElementState, Event, WindowEvent, KeyEvent, DeviceEvent, MouseScrollDelta},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::WindowBuilder, keyboard::{KeyCode, PhysicalKey},
 };
 
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> anyhow::Result<()> {
+    let config_path = config_path(std::env::args().skip(1))?;
+    let config_path = heightmap_navmesh::resources::resolve_path(&config_path);
+    check_writable(&config_path)?;
+
     let event_loop = EventLoop::new()?;
 
+    let mut config = load_config(&config_path).block_on();
+    if std::env::args().any(|arg| arg == "--ui") {
+        config.set_show_ui(true);
+    }
+
     let window = WindowBuilder::new()
         .with_visible(false)
+        .with_window_icon(config.window_icon().block_on())
         .build(&event_loop)?;
 
-    let config = load_json("config.json").block_on().unwrap_or_default();
-
     let mut game = Game::new(config, window).block_on()?;
 
-    event_loop.run(move |event, target| match event {
+    event_loop.run(move |event, target| handle_event(&mut game, &config_path, event, target))?;
+
+    Ok(())
+}
+
+/// Nothing can block in the browser, so everything up to the event loop
+/// runs as a task, and the event loop itself returns straight away and
+/// keeps going on animation frames.
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    wasm_bindgen_futures::spawn_local(async {
+        if let Err(e) = run_web().await {
+            web_sys::console::error_1(&format!("{:#}", e).into());
+        }
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn run_web() -> anyhow::Result<()> {
+    use winit::platform::web::{EventLoopExtWebSys, WindowBuilderExtWebSys};
+
+    let event_loop = EventLoop::new()?;
+    let config = load_config(DEFAULT_CONFIG_PATH).await;
+    let window = WindowBuilder::new()
+        .with_visible(false)
+        .with_append(true)
+        .build(&event_loop)?;
+
+    let mut game = Game::new(config, window).await?;
+
+    event_loop
+        .spawn(move |event, target| handle_event(&mut game, DEFAULT_CONFIG_PATH, event, target));
+
+    Ok(())
+}
+
+async fn load_config(path: &str) -> GameConfig {
+    match load_json(path).await {
+        Ok(config) => config,
+        Err(e) => {
+            // Not having a config yet is normal on the first run
+            if std::path::Path::new(path).exists() {
+                eprintln!("Unable to load config, using defaults: {:#}", e);
+            }
+            GameConfig::default()
+        }
+    }
+}
+
+fn handle_event(
+    game: &mut Game,
+    config_path: &str,
+    event: Event<()>,
+    target: &EventLoopWindowTarget<()>,
+) {
+    match event {
         Event::NewEvents(_) => game.show(),
         Event::AboutToWait => {
             if !game.is_running() {
                 target.exit();
+                return;
             }
+            if let Some(config) = game.poll_autosave() {
+                save_config(config_path, config, "Unable to autosave config");
+            }
+            let next_load_poll = game.poll_model_loader();
+            target.set_control_flow(match game.schedule_redraw().or(next_load_poll) {
+                Some(wake_up) => ControlFlow::WaitUntil(wake_up),
+                None => ControlFlow::Wait,
+            });
         }
-        Event::WindowEvent { event, .. } => match event {
-            WindowEvent::CloseRequested => target.exit(),
+        // Extra viewports take keyboard controls, but mouse look and the ui
+        // only work in the main window
+        Event::WindowEvent { window_id, event } if window_id != game.window_id() => match event {
+            WindowEvent::CloseRequested => game.close_window(window_id),
+            WindowEvent::Resized(size) => game.resize_window(window_id, size.width, size.height),
+            // Only asked for while the main window can't draw
+            WindowEvent::RedrawRequested => game.render(),
+            WindowEvent::Focused(focused) => game.handle_focused(focused),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => game.handle_keyboard(key, state == ElementState::Pressed),
+            _ => (),
+        },
+        Event::WindowEvent { event, .. } if game.handle_window_event(&event) => (),
+        Event::WindowEvent { window_id, event } => match event {
+            WindowEvent::CloseRequested => game.close_window(window_id),
             WindowEvent::Resized(size) => game.resize(size.width, size.height),
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                mut inner_size_writer,
+            } => {
+                let size = game.rescale(scale_factor);
+                // Keep the window in sync with the surface we just configured
+                let _ = inner_size_writer.request_inner_size(size);
+            }
+            WindowEvent::CursorMoved { position, .. } => game.handle_cursor_moved(position),
+            WindowEvent::Focused(focused) => game.handle_focused(focused),
+            // Needs the event loop to make the window, so game can't do it
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Err(e) = game.open_viewport(target) {
+                    eprintln!("Unable to open viewport: {:#}", e);
+                }
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -47,6 +168,14 @@ fn main() -> anyhow::Result<()> {
             WindowEvent::MouseInput { state, button, .. } => {
                 game.handle_mouse_button(button, state == ElementState::Pressed);
             }
+            WindowEvent::MouseWheel { delta, .. } => game.handle_scroll(match delta {
+                MouseScrollDelta::LineDelta(_, y) => y,
+                // Touchpads report pixels, treat a line as roughly 40 of them
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+            }),
+            WindowEvent::HoveredFile(path) => game.handle_hovered_file(Some(&path)),
+            WindowEvent::HoveredFileCancelled => game.handle_hovered_file(None),
+            WindowEvent::DroppedFile(path) => game.handle_dropped_file(&path),
             _ => (),
         },
         Event::DeviceEvent { device_id, event } => match event {
@@ -56,12 +185,105 @@ fn main() -> anyhow::Result<()> {
             _ => (),
         }
         Event::LoopExiting => {
-            save_json("config.json", game.export_config())
-                .block_on()
-                .unwrap();
+            save_config(config_path, game.export_config(), "Unable to save config")
         }
         _ => (),
-    })?;
+    }
+}
+
+/// Waits for the save on native, so nothing is lost on exit. The browser
+/// can't block, so there it runs as a task.
+fn save_config(path: &str, config: GameConfig, error: &'static str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(e) = save_json(path, config).block_on() {
+        eprintln!("{}: {}", error, e);
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let path = path.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = save_json(&path, config).await {
+                web_sys::console::error_1(&format!("{}: {:#}", error, e).into());
+            }
+        });
+    }
+}
+
+/// Takes `--config path` or `--config=path`, and falls back to
+/// `config.json`.
+#[cfg(not(target_arch = "wasm32"))]
+fn config_path(mut args: impl Iterator<Item = String>) -> anyhow::Result<String> {
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().context("--config needs a path after it");
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(path.to_string());
+        }
+    }
+    Ok(DEFAULT_CONFIG_PATH.to_string())
+}
 
+/// Better to find out now than when saving on exit.
+#[cfg(not(target_arch = "wasm32"))]
+fn check_writable(path: &str) -> anyhow::Result<()> {
+    let dir = match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    if !dir.is_dir() {
+        anyhow::bail!("Config folder {} doesn't exist", dir.display());
+    }
+    // The readonly flag misses ACLs and doesn't mean much for folders on
+    // Windows, so actually try writing something
+    let probe = dir.join(format!(".config-write-test-{}", std::process::id()));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .with_context(|| {
+            format!(
+                "Can't save config to {}, {} isn't writable",
+                path,
+                dir.display()
+            )
+        })?;
+    std::fs::remove_file(&probe)?;
     Ok(())
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    fn config_path_of(args: &[&str]) -> anyhow::Result<String> {
+        config_path(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn config_path_takes_either_form() {
+        assert_eq!(config_path_of(&["--config", "a.json"]).unwrap(), "a.json");
+        assert_eq!(config_path_of(&["--config=b.json"]).unwrap(), "b.json");
+        let later = config_path_of(&["-v", "--config=c.json"]).unwrap();
+        assert_eq!(later, "c.json");
+    }
+
+    #[test]
+    fn writable_folders_are_left_clean() {
+        let dir = std::env::temp_dir().join(format!("config-writable-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        check_writable(path.to_str().unwrap()).unwrap();
+        // The probe file is gone again
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert!(check_writable("no/such/folder/config.json").is_err());
+    }
+
+    #[test]
+    fn config_path_defaults_and_needs_a_value() {
+        assert_eq!(config_path_of(&[]).unwrap(), DEFAULT_CONFIG_PATH);
+        assert!(config_path_of(&["--config"]).is_err());
+    }
+}
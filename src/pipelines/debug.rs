@@ -1,8 +1,10 @@
 use bytemuck::{Pod, Zeroable};
 
+use super::{depth::DepthConfig, render_format::RenderFormat};
 use crate::resources::{
     buffer::{Batch, CpuBuffer},
     camera::{CameraBinder, CameraBinding},
+    model::Model,
 };
 
 #[repr(C)]
@@ -27,17 +29,32 @@ impl DebugVertex {
     }
 }
 
+/// Separates strips in the strip index buffer.
+const RESTART_INDEX: u32 = u32::MAX;
+
+/// Lines per direction. Anything past this is almost certainly a typo in
+/// the extent or step rather than a grid anyone wants.
+const MAX_GRID_LINES: f32 = 4096.0;
+
 pub struct DebugPipeline {
     draw_lines: wgpu::RenderPipeline,
+    draw_strips: wgpu::RenderPipeline,
+    draw_triangles: wgpu::RenderPipeline,
     vertex_buffer: CpuBuffer<DebugVertex>,
     index_buffer: CpuBuffer<u32>,
+    strip_index_buffer: CpuBuffer<u32>,
+    triangle_index_buffer: CpuBuffer<u32>,
+    #[cfg(feature = "hot-reload")]
+    template: PipelineTemplate,
 }
 
 impl DebugPipeline {
+    /// Without `depth_test` the lines draw on top of everything. They never
+    /// write depth either way, so they can't hide anything drawn after them.
     pub fn new(
         device: &wgpu::Device,
-        surface_format: wgpu::TextureFormat,
-        // depth_format: wgpu::TextureFormat,
+        format: &RenderFormat,
+        depth_test: bool,
         camera_binder: &CameraBinder,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("debug.wgsl"));
@@ -46,39 +63,41 @@ impl DebugPipeline {
             bind_group_layouts: &[camera_binder.layout()],
             push_constant_ranges: &[],
         });
-        let draw_lines = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("debug"),
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "displace_vertices",
-                buffers: &[DebugVertex::LAYOUT],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::LineList,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "draw",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            multiview: None,
-        });
+        let template = PipelineTemplate {
+            layout,
+            format: format.color_only(),
+            depth_stencil: depth_stencil(format.depth(), depth_test),
+        };
+        let (draw_lines, draw_strips, draw_triangles) = template.create(device, &shader);
 
         Self {
             draw_lines,
+            draw_strips,
+            draw_triangles,
             vertex_buffer: CpuBuffer::with_capacity(device, 64, wgpu::BufferUsages::VERTEX),
             index_buffer: CpuBuffer::with_capacity(device, 64, wgpu::BufferUsages::INDEX),
+            strip_index_buffer: CpuBuffer::with_capacity(device, 64, wgpu::BufferUsages::INDEX),
+            triangle_index_buffer: CpuBuffer::with_capacity(device, 64, wgpu::BufferUsages::INDEX),
+            #[cfg(feature = "hot-reload")]
+            template,
         }
     }
 
+    /// Rebuilds the pipelines from new debug.wgsl source. The old pipelines
+    /// stay in place if it doesn't compile.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        let (draw_lines, draw_strips, draw_triangles) =
+            super::hot_reload::validated(device, || {
+                let shader = super::hot_reload::create_shader(device, "debug.wgsl", source);
+                self.template.create(device, &shader)
+            })?;
+        self.draw_lines = draw_lines;
+        self.draw_strips = draw_strips;
+        self.draw_triangles = draw_triangles;
+        Ok(())
+    }
+
     pub fn batch<'a>(
         &'a mut self,
         device: &'a wgpu::Device,
@@ -90,6 +109,8 @@ impl DebugPipeline {
     pub fn clear(&mut self) {
         self.vertex_buffer.clear();
         self.index_buffer.clear();
+        self.strip_index_buffer.clear();
+        self.triangle_index_buffer.clear();
     }
 
     pub fn draw_lines<'a: 'b, 'b>(
@@ -102,6 +123,109 @@ impl DebugPipeline {
         pass.set_vertex_buffer(0, self.vertex_buffer.slice());
         pass.set_index_buffer(self.index_buffer.slice(), wgpu::IndexFormat::Uint32);
         pass.draw_indexed(0..self.index_buffer.len(), 0, 0..1);
+
+        if !self.strip_index_buffer.is_empty() {
+            pass.set_pipeline(&self.draw_strips);
+            pass.set_index_buffer(self.strip_index_buffer.slice(), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..self.strip_index_buffer.len(), 0, 0..1);
+        }
+    }
+
+    /// Draws everything pushed with [`DebugBatch::push_triangle`].
+    pub fn draw_triangles<'a: 'b, 'b>(
+        &'a self,
+        pass: &'b mut wgpu::RenderPass<'a>,
+        camera: &'a CameraBinding,
+    ) {
+        if !self.triangle_index_buffer.is_empty() {
+            pass.set_pipeline(&self.draw_triangles);
+            pass.set_bind_group(0, camera.bind_group(), &[]);
+            pass.set_vertex_buffer(0, self.vertex_buffer.slice());
+            pass.set_index_buffer(
+                self.triangle_index_buffer.slice(),
+                wgpu::IndexFormat::Uint32,
+            );
+            pass.draw_indexed(0..self.triangle_index_buffer.len(), 0, 0..1);
+        }
+    }
+}
+
+/// Never writes depth. Still needed without `depth_test` so the pipelines
+/// can be used in passes with a depth attachment.
+fn depth_stencil(depth: &DepthConfig, depth_test: bool) -> wgpu::DepthStencilState {
+    if depth_test {
+        depth.state(false, true)
+    } else {
+        wgpu::DepthStencilState {
+            depth_compare: wgpu::CompareFunction::Always,
+            ..depth.state(false, true)
+        }
+    }
+}
+
+/// Everything besides the shader that goes into the debug pipelines.
+struct PipelineTemplate {
+    layout: wgpu::PipelineLayout,
+    format: RenderFormat,
+    depth_stencil: wgpu::DepthStencilState,
+}
+
+impl PipelineTemplate {
+    /// Returns the line list, line strip and triangle pipelines.
+    fn create(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+    ) {
+        let create_pipeline = |label, primitive| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&self.layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: "displace_vertices",
+                    buffers: &[DebugVertex::LAYOUT],
+                },
+                primitive,
+                depth_stencil: Some(self.depth_stencil.clone()),
+                multisample: self.format.multisample(),
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: "draw",
+                    targets: self.format.targets(),
+                }),
+                multiview: None,
+            })
+        };
+        let draw_lines = create_pipeline(
+            "debug",
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+        );
+        let draw_strips = create_pipeline(
+            "debug_strips",
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineStrip,
+                strip_index_format: Some(wgpu::IndexFormat::Uint32),
+                ..Default::default()
+            },
+        );
+        // No culling, so shapes are visible from both sides
+        let draw_triangles = create_pipeline(
+            "debug_triangles",
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+        );
+
+        (draw_lines, draw_strips, draw_triangles)
     }
 }
 
@@ -109,6 +233,8 @@ pub struct DebugBatch<'a> {
     current_vertex: u32,
     vertices: Batch<'a, DebugVertex>,
     indices: Batch<'a, u32>,
+    strip_indices: Batch<'a, u32>,
+    triangle_indices: Batch<'a, u32>,
 }
 
 impl<'a> DebugBatch<'a> {
@@ -118,9 +244,11 @@ impl<'a> DebugBatch<'a> {
         queue: &'a wgpu::Queue,
     ) -> Self {
         Self {
-            current_vertex: 0,
+            current_vertex: pipeline.vertex_buffer.len(),
             vertices: pipeline.vertex_buffer.batch(device, queue),
             indices: pipeline.index_buffer.batch(device, queue),
+            strip_indices: pipeline.strip_index_buffer.batch(device, queue),
+            triangle_indices: pipeline.triangle_index_buffer.batch(device, queue),
         }
     }
 
@@ -131,4 +259,261 @@ impl<'a> DebugBatch<'a> {
         self.current_vertex += 1;
         self
     }
-}
\ No newline at end of file
+
+    pub fn push_line(
+        &mut self,
+        start: glam::Vec3,
+        end: glam::Vec3,
+        color: glam::Vec3,
+    ) -> &mut Self {
+        self.push_vertex(DebugVertex::new(start, color))
+            .push_vertex(DebugVertex::new(end, color))
+    }
+
+    /// Red, green and blue lines along +X, +Y and +Z, fading in from half
+    /// brightness at `origin`.
+    pub fn push_axes(&mut self, origin: glam::Vec3, length: f32) -> &mut Self {
+        for axis in [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z] {
+            self.push_vertex(DebugVertex::new(origin, axis * 0.5))
+                .push_vertex(DebugVertex::new(origin + axis * length, axis));
+        }
+        self
+    }
+
+    /// Draws a line out of every vertex of `model` along its normal.
+    pub fn push_normals(&mut self, model: &Model, length: f32, color: glam::Vec3) -> &mut Self {
+        for prim in model.primitives() {
+            let transform = prim.transform();
+            for v in prim.vertices() {
+                let position = transform.transform_point3(v.position());
+                let normal = transform.transform_vector3(v.normal()).normalize_or_zero();
+                self.push_line(position, position + normal * length, color);
+            }
+        }
+        self
+    }
+
+    /// Lines on the XZ plane every `step` units, out to `half_extent` in
+    /// every direction. The two lines through the origin use `axis_color`
+    /// if there is one.
+    pub fn push_grid(
+        &mut self,
+        half_extent: f32,
+        step: f32,
+        color: glam::Vec3,
+        axis_color: Option<glam::Vec3>,
+    ) -> anyhow::Result<&mut Self> {
+        if !(step.is_finite() && step > 0.0) {
+            anyhow::bail!("grid step needs to be positive, got {}", step);
+        }
+        if !(half_extent.is_finite() && half_extent >= 0.0) {
+            anyhow::bail!("grid extent can't be negative, got {}", half_extent);
+        }
+        let lines = (half_extent / step).floor();
+        if lines * 2.0 + 1.0 > MAX_GRID_LINES {
+            anyhow::bail!(
+                "grid of extent {} and step {} needs more than {} lines",
+                half_extent,
+                step,
+                MAX_GRID_LINES,
+            );
+        }
+
+        let lines = lines as i32;
+        for i in -lines..=lines {
+            let offset = i as f32 * step;
+            let color = match axis_color {
+                Some(axis_color) if i == 0 => axis_color,
+                _ => color,
+            };
+            self.push_line(
+                glam::vec3(offset, 0.0, -half_extent),
+                glam::vec3(offset, 0.0, half_extent),
+                color,
+            )
+            .push_line(
+                glam::vec3(-half_extent, 0.0, offset),
+                glam::vec3(half_extent, 0.0, offset),
+                color,
+            );
+        }
+        Ok(self)
+    }
+
+    /// A filled triangle, drawn by [`DebugPipeline::draw_triangles`].
+    pub fn push_triangle(
+        &mut self,
+        a: glam::Vec3,
+        b: glam::Vec3,
+        c: glam::Vec3,
+        color: glam::Vec3,
+    ) -> &mut Self {
+        for point in [a, b, c] {
+            self.vertices.push(DebugVertex::new(point, color));
+            self.triangle_indices.push(self.current_vertex);
+            self.current_vertex += 1;
+        }
+        self
+    }
+
+    /// Draws a connected line through `points` using one vertex per point.
+    pub fn push_strip(&mut self, points: &[glam::Vec3], color: glam::Vec3) -> &mut Self {
+        for point in points {
+            self.vertices.push(DebugVertex::new(*point, color));
+            self.strip_indices.push(self.current_vertex);
+            self.current_vertex += 1;
+        }
+        self.strip_indices.push(RESTART_INDEX);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipelines::render_format::check_pipeline, resources::texture::Texture};
+
+    fn pipeline(device: &wgpu::Device) -> DebugPipeline {
+        let format = RenderFormat::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            DepthConfig::new(Texture::DEPTH_FORMAT, false),
+            1,
+        );
+        DebugPipeline::new(device, &format, true, &CameraBinder::new(device))
+    }
+
+    #[test]
+    fn strips_use_one_vertex_per_point() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut debug = pipeline(&device);
+        debug
+            .batch(&device, &queue)
+            .push_strip(
+                &[glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y],
+                glam::Vec3::ONE,
+            )
+            .push_strip(&[glam::Vec3::Z, glam::Vec3::ONE], glam::Vec3::ONE);
+
+        assert_eq!(debug.vertex_buffer.len(), 5);
+        assert_eq!(
+            debug.strip_index_buffer.data(),
+            [0, 1, 2, RESTART_INDEX, 3, 4, RESTART_INDEX]
+        );
+        assert!(debug.index_buffer.is_empty());
+    }
+
+    #[test]
+    fn grid_has_a_line_per_step_each_way() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut debug = pipeline(&device);
+        debug
+            .batch(&device, &queue)
+            .push_grid(2.0, 1.0, glam::Vec3::ONE, None)
+            .unwrap();
+        // -2 to 2 is 5 lines along each axis, 2 indices per line
+        assert_eq!(debug.index_buffer.len(), 5 * 2 * 2);
+    }
+
+    #[test]
+    fn grid_rejects_bad_steps_and_extents() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut debug = pipeline(&device);
+        let mut batch = debug.batch(&device, &queue);
+        assert!(batch.push_grid(2.0, 0.0, glam::Vec3::ONE, None).is_err());
+        assert!(batch.push_grid(2.0, -1.0, glam::Vec3::ONE, None).is_err());
+        assert!(batch.push_grid(-2.0, 1.0, glam::Vec3::ONE, None).is_err());
+        assert!(batch.push_grid(1e9, 1.0, glam::Vec3::ONE, None).is_err());
+        drop(batch);
+        assert!(debug.index_buffer.is_empty());
+    }
+
+    #[test]
+    fn three_triangles_make_nine_indices() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut debug = pipeline(&device);
+        {
+            let mut batch = debug.batch(&device, &queue);
+            for i in 0..3 {
+                let offset = glam::Vec3::Z * i as f32;
+                batch.push_triangle(
+                    offset,
+                    offset + glam::Vec3::X,
+                    offset + glam::Vec3::Y,
+                    glam::Vec3::ONE,
+                );
+            }
+        }
+        assert_eq!(debug.triangle_index_buffer.len(), 9);
+        assert_eq!(debug.vertex_buffer.len(), 9);
+        // Lines are untouched
+        assert!(debug.index_buffer.is_empty());
+    }
+
+    #[test]
+    fn axes_are_three_colored_lines() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut debug = pipeline(&device);
+        let origin = glam::vec3(1.0, 2.0, 3.0);
+        debug.batch(&device, &queue).push_axes(origin, 2.0);
+
+        assert_eq!(debug.index_buffer.data(), [0, 1, 2, 3, 4, 5]);
+        let vertices = debug.vertex_buffer.data();
+        assert_eq!(vertices.len(), 6);
+        for (line, axis) in vertices
+            .chunks(2)
+            .zip([glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z])
+        {
+            assert_eq!(line[0].position, origin);
+            assert_eq!(line[1].position - line[0].position, axis * 2.0);
+            assert_eq!(line[0].color, axis * 0.5);
+            assert_eq!(line[1].color, axis);
+        }
+    }
+
+    #[test]
+    fn depth_test_is_optional() {
+        let depth = DepthConfig::new(Texture::DEPTH_FORMAT, false);
+        let tested = depth_stencil(&depth, true);
+        assert_eq!(tested.format, Texture::DEPTH_FORMAT);
+        assert_eq!(tested.depth_compare, wgpu::CompareFunction::LessEqual);
+        assert!(!tested.depth_write_enabled);
+
+        let on_top = depth_stencil(&depth, false);
+        assert_eq!(on_top.format, Texture::DEPTH_FORMAT);
+        assert_eq!(on_top.depth_compare, wgpu::CompareFunction::Always);
+        assert!(!on_top.depth_write_enabled);
+
+        let reversed = DepthConfig::new(Texture::DEPTH_FORMAT, true);
+        assert_eq!(
+            depth_stencil(&reversed, true).depth_compare,
+            wgpu::CompareFunction::GreaterEqual
+        );
+    }
+
+    #[test]
+    fn pipelines_fit_the_render_format() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let format = RenderFormat::new(
+            wgpu::TextureFormat::Rgba16Float,
+            DepthConfig::new(Texture::DEPTH_FORMAT, true),
+            1,
+        )
+        .with_target(wgpu::TextureFormat::Rgba8Unorm);
+        let debug = DebugPipeline::new(&device, &format, true, &CameraBinder::new(&device));
+        check_pipeline(&device, &queue, &debug.draw_lines, &format);
+        check_pipeline(&device, &queue, &debug.draw_strips, &format);
+        check_pipeline(&device, &queue, &debug.draw_triangles, &format);
+    }
+}
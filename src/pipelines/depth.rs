@@ -0,0 +1,47 @@
+/// Depth settings that every pass needs to agree on.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthConfig {
+    format: wgpu::TextureFormat,
+    reverse_z: bool,
+}
+
+impl DepthConfig {
+    /// With `reverse_z` the near plane maps to 1.0 and the far plane to 0.0,
+    /// which spreads float precision more evenly over the distance.
+    pub fn new(format: wgpu::TextureFormat, reverse_z: bool) -> Self {
+        Self { format, reverse_z }
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    /// The value at the far plane.
+    pub fn clear_value(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Passes when the fragment is closer than what's already there.
+    pub fn compare(&self, or_equal: bool) -> wgpu::CompareFunction {
+        match (self.reverse_z, or_equal) {
+            (false, false) => wgpu::CompareFunction::Less,
+            (false, true) => wgpu::CompareFunction::LessEqual,
+            (true, false) => wgpu::CompareFunction::Greater,
+            (true, true) => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    pub fn state(&self, write: bool, or_equal: bool) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: self.format,
+            depth_write_enabled: write,
+            depth_compare: self.compare(or_equal),
+            bias: wgpu::DepthBiasState::default(),
+            stencil: wgpu::StencilState::default(),
+        }
+    }
+}
@@ -1,57 +1,567 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use super::{render_format::RenderFormat, shadow::ShadowPass};
 use crate::resources::{
+    aabb::Aabb,
     camera::{CameraBinder, CameraBinding},
-    model::{Model, Vertex},
+    frustum::Frustum,
+    material::MaterialBinder,
+    model::{Model, Morphs, Vertex},
 };
 
-pub struct Fur {
-    draw: wgpu::RenderPipeline,
+pub const MIN_LAYERS: u32 = 1;
+pub const MAX_LAYERS: u32 = 128;
+
+/// Copies of the model drawn per primitive. This many transforms fill the
+/// 16KiB that every backend allows for a uniform buffer.
+pub const MAX_INSTANCES: usize = 256;
+
+/// Which side of the model's triangles gets skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CullMode {
+    None,
+    #[default]
+    Back,
+    /// Only the insides show, handy for spotting flipped triangles.
+    Front,
+}
+
+impl CullMode {
+    pub fn face(self) -> Option<wgpu::Face> {
+        match self {
+            Self::None => None,
+            Self::Back => Some(wgpu::Face::Back),
+            Self::Front => Some(wgpu::Face::Front),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Back,
+            Self::Back => Self::Front,
+            Self::Front => Self::None,
+        }
+    }
+}
+
+/// Which way round the vertices of a front facing triangle go on screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Winding {
+    /// What glTF uses.
+    #[default]
+    CounterClockwise,
+    Clockwise,
+}
+
+impl Winding {
+    pub fn front_face(self) -> wgpu::FrontFace {
+        match self {
+            Self::CounterClockwise => wgpu::FrontFace::Ccw,
+            Self::Clockwise => wgpu::FrontFace::Cw,
+        }
+    }
+}
+
+/// Matches `FurParams` in fur.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct FurParams {
+    /// Multiplies the base color at the root of the strands, `w` is unused.
+    pub root_color: glam::Vec4,
+    /// Multiplies the base color at the tip of the strands, `w` is unused.
+    pub tip_color: glam::Vec4,
+    /// Offset at the tip of the strands as a fraction of `length`, bending
+    /// them quadratically towards it. `w` is unused.
+    pub gravity: glam::Vec4,
+    /// How far the outermost layer sits from the surface.
+    pub length: f32,
+    /// Strands per UV unit along each axis.
+    pub density: f32,
+    /// Strand radius as a fraction of the space between strands. Anything
+    /// past 0.71 fills the whole cell.
+    pub thickness: f32,
+    /// Set through [`Fur::set_num_layers`] so the layers always span the
+    /// whole length.
     num_layers: u32,
+    /// Blend weights of the two morph targets in `x` and `y`. Set through
+    /// [`Fur::set_morph_weights`].
+    morph_weights: glam::Vec4,
+}
+
+impl Default for FurParams {
+    fn default() -> Self {
+        Self {
+            root_color: glam::Vec4::ONE,
+            tip_color: glam::Vec4::ONE,
+            gravity: glam::Vec4::ZERO,
+            length: 0.05,
+            density: 200.0,
+            thickness: 1.0,
+            num_layers: 32,
+            morph_weights: glam::Vec4::ZERO,
+        }
+    }
+}
+
+impl FurParams {
+    /// How far the strands can reach from the surface, for culling.
+    pub fn reach(&self) -> f32 {
+        self.length * (1.0 + self.gravity.truncate().length())
+    }
+}
+
+/// Matches `Light` in fur.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct LightData {
+    /// Normalized direction the light travels in, `w` is unused.
+    direction: glam::Vec4,
+    /// `w` is unused.
+    color: glam::Vec4,
+    /// Added everywhere, lit or not. `w` is unused.
+    ambient: glam::Vec4,
+}
+
+impl LightData {
+    /// A zero `direction` falls back to straight down.
+    pub fn new(direction: glam::Vec3, color: glam::Vec3, ambient: glam::Vec3) -> Self {
+        Self {
+            direction: direction
+                .try_normalize()
+                .unwrap_or(glam::Vec3::NEG_Y)
+                .extend(0.0),
+            color: color.extend(1.0),
+            ambient: ambient.extend(1.0),
+        }
+    }
+
+    pub fn direction(&self) -> glam::Vec3 {
+        self.direction.truncate()
+    }
+
+    pub fn color(&self) -> glam::Vec3 {
+        self.color.truncate()
+    }
+
+    pub fn ambient(&self) -> glam::Vec3 {
+        self.ambient.truncate()
+    }
+}
+
+impl Default for LightData {
+    fn default() -> Self {
+        Self::new(
+            glam::vec3(-0.4, -1.0, -0.6),
+            glam::Vec3::ONE,
+            glam::Vec3::splat(0.15),
+        )
+    }
+}
+
+pub struct Fur {
+    pipelines: Pipelines,
+    params: FurParams,
+    params_buffer: wgpu::Buffer,
+    /// `FurParams` and the instance transforms.
+    params_bind_group: wgpu::BindGroup,
+    instances: Vec<glam::Mat4>,
+    instance_buffer: wgpu::Buffer,
+    /// The instance transforms again, laid out for `ShadowPass`.
+    shadow_instances: wgpu::BindGroup,
+    light: LightData,
+    light_buffer: wgpu::Buffer,
+    /// `LightData`, and the shadow map with what it needs to be sampled.
+    light_bind_group: wgpu::BindGroup,
+    /// Kept so the pipelines can be rebuilt when culling changes.
+    shader: wgpu::ShaderModule,
+    template: PipelineTemplate,
 }
 
 impl Fur {
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         num_layers: u32,
-        surface_format: wgpu::TextureFormat,
-        depth_format: wgpu::TextureFormat,
+        format: &RenderFormat,
         camera_binder: &CameraBinder,
+        material_binder: &MaterialBinder,
+        shadow: &ShadowPass,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("fur.wgsl"));
+        let uniform = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fur::params_layout"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::VERTEX_FRAGMENT),
+                uniform(1, wgpu::ShaderStages::VERTEX_FRAGMENT),
+            ],
+        });
+        let light_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fur::light_layout"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::FRAGMENT),
+                // The light's camera, for moving into shadow map space
+                uniform(1, wgpu::ShaderStages::FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        });
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[camera_binder.layout()],
+            bind_group_layouts: &[
+                camera_binder.layout(),
+                material_binder.layout(),
+                &params_layout,
+                &light_layout,
+            ],
             push_constant_ranges: &[],
         });
-        let draw = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Fur"),
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "displace_vertices",
-                buffers: &[Vertex::LAYOUT],
+        let template = PipelineTemplate {
+            layout,
+            format: format.clone(),
+            polygon_mode_line: device
+                .features()
+                .contains(wgpu::Features::POLYGON_MODE_LINE),
+            cull_mode: CullMode::default(),
+            winding: Winding::default(),
+        };
+        let pipelines = template.create(device, &shader);
+        let params = FurParams {
+            num_layers: num_layers.clamp(MIN_LAYERS, MAX_LAYERS),
+            ..Default::default()
+        };
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Fur::params_buffer"),
+            contents: bytemuck::bytes_of(&params),
+            // Copying out is only for reading it back in tests
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        // Always full size, only the first `instances.len()` get used
+        let instances = vec![glam::Mat4::IDENTITY];
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Fur::instance_buffer"),
+            size: (MAX_INSTANCES * std::mem::size_of::<glam::Mat4>()) as _,
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&instance_buffer, 0, bytemuck::cast_slice(&instances));
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fur::params_bind_group"),
+            layout: &params_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let shadow_instances = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fur::shadow_instances"),
+            layout: shadow.instances_layout(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: instance_buffer.as_entire_binding(),
+            }],
+        });
+        let light = LightData::default();
+        let light_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Fur::light_buffer"),
+            contents: bytemuck::bytes_of(&light),
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fur::light_bind_group"),
+            layout: &light_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: shadow.light_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(shadow.shadow_map().view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(shadow.compare_sampler()),
+                },
+            ],
+        });
+
+        Self {
+            pipelines,
+            params,
+            params_buffer,
+            params_bind_group,
+            instances,
+            instance_buffer,
+            shadow_instances,
+            light,
+            light_buffer,
+            light_bind_group,
+            shader,
+            template,
+        }
+    }
+
+    /// Rebuilds the pipelines from new fur.wgsl source. The old pipelines
+    /// stay in place if it doesn't compile.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload(&mut self, device: &wgpu::Device, source: &str) -> anyhow::Result<()> {
+        let shader = super::hot_reload::create_shader(device, "fur.wgsl", source);
+        self.pipelines =
+            super::hot_reload::validated(device, || self.template.create(device, &shader))?;
+        self.shader = shader;
+        Ok(())
+    }
+
+    pub fn cull_mode(&self) -> CullMode {
+        self.template.cull_mode
+    }
+
+    /// Rebuilds the pipelines. Wireframes are never culled.
+    pub fn set_cull_mode(&mut self, device: &wgpu::Device, cull_mode: CullMode) {
+        self.template.cull_mode = cull_mode;
+        self.pipelines = self.template.create(device, &self.shader);
+    }
+
+    pub fn winding(&self) -> Winding {
+        self.template.winding
+    }
+
+    /// Rebuilds the pipelines.
+    pub fn set_winding(&mut self, device: &wgpu::Device, winding: Winding) {
+        self.template.winding = winding;
+        self.pipelines = self.template.create(device, &self.shader);
+    }
+
+    pub fn num_layers(&self) -> u32 {
+        self.params.num_layers
+    }
+
+    /// Each layer is an instance, so no pipelines need rebuilding. Clamped
+    /// between `MIN_LAYERS` and `MAX_LAYERS`.
+    pub fn set_num_layers(&mut self, queue: &wgpu::Queue, num_layers: u32) {
+        self.params.num_layers = num_layers.clamp(MIN_LAYERS, MAX_LAYERS);
+        self.write_params(queue);
+    }
+
+    fn write_params(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+    }
+
+    pub fn instances(&self) -> &[glam::Mat4] {
+        &self.instances
+    }
+
+    /// Draws a copy of the model with each transform. Anything past
+    /// `MAX_INSTANCES` is dropped, and an empty slice draws nothing.
+    pub fn set_instances(&mut self, queue: &wgpu::Queue, transforms: &[glam::Mat4]) {
+        if transforms.len() > MAX_INSTANCES {
+            eprintln!(
+                "Only {} of {} instances will be drawn",
+                MAX_INSTANCES,
+                transforms.len()
+            );
+        }
+        self.instances = transforms[..transforms.len().min(MAX_INSTANCES)].to_vec();
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+    }
+
+    pub fn params(&self) -> FurParams {
+        self.params
+    }
+
+    /// Keeps the current layer count and morph weights, those are set with
+    /// `set_num_layers` and `set_morph_weights`.
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: FurParams) {
+        self.params = FurParams {
+            num_layers: self.params.num_layers,
+            morph_weights: self.params.morph_weights,
+            ..params
+        };
+        self.write_params(queue);
+    }
+
+    pub fn morph_weights(&self) -> [f32; 2] {
+        [self.params.morph_weights.x, self.params.morph_weights.y]
+    }
+
+    /// How much of each of the first two morph targets to blend in.
+    /// Primitives without morph targets ignore this.
+    pub fn set_morph_weights(&mut self, queue: &wgpu::Queue, weights: [f32; 2]) {
+        self.params.morph_weights = glam::vec4(weights[0], weights[1], 0.0, 0.0);
+        self.write_params(queue);
+    }
+
+    pub fn set_length(&mut self, queue: &wgpu::Queue, length: f32) {
+        self.set_params(
+            queue,
+            FurParams {
+                length,
+                ..self.params
             },
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: depth_format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                bias: wgpu::DepthBiasState::default(),
-                stencil: wgpu::StencilState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "shade_fur",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            multiview: None,
+        );
+    }
+
+    pub fn set_gravity(&mut self, queue: &wgpu::Queue, gravity: glam::Vec3) {
+        self.set_params(
+            queue,
+            FurParams {
+                gravity: gravity.extend(0.0),
+                ..self.params
+            },
+        );
+    }
+
+    pub fn set_density(&mut self, queue: &wgpu::Queue, density: f32, thickness: f32) {
+        self.set_params(
+            queue,
+            FurParams {
+                density,
+                thickness,
+                ..self.params
+            },
+        );
+    }
+
+    pub fn set_colors(&mut self, queue: &wgpu::Queue, root: glam::Vec3, tip: glam::Vec3) {
+        self.set_params(
+            queue,
+            FurParams {
+                root_color: root.extend(1.0),
+                tip_color: tip.extend(1.0),
+                ..self.params
+            },
+        );
+    }
+
+    pub fn light(&self) -> LightData {
+        self.light
+    }
+
+    /// `direction` is the way the light travels, and gets normalized.
+    pub fn set_light(&mut self, queue: &wgpu::Queue, direction: glam::Vec3, color: glam::Vec3) {
+        self.set_light_data(
+            queue,
+            LightData::new(direction, color, self.light.ambient()),
+        );
+    }
+
+    pub fn set_light_data(&mut self, queue: &wgpu::Queue, light: LightData) {
+        self.light = light;
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&self.light));
+    }
+
+    /// Redraws the shadow map with every copy of the model.
+    pub fn draw_shadow(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        shadow: &ShadowPass,
+        model: Option<&Model>,
+    ) {
+        shadow.render(
+            encoder,
+            model,
+            &self.shadow_instances,
+            self.instances.len() as u32,
+        );
+    }
+
+    /// Draws the edges of every copy of the model, without any fur. Uses
+    /// `Primitive::line_indices` when the device can't draw triangles as
+    /// lines.
+    pub fn draw_wireframe<'a: 'b, 'b>(
+        &'a self,
+        pass: &'b mut wgpu::RenderPass<'a>,
+        model: &'a Model,
+        camera: &'a CameraBinding,
+        frustum: &Frustum,
+    ) {
+        let visible = model.primitives().filter(|prim| {
+            self.instances.iter().any(|transform| {
+                frustum.contains_aabb(prim.aabb().transform(*transform * prim.transform()))
+            })
         });
+        let num_instances = self.instances.len() as u32;
+        // Same layout as the fur, so every group needs setting even though
+        // only the camera and instances get used
+        pass.set_pipeline(&self.pipelines.wireframe);
+        pass.set_bind_group(0, camera.bind_group(), &[]);
+        pass.set_bind_group(2, &self.params_bind_group, &[]);
+        pass.set_bind_group(3, &self.light_bind_group, &[]);
+        for prim in visible {
+            pass.set_bind_group(1, prim.material().bind_group(), &[]);
+            pass.set_vertex_buffer(0, prim.vertex_buffer().slice(..));
+            match (prim.line_indices(), prim.index_buffer()) {
+                (Some((line_indices, count)), _) => {
+                    pass.set_index_buffer(line_indices.slice(..), wgpu::IndexFormat::Uint32);
+                    pass.draw_indexed(0..count, 0, 0..num_instances);
+                }
+                (None, Some(index_buffer)) => {
+                    pass.set_index_buffer(index_buffer.slice(..), prim.index_format());
+                    pass.draw_indexed(0..prim.num_indices(), 0, 0..num_instances);
+                }
+                (None, None) => pass.draw(0..prim.num_vertices(), 0..num_instances),
+            }
+        }
+    }
 
-        Self { draw, num_layers }
+    /// Everything the strands of every copy can reach. Not valid without
+    /// any instances.
+    pub fn bounds(&self, model: &Model) -> Aabb {
+        let reach = self.params.reach();
+        model
+            .primitives()
+            .flat_map(|prim| {
+                let aabb = prim.aabb().expand(reach);
+                self.instances
+                    .iter()
+                    .map(move |transform| aabb.transform(*transform * prim.transform()))
+            })
+            .fold(Aabb::EMPTY, Aabb::union)
     }
 
     pub fn draw<'a: 'b, 'b>(
@@ -59,16 +569,484 @@ impl Fur {
         pass: &'b mut wgpu::RenderPass<'a>,
         model: &'a Model,
         camera: &'a CameraBinding,
+        frustum: &Frustum,
     ) {
-        // For now just load the first mesh
-        for mesh in model.meshes() {
-            for prim in mesh.primitives() {
-                pass.set_pipeline(&self.draw);
-                pass.set_bind_group(0, camera.bind_group(), &[]);
-                pass.set_index_buffer(prim.index_buffer().slice(..), prim.index_format());
-                pass.set_vertex_buffer(0, prim.vertex_buffer().slice(..));
-                pass.draw_indexed(0..prim.num_indices(), 0, 0..self.num_layers);
+        // The shells stick out past the mesh, so the bounds need to as well
+        let reach = self.params.reach();
+        let visible = model.primitives().filter(|prim| {
+            let aabb = prim.aabb().expand(reach);
+            self.instances.iter().any(|transform| {
+                frustum.contains_aabb(aabb.transform(*transform * prim.transform()))
+            })
+        });
+        // Every copy gets all the layers, see `displace` in fur.wgsl
+        let num_instances = self.params.num_layers * self.instances.len() as u32;
+        for prim in visible {
+            let tex_coord_1 = prim.tex_coord_1_buffer();
+            let morphs = prim.morph_buffer();
+            pass.set_pipeline(self.pipelines.get(tex_coord_1.is_some(), morphs.is_some()));
+            for (slot, buffer) in extra_vertex_buffers(tex_coord_1, morphs) {
+                pass.set_vertex_buffer(slot, buffer.slice(..));
             }
+            pass.set_bind_group(0, camera.bind_group(), &[]);
+            pass.set_bind_group(1, prim.material().bind_group(), &[]);
+            pass.set_bind_group(2, &self.params_bind_group, &[]);
+            pass.set_bind_group(3, &self.light_bind_group, &[]);
+            pass.set_vertex_buffer(0, prim.vertex_buffer().slice(..));
+            match prim.index_buffer() {
+                Some(index_buffer) => {
+                    pass.set_index_buffer(index_buffer.slice(..), prim.index_format());
+                    pass.draw_indexed(0..prim.num_indices(), 0, 0..num_instances);
+                }
+                None => pass.draw(0..prim.num_vertices(), 0..num_instances),
+            }
+        }
+    }
+}
+
+/// `n` by `n` copies spread over the XZ plane, `spacing` apart and centered
+/// on the origin.
+pub fn grid_transforms(n: u32, spacing: f32) -> Vec<glam::Mat4> {
+    let offset = (n as f32 - 1.0) * 0.5;
+    (0..n * n)
+        .map(|i| {
+            let x = (i % n) as f32 - offset;
+            let z = (i / n) as f32 - offset;
+            glam::Mat4::from_translation(glam::vec3(x, 0.0, z) * spacing)
+        })
+        .collect()
+}
+
+/// The slots for a primitive's optional vertex buffers. They fill the slots
+/// after the vertex buffer in order, same as the layouts in `Pipelines`.
+fn extra_vertex_buffers<T>(
+    tex_coord_1: Option<T>,
+    morphs: Option<T>,
+) -> impl Iterator<Item = (u32, T)> {
+    [tex_coord_1, morphs]
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, buffer)| (i as u32 + 1, buffer))
+}
+
+/// A pipeline for each combination of optional vertex buffers a primitive
+/// can have, and one for wireframes.
+struct Pipelines {
+    draw: wgpu::RenderPipeline,
+    tex_coord_1: wgpu::RenderPipeline,
+    morphs: wgpu::RenderPipeline,
+    tex_coord_1_morphs: wgpu::RenderPipeline,
+    wireframe: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn get(&self, tex_coord_1: bool, morphs: bool) -> &wgpu::RenderPipeline {
+        match (tex_coord_1, morphs) {
+            (false, false) => &self.draw,
+            (true, false) => &self.tex_coord_1,
+            (false, true) => &self.morphs,
+            (true, true) => &self.tex_coord_1_morphs,
+        }
+    }
+}
+
+/// Everything besides the shader that goes into the fur pipelines.
+struct PipelineTemplate {
+    layout: wgpu::PipelineLayout,
+    format: RenderFormat,
+    /// Without it wireframes get drawn as line lists instead.
+    polygon_mode_line: bool,
+    cull_mode: CullMode,
+    winding: Winding,
+}
+
+impl PipelineTemplate {
+    /// Opaque targets keep their alpha at 1. A second target gets the
+    /// normals.
+    fn fragment_entry_point(&self) -> &'static str {
+        match (self.format.targets().len(), self.format.is_blended()) {
+            (1, false) => "shade_fur",
+            (1, true) => "shade_fur_translucent",
+            (_, false) => "shade_fur_normals",
+            (_, true) => "shade_fur_normals_translucent",
+        }
+    }
+
+    /// For the fur pipelines, wireframes are never culled.
+    fn primitive(&self) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            cull_mode: self.cull_mode.face(),
+            front_face: self.winding.front_face(),
+            ..Default::default()
+        }
+    }
+
+    fn create(&self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Pipelines {
+        let create_pipeline = |entry_point, buffers| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Fur"),
+                layout: Some(&self.layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point,
+                    buffers,
+                },
+                primitive: self.primitive(),
+                depth_stencil: Some(self.format.depth().state(true, false)),
+                multisample: self.format.multisample(),
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: self.fragment_entry_point(),
+                    targets: self.format.targets(),
+                }),
+                multiview: None,
+            })
+        };
+        let wireframe_format = self.format.color_only().with_blend(None);
+        let wireframe = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fur::wireframe"),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "wireframe_vertices",
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: if self.polygon_mode_line {
+                wgpu::PrimitiveState {
+                    polygon_mode: wgpu::PolygonMode::Line,
+                    ..Default::default()
+                }
+            } else {
+                wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                }
+            },
+            depth_stencil: Some(wireframe_format.depth().state(true, false)),
+            multisample: wireframe_format.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "shade_wireframe",
+                targets: wireframe_format.targets(),
+            }),
+            multiview: None,
+        });
+        // The second UV set and morph targets come from separate buffers
+        Pipelines {
+            draw: create_pipeline("displace_vertices", &[Vertex::LAYOUT]),
+            tex_coord_1: create_pipeline(
+                "displace_vertices_tex_coord_1",
+                &[Vertex::LAYOUT, Vertex::TEX_COORD_1_LAYOUT],
+            ),
+            morphs: create_pipeline(
+                "displace_vertices_morphs",
+                &[Vertex::LAYOUT, Morphs::LAYOUT],
+            ),
+            tex_coord_1_morphs: create_pipeline(
+                "displace_vertices_tex_coord_1_morphs",
+                &[Vertex::LAYOUT, Vertex::TEX_COORD_1_LAYOUT, Morphs::LAYOUT],
+            ),
+            wireframe,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipelines::{depth::DepthConfig, render_format::check_pipeline},
+        resources::texture::Texture,
+    };
+
+    fn format(sample_count: u32) -> RenderFormat {
+        RenderFormat::new(
+            wgpu::TextureFormat::Rgba8Unorm,
+            DepthConfig::new(Texture::DEPTH_FORMAT, false),
+            sample_count,
+        )
+    }
+
+    fn fur(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        num_layers: u32,
+        format: &RenderFormat,
+    ) -> Fur {
+        let camera_binder = CameraBinder::new(device);
+        let material_binder = MaterialBinder::new(device, queue);
+        let shadow = ShadowPass::new(device, 64, &camera_binder, &material_binder);
+        Fur::new(
+            device,
+            queue,
+            num_layers,
+            format,
+            &camera_binder,
+            &material_binder,
+            &shadow,
+        )
+    }
+
+    fn read_back(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<u8> {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = slice.get_mapped_range().to_vec();
+        data
+    }
+
+    #[test]
+    fn layers_are_clamped() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 0, &format(1));
+        assert_eq!(fur.num_layers(), MIN_LAYERS);
+
+        fur.set_num_layers(&queue, MAX_LAYERS + 1);
+        assert_eq!(fur.num_layers(), MAX_LAYERS);
+        fur.set_num_layers(&queue, 0);
+        assert_eq!(fur.num_layers(), MIN_LAYERS);
+        fur.set_num_layers(&queue, 64);
+        assert_eq!(fur.num_layers(), 64);
+    }
+
+    #[test]
+    fn pipelines_get_the_sample_count() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let format = format(4);
+        let fur = fur(&device, &queue, 8, &format);
+        assert_eq!(fur.template.format.multisample().count, 4);
+
+        check_pipeline(&device, &queue, &fur.pipelines.draw, &format);
+    }
+
+    #[test]
+    fn setters_upload_the_params() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 16, &format(1));
+        fur.set_length(&queue, 0.2);
+        fur.set_gravity(&queue, glam::Vec3::NEG_Y);
+        fur.set_density(&queue, 50.0, 0.5);
+        let expected = FurParams {
+            length: 0.2,
+            gravity: glam::Vec3::NEG_Y.extend(0.0),
+            density: 50.0,
+            thickness: 0.5,
+            num_layers: 16,
+            ..Default::default()
+        };
+        assert_eq!(fur.params(), expected);
+
+        let bytes = read_back(&device, &queue, &fur.params_buffer);
+        assert_eq!(bytes, bytemuck::bytes_of(&expected));
+        // Where fur.wgsl expects them after the three colors
+        assert_eq!(bytes.len(), 80);
+        assert_eq!(bytes[48..52], 0.2f32.to_ne_bytes());
+        assert_eq!(bytes[60..64], 16u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn light_directions_get_normalized() {
+        let light = LightData::new(glam::vec3(3.0, 0.0, 4.0), glam::Vec3::ONE, glam::Vec3::ZERO);
+        let direction = light.direction();
+        assert!(direction.abs_diff_eq(glam::vec3(0.6, 0.0, 0.8), 1e-6));
+        // Packed as three vec4s, with nothing in the direction's w
+        let floats: &[f32] = bytemuck::cast_slice(bytemuck::bytes_of(&light));
+        assert_eq!(floats.len(), 12);
+        assert_eq!(floats[3], 0.0);
+        assert_eq!(floats[4..7], [1.0; 3]);
+
+        let zero = LightData::new(glam::Vec3::ZERO, glam::Vec3::ONE, glam::Vec3::ZERO);
+        assert_eq!(zero.direction(), glam::Vec3::NEG_Y);
+    }
+
+    #[test]
+    fn set_light_uploads_the_normalized_direction() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 16, &format(1));
+        fur.set_light(&queue, glam::vec3(0.0, -2.0, 0.0), glam::Vec3::ONE);
+        assert_eq!(fur.light().direction(), glam::Vec3::NEG_Y);
+        let bytes = read_back(&device, &queue, &fur.light_buffer);
+        assert_eq!(bytes, bytemuck::bytes_of(&fur.light()));
+    }
+
+    #[test]
+    fn grids_are_centered_on_the_origin() {
+        let transforms = grid_transforms(3, 2.0);
+        assert_eq!(transforms.len(), 9);
+        let positions = transforms
+            .iter()
+            .map(|transform| transform.w_axis.truncate())
+            .collect::<Vec<_>>();
+        assert_eq!(positions[0], glam::vec3(-2.0, 0.0, -2.0));
+        assert_eq!(positions[4], glam::Vec3::ZERO);
+        assert_eq!(positions[8], glam::vec3(2.0, 0.0, 2.0));
+        // Neighbors along a row are `spacing` apart
+        assert_eq!(positions[1] - positions[0], glam::vec3(2.0, 0.0, 0.0));
+        assert_eq!(positions.iter().sum::<glam::Vec3>(), glam::Vec3::ZERO);
+
+        // Even sizes have nothing in the middle
+        let transforms = grid_transforms(2, 1.0);
+        assert_eq!(transforms[0].w_axis.truncate(), glam::vec3(-0.5, 0.0, -0.5));
+        assert!(grid_transforms(0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn instances_are_packed_in_order() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 16, &format(1));
+        let transforms = grid_transforms(2, 3.0);
+        fur.set_instances(&queue, &transforms);
+        assert_eq!(fur.instances(), transforms);
+
+        let bytes = read_back(&device, &queue, &fur.instance_buffer);
+        let packed: &[u8] = bytemuck::cast_slice(&transforms);
+        assert_eq!(bytes[..packed.len()], *packed);
+    }
+
+    #[test]
+    fn morph_weights_get_uploaded_and_kept() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 16, &format(1));
+        fur.set_morph_weights(&queue, [0.5, 0.25]);
+        // Other params changing leaves them alone
+        fur.set_length(&queue, 0.1);
+        assert_eq!(fur.morph_weights(), [0.5, 0.25]);
+
+        let bytes = read_back(&device, &queue, &fur.params_buffer);
+        let weights: &[u8] = bytemuck::cast_slice(&[0.5f32, 0.25]);
+        assert_eq!(bytes[64..72], *weights);
+    }
+
+    #[test]
+    fn morphs_get_the_next_free_slot() {
+        let slots = |tex_coord_1: Option<&'static str>, morphs| {
+            extra_vertex_buffers(tex_coord_1, morphs).collect::<Vec<_>>()
+        };
+        assert_eq!(slots(None, Some("morphs")), [(1, "morphs")]);
+        assert_eq!(
+            slots(Some("tex_coord_1"), Some("morphs")),
+            [(1, "tex_coord_1"), (2, "morphs")]
+        );
+        assert_eq!(slots(Some("tex_coord_1"), None), [(1, "tex_coord_1")]);
+        assert!(slots(None, None).is_empty());
+    }
+
+    #[test]
+    fn pipelines_use_the_render_formats() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let format = RenderFormat::new(
+            wgpu::TextureFormat::Rgba16Float,
+            DepthConfig::new(Texture::DEPTH_FORMAT, true),
+            1,
+        )
+        .with_target(wgpu::TextureFormat::Rgba8Unorm);
+        let fur = fur(&device, &queue, 16, &format);
+        let formats = fur
+            .template
+            .format
+            .targets()
+            .iter()
+            .flatten()
+            .map(|target| target.format)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            formats,
+            [
+                wgpu::TextureFormat::Rgba16Float,
+                wgpu::TextureFormat::Rgba8Unorm
+            ]
+        );
+        assert!(fur.template.format.depth().reverse_z());
+
+        check_pipeline(&device, &queue, &fur.pipelines.draw, &format);
+        check_pipeline(&device, &queue, &fur.pipelines.morphs, &format);
+        check_pipeline(&device, &queue, &fur.pipelines.wireframe, &format);
+    }
+
+    #[test]
+    fn fragment_outputs_match_the_targets() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let cases = [
+            (format(1), 1, "shade_fur"),
+            (format(1).alpha_blend(), 1, "shade_fur_translucent"),
+            (
+                format(1).with_target(wgpu::TextureFormat::Rgba8Unorm),
+                2,
+                "shade_fur_normals",
+            ),
+            (
+                format(1)
+                    .with_target(wgpu::TextureFormat::Rgba8Unorm)
+                    .alpha_blend(),
+                2,
+                "shade_fur_normals_translucent",
+            ),
+        ];
+        for (format, num_targets, entry_point) in cases {
+            let fur = fur(&device, &queue, 16, &format);
+            assert_eq!(fur.template.format.targets().len(), num_targets);
+            assert_eq!(fur.template.fragment_entry_point(), entry_point);
+            check_pipeline(&device, &queue, &fur.pipelines.draw, &format);
+        }
+    }
+
+    #[test]
+    fn primitive_state_follows_culling_and_winding() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut fur = fur(&device, &queue, 16, &format(1));
+        // Back faces of glTF's counter clockwise triangles by default
+        let primitive = fur.template.primitive();
+        assert_eq!(primitive.cull_mode, Some(wgpu::Face::Back));
+        assert_eq!(primitive.front_face, wgpu::FrontFace::Ccw);
+
+        fur.set_cull_mode(&device, CullMode::Front);
+        assert_eq!(fur.template.primitive().cull_mode, Some(wgpu::Face::Front));
+        fur.set_cull_mode(&device, CullMode::None);
+        assert_eq!(fur.template.primitive().cull_mode, None);
+        fur.set_winding(&device, Winding::Clockwise);
+        assert_eq!(fur.template.primitive().front_face, wgpu::FrontFace::Cw);
+        // Still the cull mode from before
+        assert_eq!(fur.cull_mode(), CullMode::None);
+    }
+
+    #[test]
+    fn cull_modes_cycle() {
+        let mut cull_mode = CullMode::default();
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(cull_mode);
+            cull_mode = cull_mode.next();
         }
+        assert_eq!(seen, [CullMode::Back, CullMode::Front, CullMode::None]);
+        assert_eq!(cull_mode, CullMode::Back);
     }
 }
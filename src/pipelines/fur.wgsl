@@ -9,6 +9,26 @@ struct Vertex {
     normal: vec3<f32>,
     @location(2)
     tex_coord: vec2<f32>,
+    @location(3)
+    tangent: vec4<f32>,
+    @location(9)
+    color: vec4<f32>,
+}
+
+struct TexCoord1 {
+    @location(4)
+    tex_coord_1: vec2<f32>,
+}
+
+struct Morphs {
+    @location(5)
+    d0_position: vec3<f32>,
+    @location(6)
+    d0_normal: vec3<f32>,
+    @location(7)
+    d1_position: vec3<f32>,
+    @location(8)
+    d1_normal: vec3<f32>,
 }
 
 struct Instance {
@@ -20,6 +40,61 @@ struct Instance {
 @binding(0)
 var<uniform> camera: Camera;
 
+@group(1)
+@binding(0)
+var t_normal: texture_2d<f32>;
+@group(1)
+@binding(1)
+var s_normal: sampler;
+@group(1)
+@binding(2)
+var t_base_color: texture_2d<f32>;
+@group(1)
+@binding(3)
+var s_base_color: sampler;
+// Where the primitive's node puts it, before the instance transform
+@group(1)
+@binding(4)
+var<uniform> node: mat4x4<f32>;
+
+struct FurParams {
+    root_color: vec4<f32>,
+    tip_color: vec4<f32>,
+    gravity: vec4<f32>,
+    length: f32,
+    density: f32,
+    thickness: f32,
+    num_layers: u32,
+    morph_weights: vec4<f32>,
+}
+
+@group(2)
+@binding(0)
+var<uniform> fur: FurParams;
+// Should match MAX_INSTANCES in fur.rs
+@group(2)
+@binding(1)
+var<uniform> instances: array<mat4x4<f32>, 256>;
+
+struct Light {
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    ambient: vec4<f32>,
+}
+
+@group(3)
+@binding(0)
+var<uniform> light: Light;
+@group(3)
+@binding(1)
+var<uniform> light_camera: Camera;
+@group(3)
+@binding(2)
+var t_shadow: texture_depth_2d;
+@group(3)
+@binding(3)
+var s_shadow: sampler_comparison;
+
 struct VsOut {
     @location(0)
     world_normal: vec3<f32>,
@@ -28,28 +103,126 @@ struct VsOut {
     @location(2)
     height_factor: f32,
     @location(3)
-    debug: vec3<f32>,
+    world_position: vec3<f32>,
+    @location(4)
+    world_tangent: vec4<f32>,
+    // Second UV set for lightmaps and AO, same as tex_coord if the model
+    // only has one.
+    @location(5)
+    tex_coord_1: vec2<f32>,
+    @location(6)
+    color: vec4<f32>,
     @builtin(position)
     frag_position: vec4<f32>,
 }
 
 @vertex
 fn displace_vertices(vertex: Vertex, shell: Instance) -> VsOut {
-    let normal = vertex.normal;
-    let num_shells = 32.0;
-    let fur_height = 0.05;
-    let height_factor = f32(shell.id) / num_shells;
-    let displaced = vertex.position + vertex.normal * height_factor * fur_height;
+    return displace(vertex, vertex.tex_coord, shell);
+}
+
+@vertex
+fn displace_vertices_tex_coord_1(vertex: Vertex, uv1: TexCoord1, shell: Instance) -> VsOut {
+    return displace(vertex, uv1.tex_coord_1, shell);
+}
+
+@vertex
+fn displace_vertices_morphs(vertex: Vertex, morphs: Morphs, shell: Instance) -> VsOut {
+    return displace(morph(vertex, morphs), vertex.tex_coord, shell);
+}
+
+@vertex
+fn displace_vertices_tex_coord_1_morphs(
+    vertex: Vertex,
+    uv1: TexCoord1,
+    morphs: Morphs,
+    shell: Instance,
+) -> VsOut {
+    return displace(morph(vertex, morphs), uv1.tex_coord_1, shell);
+}
+
+fn morph(vertex: Vertex, morphs: Morphs) -> Vertex {
+    let w = fur.morph_weights;
+    var morphed = vertex;
+    morphed.position += morphs.d0_position * w.x + morphs.d1_position * w.y;
+    morphed.normal = normalize(vertex.normal + morphs.d0_normal * w.x + morphs.d1_normal * w.y);
+    return morphed;
+}
+
+// Instances are laid out copy by copy, each with every layer.
+fn displace(vertex: Vertex, tex_coord_1: vec2<f32>, shell: Instance) -> VsOut {
+    let model = instances[shell.id / fur.num_layers] * node;
+    // Normals come out slightly wrong for non-uniform scales, but fur
+    // hides most of that
+    let model_3 = mat3x3(model[0].xyz, model[1].xyz, model[2].xyz);
+    let normal = normalize(model_3 * vertex.normal);
+    let tangent = vec4(model_3 * vertex.tangent.xyz, vertex.tangent.w);
+    let position = (model * vec4(vertex.position, 1.0)).xyz;
+
+    let height_factor = f32(shell.id % fur.num_layers) / f32(fur.num_layers);
+    // Strands get stiffer towards the root
+    let droop = fur.gravity.xyz * height_factor * height_factor;
+    let displaced = position + (normal * height_factor + droop) * fur.length;
     let frag_position = camera.view_proj * vec4(displaced, 1.0);
-    return VsOut(normal, vertex.tex_coord, height_factor, vec3(displaced), frag_position);
+    return VsOut(normal, vertex.tex_coord, height_factor, vec3(displaced), tangent, tex_coord_1, vertex.color, frag_position);
 }
 
 @fragment
 fn shade_fur(in: VsOut) -> @location(0) vec4<f32> {
+    return vec4(shade(in), 1.0);
+}
+
+// Outer shells fade out, needs a blended target
+@fragment
+fn shade_fur_translucent(in: VsOut) -> @location(0) vec4<f32> {
+    return vec4(shade(in), 1.0 - in.height_factor);
+}
+
+// Just the surface of each copy, for wireframes
+@vertex
+fn wireframe_vertices(vertex: Vertex, copy: Instance) -> @builtin(position) vec4<f32> {
+    let model = instances[copy.id] * node;
+    return camera.view_proj * model * vec4(vertex.position, 1.0);
+}
+
+@fragment
+fn shade_wireframe() -> @location(0) vec4<f32> {
+    return vec4(fur.tip_color.rgb, 1.0);
+}
+
+// What a G-buffer pass writes, normals are packed into 0 to 1
+struct GBuffer {
+    @location(0)
+    color: vec4<f32>,
+    @location(1)
+    normal: vec4<f32>,
+}
+
+@fragment
+fn shade_fur_normals(in: VsOut) -> GBuffer {
+    return gbuffer(in, 1.0);
+}
+
+@fragment
+fn shade_fur_normals_translucent(in: VsOut) -> GBuffer {
+    return gbuffer(in, 1.0 - in.height_factor);
+}
+
+fn gbuffer(in: VsOut, alpha: f32) -> GBuffer {
+    let color = shade(in);
+    let normal = normalize(in.world_normal) * 0.5 + 0.5;
+    return GBuffer(vec4(color, alpha), vec4(normal, alpha));
+}
+
+fn shade(in: VsOut) -> vec3<f32> {
+    // Sample before any discards so we stay in uniform control flow
+    let normal = perturb_normal(in);
+    let base_color = textureSample(t_base_color, s_base_color, in.tex_coord).rgb * in.color.rgb;
+
     // let color = in.world_normal * 0.5 + 0.5;
     // let color = vec3(in.tex_coord, 0.0);
 
-    let p = in.tex_coord * 200.0;
+    let p = in.tex_coord * fur.density;
     let grid_cell = floor(p);
     let noise = rand(grid_cell);
     if noise < in.height_factor {
@@ -59,13 +232,53 @@ fn shade_fur(in: VsOut) -> @location(0) vec4<f32> {
     let g = fract(p);
     let d = distance(vec2(0.5), g);
 
-    if d < 0.0 {
+    if d > fur.thickness {
         discard;
     }
 
-    let color = vec3(1.0 - d) * (in.world_normal * 0.5 + 0.5);
+    let tint = mix(fur.root_color.rgb, fur.tip_color.rgb, in.height_factor);
+    let lit = max(dot(normal, -light.direction.xyz), 0.0) * shadow(in.world_position);
+    let diffuse = lit * light.color.rgb;
+    let color = base_color * tint * vec3(1.0 - d) * (diffuse + light.ambient.rgb);
+
+    return color * in.height_factor;
+}
+
+// How much of the light reaches `position`, averaged over a 3x3 block of
+// shadow map texels to soften the edges.
+fn shadow(position: vec3<f32>) -> f32 {
+    let clip = light_camera.view_proj * vec4(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    // Anything the light's camera can't see isn't shadowed
+    if any(abs(ndc.xy) > vec2(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let uv = ndc.xy * vec2(0.5, -0.5) + 0.5;
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+
+    // The Level variant doesn't need uniform control flow
+    var total = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y)) * texel;
+            total += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, ndc.z);
+        }
+    }
+    return total / 9.0;
+}
+
+fn perturb_normal(in: VsOut) -> vec3<f32> {
+    let n = normalize(in.world_normal);
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coord).xyz * 2.0 - 1.0;
+
+    // Vertices without usable UVs get a zero tangent
+    if dot(in.world_tangent.xyz, in.world_tangent.xyz) < 0.0001 {
+        return n;
+    }
 
-    return vec4(color * in.height_factor, 1.0);
+    let t = normalize(in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz));
+    let b = cross(n, t) * in.world_tangent.w;
+    return normalize(mat3x3(t, b, n) * tangent_normal);
 }
 
 fn rand(co: vec2<f32>) -> f32 {
@@ -0,0 +1,171 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::resources::{
+    buffer::CpuBuffer,
+    camera::{CameraBinder, CameraBinding},
+};
+
+use super::{debug::DebugVertex, render_format::RenderFormat};
+
+/// Every nth line is drawn brighter.
+const MAJOR_EVERY: i32 = 5;
+
+const MINOR_COLOR: glam::Vec3 = glam::vec3(0.2, 0.2, 0.2);
+const MAJOR_COLOR: glam::Vec3 = glam::vec3(0.45, 0.45, 0.45);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GridData {
+    fade_start: f32,
+    fade_end: f32,
+    _padding: [f32; 2],
+}
+
+pub struct GridPipeline {
+    draw: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: CpuBuffer<DebugVertex>,
+    spacing: f32,
+    extent: f32,
+}
+
+impl GridPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: &RenderFormat,
+        camera_binder: &CameraBinder,
+        spacing: f32,
+        extent: f32,
+    ) -> Self {
+        let data_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("GridPipeline::data_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let data = GridData {
+            fade_start: extent * 0.25,
+            fade_end: extent,
+            _padding: [0.0; 2],
+        };
+        let data_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("GridPipeline::data_buffer"),
+            contents: bytemuck::bytes_of(&data),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GridPipeline::bind_group"),
+            layout: &data_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: data_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("grid.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_binder.layout(), &data_layout],
+            push_constant_ranges: &[],
+        });
+        // Lines fade out towards the edge
+        let format = format.color_only().alpha_blend();
+        let draw = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("grid"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "displace_vertices",
+                buffers: &[DebugVertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // Test against the model, but don't occlude anything ourselves
+            depth_stencil: Some(format.depth().state(false, true)),
+            multisample: format.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "draw",
+                targets: format.targets(),
+            }),
+            multiview: None,
+        });
+
+        // The grid never changes so we only need to build it once.
+        let steps = if spacing > 0.0 {
+            (extent / spacing) as i32
+        } else {
+            0
+        };
+        let num_vertices = (2 * steps as usize + 1) * 4 + 2;
+        let mut vertex_buffer =
+            CpuBuffer::with_capacity(device, num_vertices, wgpu::BufferUsages::VERTEX);
+        {
+            let mut batch = vertex_buffer.batch(device, queue);
+            for i in -steps..=steps {
+                let offset = i as f32 * spacing;
+                let (x_color, z_color) = if i == 0 {
+                    (glam::vec3(0.0, 0.0, 1.0), glam::vec3(1.0, 0.0, 0.0))
+                } else if i % MAJOR_EVERY == 0 {
+                    (MAJOR_COLOR, MAJOR_COLOR)
+                } else {
+                    (MINOR_COLOR, MINOR_COLOR)
+                };
+
+                // Line running along Z
+                batch.push(DebugVertex::new(glam::vec3(offset, 0.0, -extent), x_color));
+                batch.push(DebugVertex::new(glam::vec3(offset, 0.0, extent), x_color));
+
+                // Line running along X
+                batch.push(DebugVertex::new(glam::vec3(-extent, 0.0, offset), z_color));
+                batch.push(DebugVertex::new(glam::vec3(extent, 0.0, offset), z_color));
+            }
+
+            let y_color = glam::vec3(0.0, 1.0, 0.0);
+            batch.push(DebugVertex::new(glam::Vec3::ZERO, y_color));
+            batch.push(DebugVertex::new(
+                glam::vec3(0.0, spacing * MAJOR_EVERY as f32, 0.0),
+                y_color,
+            ));
+        }
+
+        Self {
+            draw,
+            bind_group,
+            vertex_buffer,
+            spacing,
+            extent,
+        }
+    }
+
+    pub fn draw<'a: 'b, 'b>(
+        &'a self,
+        pass: &'b mut wgpu::RenderPass<'a>,
+        camera: &'a CameraBinding,
+    ) {
+        pass.set_pipeline(&self.draw);
+        pass.set_bind_group(0, camera.bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice());
+        pass.draw(0..self.vertex_buffer.len(), 0..1);
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    pub fn extent(&self) -> f32 {
+        self.extent
+    }
+}
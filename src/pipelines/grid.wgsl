@@ -0,0 +1,46 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+struct Grid {
+    fade_start: f32,
+    fade_end: f32,
+}
+
+struct DebugVertex {
+    @location(0)
+    position: vec3<f32>,
+    @location(1)
+    color: vec3<f32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> camera: Camera;
+
+@group(1)
+@binding(0)
+var<uniform> grid: Grid;
+
+struct VsOut {
+    @location(0)
+    color: vec3<f32>,
+    @location(1)
+    view_depth: f32,
+    @builtin(position)
+    frag_position: vec4<f32>,
+}
+
+@vertex
+fn displace_vertices(vertex: DebugVertex) -> VsOut {
+    let frag_position = camera.view_proj * vec4(vertex.position, 1.0);
+    // For a perspective projection w is the distance along the view axis
+    return VsOut(vertex.color, frag_position.w, frag_position);
+}
+
+@fragment
+fn draw(vs: VsOut) -> @location(0) vec4<f32> {
+    // Thin lines alias badly in the distance, so fade them out
+    let fade = 1.0 - smoothstep(grid.fade_start, grid.fade_end, vs.view_depth);
+    return vec4(vs.color, fade);
+}
@@ -0,0 +1,62 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use pollster::FutureExt;
+
+use crate::resources::load_text;
+
+/// Watches a shader file on disk so pipelines can be rebuilt while the
+/// game is running.
+pub struct ShaderWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ShaderWatcher {
+    /// `path` is relative to the crate root, so this works no matter where
+    /// the game is run from.
+    pub fn new(path: &str) -> Self {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path);
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    /// The new source if the file changed since the last call, or always
+    /// with `force`.
+    pub fn poll(&mut self, force: bool) -> Option<String> {
+        let modified = modified(&self.path);
+        if !force && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        match load_text(&self.path.to_string_lossy()).block_on() {
+            Ok(source) => Some(source),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn create_shader(device: &wgpu::Device, label: &str, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// Runs `create` and turns any validation errors it causes into an `Err`
+/// instead of the default handler's panic.
+pub fn validated<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> anyhow::Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match device.pop_error_scope().block_on() {
+        Some(e) => anyhow::bail!("{}", e),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,26 @@
+@group(0)
+@binding(0)
+var t_source: texture_2d<f32>;
+@group(0)
+@binding(1)
+var s_source: sampler;
+
+struct VsOut {
+    @location(0)
+    tex_coord: vec2<f32>,
+    @builtin(position)
+    frag_position: vec4<f32>,
+}
+
+@vertex
+fn fullscreen(@builtin(vertex_index) id: u32) -> VsOut {
+    let uv = vec2(f32((id << 1u) & 2u), f32(id & 2u));
+    return VsOut(vec2(uv.x, 1.0 - uv.y), vec4(uv * 2.0 - 1.0, 0.0, 1.0));
+}
+
+// Each pixel lands between 4 source pixels, so linear filtering
+// averages them for us.
+@fragment
+fn downsample(vs: VsOut) -> @location(0) vec4<f32> {
+    return textureSample(t_source, s_source, vs.tex_coord);
+}
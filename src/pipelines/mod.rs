@@ -1,2 +1,11 @@
 pub mod fur;
-pub mod debug;
\ No newline at end of file
+pub mod debug;
+pub mod depth;
+pub mod grid;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod mipmap;
+pub mod post_process;
+pub mod render_format;
+pub mod shadow;
+pub mod skybox;
\ No newline at end of file
@@ -0,0 +1,188 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::resources::texture::Texture;
+
+/// How HDR values get squashed into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Tonemap {
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl Tonemap {
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Reinhard,
+            Self::Reinhard => Self::Aces,
+            Self::Aces => Self::None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PostProcessData {
+    exposure: f32,
+    tonemap: u32,
+    _padding: [u32; 2],
+}
+
+/// Draws an offscreen texture to the final target with a fullscreen
+/// triangle. This is where tonemapping and the like should go. Each input
+/// gets its own bind group from `bind_input`, so one `PostProcess` can
+/// serve several windows.
+pub struct PostProcess {
+    draw: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    exposure: f32,
+    tonemap: Tonemap,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        exposure: f32,
+        tonemap: Tonemap,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("PostProcess::layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("PostProcess::buffer"),
+            contents: bytemuck::bytes_of(&PostProcessData {
+                exposure,
+                tonemap: tonemap as u32,
+                _padding: [0; 2],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("post_process.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let draw = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("PostProcess"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "blit",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            draw,
+            layout,
+            buffer,
+            exposure,
+            tonemap,
+        }
+    }
+
+    /// Needs to be called again whenever the input texture is recreated, ie.
+    /// on resize.
+    pub fn bind_input(&self, device: &wgpu::Device, input: &Texture) -> wgpu::BindGroup {
+        Self::create_bind_group(device, &self.layout, input, &self.buffer)
+    }
+
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.exposure = exposure;
+        self.update(queue);
+    }
+
+    pub fn set_tonemap(&mut self, queue: &wgpu::Queue, tonemap: Tonemap) {
+        self.tonemap = tonemap;
+        self.update(queue);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    fn update(&self, queue: &wgpu::Queue) {
+        let data = PostProcessData {
+            exposure: self.exposure,
+            tonemap: self.tonemap as u32,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&data));
+    }
+
+    pub fn draw<'a: 'b, 'b>(
+        &'a self,
+        pass: &'b mut wgpu::RenderPass<'a>,
+        input: &'a wgpu::BindGroup,
+    ) {
+        pass.set_pipeline(&self.draw);
+        pass.set_bind_group(0, input, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        input: &Texture,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PostProcess::bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
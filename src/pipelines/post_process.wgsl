@@ -0,0 +1,56 @@
+struct Settings {
+    exposure: f32,
+    tonemap: u32,
+}
+
+@group(0)
+@binding(0)
+var t_scene: texture_2d<f32>;
+@group(0)
+@binding(1)
+var<uniform> settings: Settings;
+
+struct VsOut {
+    @builtin(position)
+    frag_position: vec4<f32>,
+}
+
+// Covers the screen with a single triangle, no vertex buffer needed.
+@vertex
+fn fullscreen(@builtin(vertex_index) id: u32) -> VsOut {
+    let uv = vec2(f32((id << 1u) & 2u), f32(id & 2u));
+    return VsOut(vec4(uv * 2.0 - 1.0, 0.0, 1.0));
+}
+
+@fragment
+fn blit(vs: VsOut) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_scene, vec2<i32>(vs.frag_position.xy), 0);
+    let color = scene.rgb * settings.exposure;
+
+    // Should match the order of the Tonemap enum
+    switch settings.tonemap {
+        case 1u: {
+            return vec4(reinhard(color), scene.a);
+        }
+        case 2u: {
+            return vec4(aces(color), scene.a);
+        }
+        default: {
+            return vec4(color, scene.a);
+        }
+    }
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3(0.0), vec3(1.0));
+}
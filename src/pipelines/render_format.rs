@@ -0,0 +1,171 @@
+use super::depth::DepthConfig;
+
+/// The attachments a pass draws into. Pipelines get their color, depth and
+/// multisample states from here, so they can't disagree with the pass or
+/// each other.
+#[derive(Debug, Clone)]
+pub struct RenderFormat {
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    depth: DepthConfig,
+    sample_count: u32,
+}
+
+impl RenderFormat {
+    /// A single opaque color target. See `alpha_blend` for anything that
+    /// fades out.
+    pub fn new(format: wgpu::TextureFormat, depth: DepthConfig, sample_count: u32) -> Self {
+        Self {
+            targets: vec![Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            depth,
+            sample_count,
+        }
+    }
+
+    /// Adds another opaque target, written to at the next `@location`.
+    pub fn with_target(mut self, format: wgpu::TextureFormat) -> Self {
+        self.targets.push(Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        }));
+        self
+    }
+
+    /// For pipelines that only output `@location(0)`. The other targets
+    /// are kept so the pipeline still fits the pass, but nothing gets
+    /// written to them.
+    pub fn color_only(&self) -> Self {
+        let mut format = self.clone();
+        for target in format.targets.iter_mut().skip(1).flatten() {
+            target.write_mask = wgpu::ColorWrites::empty();
+        }
+        format
+    }
+
+    /// Sets the same blending on every target, `None` is opaque.
+    pub fn with_blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        for target in self.targets.iter_mut().flatten() {
+            target.blend = blend;
+        }
+        self
+    }
+
+    /// Regular "over" blending using the fragment's alpha.
+    pub fn alpha_blend(self) -> Self {
+        self.with_blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+    }
+
+    pub fn is_blended(&self) -> bool {
+        self.targets
+            .iter()
+            .flatten()
+            .any(|target| target.blend.is_some())
+    }
+
+    /// Ready to hand to a `FragmentState`. The first one is the color the
+    /// scene gets post processed from.
+    pub fn targets(&self) -> &[Option<wgpu::ColorTargetState>] {
+        &self.targets
+    }
+
+    pub fn depth(&self) -> &DepthConfig {
+        &self.depth
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn multisample(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count,
+            ..Default::default()
+        }
+    }
+}
+
+/// Sets `pipeline` in a pass with attachments matching `format`, so
+/// validation fails if the pipeline was built for anything else.
+#[cfg(test)]
+pub(crate) fn check_pipeline(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline: &wgpu::RenderPipeline,
+    format: &RenderFormat,
+) {
+    use crate::resources::texture::Texture;
+
+    let sample_count = format.sample_count();
+    let colors = format
+        .targets()
+        .iter()
+        .flatten()
+        .map(|target| Texture::msaa_color_target(device, 4, 4, target.format, sample_count))
+        .collect::<Vec<_>>();
+    let color_attachments = colors
+        .iter()
+        .map(|color| {
+            Some(wgpu::RenderPassColorAttachment {
+                view: color.view(),
+                resolve_target: None,
+                ops: wgpu::Operations::default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let depth = Texture::depth_texture(device, 4, 4, sample_count);
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth.view(),
+                depth_ops: Some(wgpu::Operations::default()),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+    }
+    queue.submit([encoder.finish()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::texture::Texture;
+
+    fn format() -> RenderFormat {
+        RenderFormat::new(
+            wgpu::TextureFormat::Rgba16Float,
+            DepthConfig::new(Texture::DEPTH_FORMAT, false),
+            1,
+        )
+        .with_target(wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    #[test]
+    fn alpha_blend_blends_every_target_over() {
+        let opaque = format();
+        assert!(!opaque.is_blended());
+        for target in opaque.targets().iter().flatten() {
+            assert_eq!(target.blend, None);
+        }
+
+        let blended = format().alpha_blend();
+        assert!(blended.is_blended());
+        for target in blended.targets().iter().flatten() {
+            let blend = target.blend.unwrap();
+            assert_eq!(blend.color.src_factor, wgpu::BlendFactor::SrcAlpha);
+            assert_eq!(blend.color.dst_factor, wgpu::BlendFactor::OneMinusSrcAlpha);
+            assert_eq!(blend.color.operation, wgpu::BlendOperation::Add);
+            assert_eq!(blend, wgpu::BlendState::ALPHA_BLENDING);
+        }
+        assert!(!blended.with_blend(None).is_blended());
+    }
+}
@@ -0,0 +1,246 @@
+use super::depth::DepthConfig;
+use crate::resources::{
+    aabb::Aabb,
+    camera::{Camera, CameraBinder, CameraBinding, Projection, MIN_FOVY},
+    material::MaterialBinder,
+    model::{Model, Vertex},
+    texture::Texture,
+};
+
+/// The orthographic camera a directional light sees `aabb` through. Any
+/// `direction` works, including straight up or down, and a zero one
+/// falls back to straight down like `LightData`.
+pub fn light_camera(direction: glam::Vec3, aabb: Aabb) -> Camera {
+    let direction = direction.try_normalize().unwrap_or(glam::Vec3::NEG_Y);
+    // look_at needs a direction that isn't parallel to +y to find "up"
+    let direction = if direction.cross(glam::Vec3::Y).length_squared() < 1e-6 {
+        (direction + glam::vec3(0.001, 0.0, 0.0)).normalize()
+    } else {
+        direction
+    };
+    let center = if aabb.is_valid() {
+        aabb.center()
+    } else {
+        glam::Vec3::ZERO
+    };
+    // The orthographic extents come from the fov, a narrow one keeps them
+    // close to the box
+    let mut camera = Camera::look_at(center - direction, center, 1.0, 1.0, MIN_FOVY, 0.1, 100.0);
+    camera.set_projection(Projection::Orthographic);
+    camera.fit_to(aabb);
+    camera
+}
+
+/// Renders the model's depth as seen from the light into a shadow map, that
+/// `Fur` then compares against. Only the base mesh casts shadows, without
+/// its shells or morph targets.
+pub struct ShadowPass {
+    pipeline: wgpu::RenderPipeline,
+    instances_layout: wgpu::BindGroupLayout,
+    shadow_map: Texture,
+    /// `shadow_map` is always cleared to 1.0, and compared with
+    /// `LessEqual`.
+    compare_sampler: wgpu::Sampler,
+    light_binding: CameraBinding,
+}
+
+impl ShadowPass {
+    /// `size` is the width and height of the shadow map in texels. Only
+    /// the node transform gets used out of the material bind group.
+    pub fn new(
+        device: &wgpu::Device,
+        size: u32,
+        camera_binder: &CameraBinder,
+        material_binder: &MaterialBinder,
+    ) -> Self {
+        let instances_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ShadowPass::instances_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                camera_binder.layout(),
+                &instances_layout,
+                material_binder.layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let depth = DepthConfig::new(Texture::DEPTH_FORMAT, false);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ShadowPass"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "cast_shadow",
+                buffers: &[Vertex::LAYOUT],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // Keeps lit surfaces from shadowing themselves
+            depth_stencil: Some(wgpu::DepthStencilState {
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+                ..depth.state(true, false)
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            // Only depth gets written
+            fragment: None,
+            multiview: None,
+        });
+
+        let shadow_map = Texture::depth_texture(device, size, size, 1);
+        let compare_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ShadowPass::compare_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(depth.compare(true)),
+            ..Default::default()
+        });
+        let light = light_camera(glam::Vec3::NEG_Y, Aabb::EMPTY);
+        let light_binding = camera_binder.bind(device, &light);
+
+        Self {
+            pipeline,
+            instances_layout,
+            shadow_map,
+            compare_sampler,
+            light_binding,
+        }
+    }
+
+    /// What a bind group for `render`'s `instances` needs to look like.
+    pub fn instances_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.instances_layout
+    }
+
+    pub fn shadow_map(&self) -> &Texture {
+        &self.shadow_map
+    }
+
+    pub fn compare_sampler(&self) -> &wgpu::Sampler {
+        &self.compare_sampler
+    }
+
+    /// Holds the light's `CameraData`, so shaders can move points into
+    /// light space.
+    pub fn light_buffer(&self) -> &wgpu::Buffer {
+        self.light_binding.buffer()
+    }
+
+    /// Points the light at `aabb` from `direction`, the way the light
+    /// travels.
+    pub fn update(&mut self, queue: &wgpu::Queue, direction: glam::Vec3, aabb: Aabb) {
+        self.light_binding
+            .update(queue, &light_camera(direction, aabb));
+    }
+
+    /// Redraws the shadow map. `instances` holds the transforms of each
+    /// copy of the model, of which there are `num_instances`. Without a
+    /// model the map is just cleared, so nothing is in shadow.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        model: Option<&Model>,
+        instances: &wgpu::BindGroup,
+        num_instances: u32,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("ShadowPass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.shadow_map.view(),
+                depth_ops: Some(wgpu::Operations {
+                    store: wgpu::StoreOp::Store,
+                    load: wgpu::LoadOp::Clear(1.0),
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let Some(model) = model else {
+            return;
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, self.light_binding.bind_group(), &[]);
+        pass.set_bind_group(1, instances, &[]);
+        for prim in model.primitives() {
+            pass.set_bind_group(2, prim.material().bind_group(), &[]);
+            pass.set_vertex_buffer(0, prim.vertex_buffer().slice(..));
+            match prim.index_buffer() {
+                Some(index_buffer) => {
+                    pass.set_index_buffer(index_buffer.slice(..), prim.index_format());
+                    pass.draw_indexed(0..prim.num_indices(), 0, 0..num_instances);
+                }
+                None => pass.draw(0..prim.num_vertices(), 0..num_instances),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb() -> Aabb {
+        Aabb {
+            min: glam::vec3(-1.0, 0.0, -2.0),
+            max: glam::vec3(3.0, 2.0, 2.0),
+        }
+    }
+
+    fn corners(aabb: Aabb) -> impl Iterator<Item = glam::Vec3> {
+        (0..8).map(move |i| {
+            glam::vec3(
+                if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            )
+        })
+    }
+
+    #[test]
+    fn light_sees_the_whole_box() {
+        let direction = glam::vec3(1.0, -1.0, 0.5);
+        let camera = light_camera(direction, aabb());
+        let forward = (camera.target() - camera.eye()).normalize();
+        assert!(forward.abs_diff_eq(direction.normalize(), 1e-5));
+
+        let view_proj = camera.view_proj();
+        let center = view_proj.project_point3(aabb().center());
+        assert!(center.truncate().abs_diff_eq(glam::Vec2::ZERO, 1e-5));
+        for corner in corners(aabb()) {
+            let clip = view_proj.project_point3(corner);
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?}", clip);
+            assert!((0.0..=1.0).contains(&clip.z), "{:?}", clip);
+        }
+    }
+
+    #[test]
+    fn straight_down_and_zero_directions_work() {
+        for direction in [glam::Vec3::NEG_Y, glam::Vec3::Y, glam::Vec3::ZERO] {
+            let view_proj = light_camera(direction, aabb()).view_proj();
+            assert!(view_proj.is_finite(), "{:?}", direction);
+            for corner in corners(aabb()) {
+                let clip = view_proj.project_point3(corner);
+                assert!(clip.abs().max_element() <= 1.0, "{:?}", clip);
+            }
+        }
+    }
+}
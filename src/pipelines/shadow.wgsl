@@ -0,0 +1,23 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> light: Camera;
+// Should match MAX_INSTANCES in fur.rs
+@group(1)
+@binding(0)
+var<uniform> instances: array<mat4x4<f32>, 256>;
+// Binding 4 of the material group, the rest only matter to the fur
+@group(2)
+@binding(4)
+var<uniform> node: mat4x4<f32>;
+
+@vertex
+fn cast_shadow(
+    @location(0) position: vec3<f32>,
+    @builtin(instance_index) id: u32,
+) -> @builtin(position) vec4<f32> {
+    return light.view_proj * instances[id] * node * vec4(position, 1.0);
+}
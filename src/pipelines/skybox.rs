@@ -0,0 +1,112 @@
+use crate::resources::{
+    camera::{CameraBinder, CameraBinding},
+    texture::Texture,
+};
+
+use super::render_format::RenderFormat;
+
+/// Draws a cubemap behind everything else. Needs to be drawn first, since
+/// it ignores depth.
+pub struct Skybox {
+    draw: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    /// `None` until a cubemap is set, which makes `draw` a no-op.
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl Skybox {
+    pub fn new(device: &wgpu::Device, format: &RenderFormat, camera_binder: &CameraBinder) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skybox::layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let format = format.color_only();
+        let shader = device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_binder.layout(), &layout],
+            push_constant_ranges: &[],
+        });
+        let draw = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // Everything else draws over the sky, whatever its depth
+            depth_stencil: Some(wgpu::DepthStencilState {
+                depth_compare: wgpu::CompareFunction::Always,
+                ..format.depth().state(false, true)
+            }),
+            multisample: format.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "draw",
+                targets: format.targets(),
+            }),
+            multiview: None,
+        });
+
+        Self {
+            draw,
+            layout,
+            bind_group: None,
+        }
+    }
+
+    /// `cubemap` needs a cube view, like the ones from
+    /// `Texture::cubemap_from_files`. `None` goes back to the clear color.
+    pub fn set_cubemap(&mut self, device: &wgpu::Device, cubemap: Option<&Texture>) {
+        self.bind_group = cubemap.map(|cubemap| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Skybox::bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(cubemap.view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(cubemap.sampler()),
+                    },
+                ],
+            })
+        });
+    }
+
+    pub fn draw<'a: 'b, 'b>(
+        &'a self,
+        pass: &'b mut wgpu::RenderPass<'a>,
+        camera: &'a CameraBinding,
+    ) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        pass.set_pipeline(&self.draw);
+        pass.set_bind_group(0, camera.bind_group(), &[]);
+        pass.set_bind_group(1, bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
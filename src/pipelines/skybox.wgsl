@@ -0,0 +1,40 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> camera: Camera;
+
+@group(1)
+@binding(0)
+var t_sky: texture_cube<f32>;
+@group(1)
+@binding(1)
+var s_sky: sampler;
+
+struct VsOut {
+    @location(0)
+    ndc: vec2<f32>,
+    @builtin(position)
+    frag_position: vec4<f32>,
+}
+
+// Covers the screen with a single triangle, no vertex buffer needed.
+@vertex
+fn fullscreen(@builtin(vertex_index) id: u32) -> VsOut {
+    let uv = vec2(f32((id << 1u) & 2u), f32(id & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    return VsOut(ndc, vec4(ndc, 0.0, 1.0));
+}
+
+// Unprojecting per pixel, since directions don't interpolate linearly
+// across the triangle.
+@fragment
+fn draw(vs: VsOut) -> @location(0) vec4<f32> {
+    let world = camera.inv_view_proj * vec4(vs.ndc, 0.5, 1.0);
+    let direction = world.xyz / world.w - camera.eye.xyz;
+    return vec4(textureSample(t_sky, s_sky, direction).rgb, 1.0);
+}
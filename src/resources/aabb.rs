@@ -0,0 +1,71 @@
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing. Growing it by a point gives a box around just
+    /// that point.
+    pub const EMPTY: Self = Self {
+        min: glam::Vec3::splat(f32::INFINITY),
+        max: glam::Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn from_points(points: &[glam::Vec3]) -> Self {
+        points.iter().fold(Self::EMPTY, |aabb, p| aabb.grow(*p))
+    }
+
+    pub fn grow(self, point: glam::Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// False for `EMPTY` and anything containing NaNs or infinities. A flat
+    /// or single point box is still valid.
+    pub fn is_valid(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite() && self.min.cmple(self.max).all()
+    }
+
+    /// Grows the box by `amount` in every direction.
+    pub fn expand(self, amount: f32) -> Self {
+        Self {
+            min: self.min - glam::Vec3::splat(amount),
+            max: self.max + glam::Vec3::splat(amount),
+        }
+    }
+
+    /// Box around this one after transforming it. Invalid boxes stay
+    /// invalid.
+    pub fn transform(self, matrix: glam::Mat4) -> Self {
+        if !self.is_valid() {
+            return self;
+        }
+        (0..8).fold(Self::EMPTY, |aabb, i| {
+            let corner = glam::vec3(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            aabb.grow(matrix.transform_point3(corner))
+        })
+    }
+
+    pub fn center(&self) -> glam::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> glam::Vec3 {
+        self.max - self.min
+    }
+}
@@ -1,11 +1,146 @@
 use std::marker::PhantomData;
 
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
+/// Anything that can be set as a bind group in a render pass.
+pub trait Bind {
+    fn bind_group(&self) -> &wgpu::BindGroup;
+}
+
+/// Layout for a single uniform buffer holding a `T` at binding 0.
+pub struct UniformBinder<T> {
+    label: &'static str,
+    layout: wgpu::BindGroupLayout,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformBinder<T> {
+    pub fn new(device: &wgpu::Device, label: &'static str, visibility: wgpu::ShaderStages) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<T>() as _),
+                },
+                count: None,
+            }],
+        });
+        Self {
+            label,
+            layout,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn bind(&self, device: &wgpu::Device, data: &T) -> Binding<T> {
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(self.label),
+            contents: bytemuck::bytes_of(data),
+            // Copying out is only for reading it back in tests
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(self.label),
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Binding {
+            bind_group,
+            buffer,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+}
+
+/// A uniform buffer and the bind group pointing at it.
 pub struct Binding<T> {
     bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
     _marker: PhantomData<T>,
 }
 
-pub trait Bind {
-    fn bind<T>(data: &T) -> Binding<T>;
+impl<T: bytemuck::Pod> Binding<T> {
+    /// Takes effect for the next submit.
+    pub fn update(&self, queue: &wgpu::Queue, data: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(data));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+impl<T> Bind for Binding<T> {
+    fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Uniform {
+        color: glam::Vec4,
+        counts: [u32; 4],
+    }
+
+    fn read_back(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        binding: &Binding<Uniform>,
+    ) -> Uniform {
+        let size = std::mem::size_of::<Uniform>() as u64;
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(binding.buffer(), 0, &staging, 0, size);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        data
+    }
+
+    #[test]
+    fn uniforms_round_trip() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let binder = UniformBinder::new(&device, "test", wgpu::ShaderStages::VERTEX);
+        let first = Uniform {
+            color: glam::vec4(0.25, 0.5, 0.75, 1.0),
+            counts: [1, 2, 3, 4],
+        };
+        let binding = binder.bind(&device, &first);
+        assert_eq!(read_back(&device, &queue, &binding), first);
+
+        let second = Uniform {
+            color: glam::Vec4::ONE,
+            counts: [5, 6, 7, 8],
+        };
+        binding.update(&queue, &second);
+        assert_eq!(read_back(&device, &queue, &binding), second);
+    }
 }
@@ -1,9 +1,17 @@
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
+/// How many clears in a row need to use under a quarter of the GPU buffer
+/// before it gets shrunk. High enough that a few quiet frames don't cause
+/// churn.
+const SHRINK_AFTER_CLEARS: u32 = 120;
+
 pub struct CpuBuffer<T: bytemuck::Pod + bytemuck::Zeroable> {
     buffer: wgpu::Buffer,
     data: Vec<T>,
     usage: wgpu::BufferUsages,
+    /// Never shrinks below what it was created with.
+    min_capacity: usize,
+    underused_clears: u32,
 }
 
 impl<T: bytemuck::Pod + bytemuck::Zeroable> CpuBuffer<T> {
@@ -24,6 +32,8 @@ impl<T: bytemuck::Pod + bytemuck::Zeroable> CpuBuffer<T> {
             buffer,
             usage,
             data: Vec::with_capacity(capacity),
+            min_capacity: capacity,
+            underused_clears: 0,
         }
     }
 
@@ -36,17 +46,114 @@ impl<T: bytemuck::Pod + bytemuck::Zeroable> CpuBuffer<T> {
     }
 
     pub fn clear(&mut self) {
+        if self.data.len() < self.capacity() / 4 {
+            self.underused_clears += 1;
+        } else {
+            self.underused_clears = 0;
+        }
         self.data.clear();
     }
-    
+
+    /// Overwrites the elements starting at `start` without changing the
+    /// length, and uploads just those.
+    ///
+    /// Panics if that would go past the end.
+    pub fn write_range(&mut self, queue: &wgpu::Queue, start: usize, values: &[T]) {
+        let end = start + values.len();
+        assert!(
+            end <= self.data.len(),
+            "write to {}..{} is out of bounds of CpuBuffer of length {}",
+            start,
+            end,
+            self.data.len(),
+        );
+        self.data[start..end].copy_from_slice(values);
+        self.upload(queue, start..end);
+    }
+
+    /// Removes the elements in `range` and shifts the rest down. Only the
+    /// shifted elements get uploaded again.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn remove_range(&mut self, queue: &wgpu::Queue, range: std::ops::Range<usize>) {
+        assert!(
+            range.start <= range.end && range.end <= self.data.len(),
+            "removing {:?} is out of bounds of CpuBuffer of length {}",
+            range,
+            self.data.len(),
+        );
+        let start = range.start;
+        self.data.drain(range);
+        self.upload(queue, start..self.data.len());
+    }
+
+    /// The GPU buffer always fits the data outside of a batch, so this never
+    /// needs to reallocate.
+    fn upload(&self, queue: &wgpu::Queue, range: std::ops::Range<usize>) {
+        if !range.is_empty() {
+            queue.write_buffer(
+                &self.buffer,
+                (range.start * std::mem::size_of::<T>()) as _,
+                bytemuck::cast_slice(&self.data[range]),
+            );
+        }
+    }
+
+    /// How many elements fit in the GPU buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer.size() as usize / std::mem::size_of::<T>()
+    }
+
+    fn should_shrink(&self) -> bool {
+        self.underused_clears >= SHRINK_AFTER_CLEARS && self.capacity() > self.min_capacity
+    }
+
+    /// Replaces the GPU buffer with one that fits `capacity` elements and
+    /// uploads everything.
+    fn reallocate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, capacity: usize) {
+        self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (std::mem::size_of::<T>() * capacity) as _,
+            usage: self.usage,
+            mapped_at_creation: false,
+        });
+        if !self.data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.data));
+        }
+        self.underused_clears = 0;
+    }
+
     pub(crate) fn slice(&self) -> wgpu::BufferSlice<'_> {
         self.buffer.slice(..)
     }
-    
-    pub(crate) fn len(&self) -> u32 {
+
+    /// A `u32` since that's what draw calls take.
+    pub fn len(&self) -> u32 {
         self.data.len() as u32
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Everything pushed since the last clear, including what hasn't been
+    /// uploaded yet.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+}
+
+impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> IntoIterator for &'a CpuBuffer<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 pub struct Batch<'a, T: bytemuck::Pod + bytemuck::Zeroable> {
@@ -79,7 +186,14 @@ impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> Batch<'a, T> {
 
 impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> Drop for Batch<'a, T> {
     fn drop(&mut self) {
-        if self.buffer.data.len() == 0 {
+        if self.buffer.should_shrink() {
+            // Leave room so the next busy frame doesn't grow it right back
+            let capacity = (self.buffer.data.len() * 2).max(self.buffer.min_capacity);
+            self.buffer.reallocate(self.device, self.queue, capacity);
+            return;
+        }
+
+        if self.buffer.data.is_empty() {
             return;
         }
 
@@ -89,7 +203,7 @@ impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> Drop for Batch<'a, T> {
                 contents: bytemuck::cast_slice(&self.buffer.data),
                 usage: self.buffer.usage,
             });
-        } else if self.buffer.data.len() > 0 {
+        } else {
             self.queue.write_buffer(
                 &self.buffer.buffer,
                 (self.start * std::mem::size_of::<T>()) as _,
@@ -98,3 +212,129 @@ impl<'a, T: bytemuck::Pod + bytemuck::Zeroable> Drop for Batch<'a, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What's actually on the GPU, not just in `data`.
+    fn read_back(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &CpuBuffer<u32>) -> Vec<u32> {
+        let size = (buffer.len() as usize * std::mem::size_of::<u32>()) as u64;
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&buffer.buffer, 0, &staging, 0, size);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        data
+    }
+
+    fn buffer(device: &wgpu::Device, queue: &wgpu::Queue, values: &[u32]) -> CpuBuffer<u32> {
+        let mut buffer = CpuBuffer::with_capacity(device, 8, wgpu::BufferUsages::COPY_SRC);
+        let mut batch = buffer.batch(device, queue);
+        for value in values {
+            batch.push(*value);
+        }
+        drop(batch);
+        buffer
+    }
+
+    #[test]
+    fn batches_append_to_the_data() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut buffer = buffer(&device, &queue, &[0, 1, 2]);
+        assert_eq!(buffer.len(), 3);
+        let mut batch = buffer.batch(&device, &queue);
+        batch.push(3);
+        batch.push(4);
+        drop(batch);
+
+        assert_eq!(buffer.data(), [0, 1, 2, 3, 4]);
+        assert_eq!((&buffer).into_iter().sum::<u32>(), 10);
+        assert_eq!(read_back(&device, &queue, &buffer), [0, 1, 2, 3, 4]);
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert!(buffer.data().is_empty());
+    }
+
+    #[test]
+    fn write_range_overwrites_in_place() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut buffer = buffer(&device, &queue, &[0, 1, 2, 3, 4]);
+        buffer.write_range(&queue, 1, &[10, 20]);
+        assert_eq!(buffer.data(), [0, 10, 20, 3, 4]);
+        assert_eq!(read_back(&device, &queue, &buffer), [0, 10, 20, 3, 4]);
+    }
+
+    #[test]
+    fn remove_range_shifts_the_rest_down() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let mut buffer = buffer(&device, &queue, &[0, 1, 2, 3, 4]);
+        buffer.remove_range(&queue, 1..3);
+        assert_eq!(buffer.data(), [0, 3, 4]);
+        assert_eq!(read_back(&device, &queue, &buffer), [0, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn write_range_past_the_end_panics() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            panic!("out of bounds, but there's no GPU to check");
+        };
+        let mut buffer = buffer(&device, &queue, &[0, 1]);
+        buffer.write_range(&queue, 1, &[10, 20]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn remove_range_past_the_end_panics() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            panic!("out of bounds, but there's no GPU to check");
+        };
+        let mut buffer = buffer(&device, &queue, &[0, 1]);
+        buffer.remove_range(&queue, 1..3);
+    }
+
+    #[test]
+    fn underused_buffers_shrink_and_keep_their_data() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let values = (0..100).collect::<Vec<_>>();
+        let mut buffer = buffer(&device, &queue, &values);
+        assert_eq!(buffer.capacity(), 100);
+
+        // The first clear still has all 100 in it, so doesn't count
+        for _ in 0..SHRINK_AFTER_CLEARS {
+            buffer.clear();
+            buffer.batch(&device, &queue).push(1);
+            assert_eq!(buffer.capacity(), 100);
+        }
+        buffer.clear();
+        let mut batch = buffer.batch(&device, &queue);
+        for value in [7, 8, 9] {
+            batch.push(value);
+        }
+        drop(batch);
+
+        // Back down to what it was created with
+        assert_eq!(buffer.capacity(), 8);
+        assert_eq!(buffer.data(), [7, 8, 9]);
+        assert_eq!(read_back(&device, &queue, &buffer), [7, 8, 9]);
+    }
+}
@@ -1,94 +1,154 @@
 use std::f32::consts::PI;
 
-use bytemuck::bytes_of;
 use glam::Vec3Swizzles;
-use wgpu::util::{DeviceExt, BufferInitDescriptor};
 
-const MAX_PITCH: f32 = PI - 0.01;
-const MIN_PITCH: f32 = -MAX_PITCH;
+use super::{
+    aabb::Aabb,
+    bindings::{Bind, Binding, UniformBinder},
+    frustum::Frustum,
+};
+
+/// How far fly mode can look up or down, just short of straight up so the
+/// view never flips over.
+pub const MAX_PITCH: f32 = PI * 0.5 - 0.01;
+pub const MIN_PITCH: f32 = -MAX_PITCH;
+
+/// Orbiting straight over the top would flip the view.
+const MAX_ELEVATION: f32 = PI * 0.5 - 0.01;
+const MIN_ORBIT_RADIUS: f32 = 0.01;
+
+pub const MIN_FOVY: f32 = 20.0 * PI / 180.0;
+pub const MAX_FOVY: f32 = 110.0 * PI / 180.0;
+
+/// Extra room around a model when framing it.
+const FIT_MARGIN: f32 = 1.1;
+/// Framing an empty or single point box uses this radius instead.
+const MIN_FIT_RADIUS: f32 = 0.01;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraData {
     view_proj: glam::Mat4,
+    /// Lets fullscreen passes like the skybox turn pixels into directions.
+    inv_view_proj: glam::Mat4,
+    eye: glam::Vec4,
+}
+
+impl CameraData {
+    fn new(camera: &Camera) -> Self {
+        let view_proj = camera.view_proj();
+        Self {
+            view_proj,
+            inv_view_proj: view_proj.inverse(),
+            eye: camera.eye().extend(1.0),
+        }
+    }
 }
 
 pub struct CameraBinder {
-    layout: wgpu::BindGroupLayout,
+    binder: UniformBinder<CameraData>,
 }
 
 impl CameraBinder {
     pub fn new(device: &wgpu::Device) -> Self {
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("CameraBinder"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-        });
-        Self { layout }
+        let binder =
+            UniformBinder::new(device, "CameraBinder", wgpu::ShaderStages::VERTEX_FRAGMENT);
+        Self { binder }
     }
 
     pub fn bind(&self, device: &wgpu::Device, camera: &Camera) -> CameraBinding {
-        let view_proj = camera.calc_proj() * camera.calc_view();
-        let data = CameraData { view_proj };
-        let buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("CameraBinding::buffer"),
-            contents: bytemuck::bytes_of(&data),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("CameraBinding::bind_group"),
-            layout: &self.layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
-        });
-        CameraBinding { bind_group, buffer, data }
+        let data = CameraData::new(camera);
+        CameraBinding {
+            binding: self.binder.bind(device, &data),
+        }
     }
 
     pub fn layout(&self) -> &wgpu::BindGroupLayout {
-        &self.layout
+        self.binder.layout()
     }
 }
 
 pub struct CameraBinding {
-    bind_group: wgpu::BindGroup,
-    buffer: wgpu::Buffer,
-    data: CameraData,
+    binding: Binding<CameraData>,
 }
 
 impl CameraBinding {
     pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
-        self.data.view_proj = camera.calc_proj() * camera.calc_view();
-        queue.write_buffer(&self.buffer, 0, bytes_of(&self.data));
+        let data = CameraData::new(camera);
+        self.binding.update(queue, &data);
     }
 
     pub fn bind_group(&self) -> &wgpu::BindGroup {
-        &self.bind_group
+        self.binding.bind_group()
     }
 
     pub fn buffer(&self) -> &wgpu::Buffer {
-        &self.buffer
+        self.binding.buffer()
+    }
+}
+
+/// Point on a sphere around the origin. An azimuth of 0 points down +x
+/// and increases towards +z, matching how yaw works.
+pub fn spherical_to_cartesian(radius: f32, azimuth: f32, elevation: f32) -> glam::Vec3 {
+    let (saz, caz) = azimuth.sin_cos();
+    let (sel, cel) = elevation.sin_cos();
+    glam::vec3(caz * cel, sel, saz * cel) * radius
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CameraMode {
+    /// First person, mouse looks around from the eye.
+    #[default]
+    Fly,
+    /// Mouse moves the eye around the target.
+    Orbit,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Fly => Self::Orbit,
+            Self::Orbit => Self::Fly,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Sized to match what perspective shows at the target, so switching
+    /// keeps the model the same size on screen.
+    Orthographic,
+}
+
+impl Projection {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Perspective => Self::Orthographic,
+            Self::Orthographic => Self::Perspective,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Camera {
+    mode: CameraMode,
+    projection: Projection,
+    /// Distance from the eye to the target. Kept up to date in fly mode too
+    /// so switching to orbit doesn't jump.
+    orbit_radius: f32,
     aspect: f32,
     fovy: f32,
     near: f32,
     far: f32,
+    reverse_z: bool,
     eye: glam::Vec3,
     yaw: f32,
     pitch: f32,
+    /// Radians around `forward`, positive tilts the view to the right. Only
+    /// applied in `view_matrix`, so moving around stays level.
+    roll: f32,
     forward: glam::Vec3,
     right: glam::Vec3,
     up: glam::Vec3,
@@ -100,13 +160,18 @@ impl Camera {
         let right = forward.cross(glam::Vec3::Y);
         let up = right.cross(forward);
         // let up = glam::Vec3::Y;
-        let pitch = (-forward.y).asin();
+        // Needs to match how rotate_up rebuilds forward
+        let pitch = forward.y.asin();
         let yaw = forward.z.atan2(forward.x);
 
         Self {
+            mode: CameraMode::Fly,
+            projection: Projection::Perspective,
+            orbit_radius: (position - eye).length().max(MIN_ORBIT_RADIUS),
             eye,
             yaw,
             pitch,
+            roll: 0.0,
             forward,
             right,
             up,
@@ -114,6 +179,7 @@ impl Camera {
             fovy,
             near,
             far,
+            reverse_z: false,
         }
     }
 
@@ -128,17 +194,29 @@ impl Camera {
         let cpitch = self.pitch.cos();
         self.forward.x = cyaw * cpitch;
         self.forward.z = syaw * cpitch;
-
-        // I could figure out how to do this with sin/cos
-        // but I don't feel like it.
-        self.right = self.forward.cross(glam::Vec3::Y);
-        // self.up = self.forward.cross(self.right);
+        self.update_basis();
     }
 
+    /// Stops at `MAX_PITCH` and `MIN_PITCH`. Orbit mode goes through
+    /// `orbit` instead, which has its own limit.
     pub fn rotate_up(&mut self, amount: f32) {
-        self.pitch += amount;
-        self.pitch = self.pitch.min(MAX_PITCH).max(MIN_PITCH);
-        self.forward.y = self.pitch.sin();
+        self.pitch = (self.pitch + amount).clamp(MIN_PITCH, MAX_PITCH);
+        let (syaw, cyaw) = self.yaw.sin_cos();
+        let (spitch, cpitch) = self.pitch.sin_cos();
+        self.forward = glam::vec3(cyaw * cpitch, spitch, syaw * cpitch);
+        self.update_basis();
+    }
+
+    pub fn rotate_roll(&mut self, amount: f32) {
+        self.roll = (self.roll + amount) % std::f32::consts::TAU;
+    }
+
+    /// Takes out any pitch and roll, keeping the position and the
+    /// direction the camera faces along the ground.
+    pub fn level(&mut self) {
+        self.roll = 0.0;
+        self.pitch = 0.0;
+        self.set_forward(glam::vec3(self.yaw.cos(), 0.0, self.yaw.sin()));
     }
 
     pub fn walk_forward(&mut self, amount: f32) {
@@ -157,12 +235,178 @@ impl Camera {
         self.eye.y += amount;
     }
 
-    pub fn calc_view(&self) -> glam::Mat4 {
-        glam::Mat4::look_to_rh(self.eye, self.forward, self.up)
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// The target is always `orbit_radius` in front of the eye, so nothing
+    /// needs to move when switching.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+    }
+
+    /// Moves the eye around the target, which stays put.
+    pub fn orbit(&mut self, azimuth: f32, elevation: f32) {
+        let target = self.target();
+        let offset = self.eye - target;
+        let azimuth = offset.z.atan2(offset.x) + azimuth;
+        let elevation =
+            ((offset.y / offset.length()).asin() + elevation).clamp(-MAX_ELEVATION, MAX_ELEVATION);
+
+        self.eye = target + spherical_to_cartesian(self.orbit_radius, azimuth, elevation);
+        self.set_forward(target - self.eye);
+    }
+
+    /// Moves the eye and target together across the screen. Amounts are
+    /// scaled by the orbit radius so panning feels the same at any distance.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let movement =
+            (self.right.normalize() * right + self.up.normalize() * up) * self.orbit_radius;
+        self.eye += movement;
+    }
+
+    /// Moves the eye towards or away from the target.
+    pub fn set_orbit_radius(&mut self, radius: f32) {
+        let target = self.target();
+        self.orbit_radius = radius.max(MIN_ORBIT_RADIUS);
+        self.eye = target - self.forward.normalize() * self.orbit_radius;
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn orbit_radius(&self) -> f32 {
+        self.orbit_radius
+    }
+
+    fn set_forward(&mut self, forward: glam::Vec3) {
+        self.forward = forward.normalize();
+        self.update_basis();
+        self.pitch = self.forward.y.asin();
+        self.yaw = self.forward.z.atan2(self.forward.x);
+    }
+
+    /// Keeps `right` and `up` square with `forward`, which `view_matrix`
+    /// rolls around.
+    fn update_basis(&mut self) {
+        self.right = self.forward.cross(glam::Vec3::Y);
+        self.up = self.right.cross(self.forward);
+    }
+
+    pub fn view_matrix(&self) -> glam::Mat4 {
+        let forward = self.forward.normalize();
+        let up = glam::Quat::from_axis_angle(forward, -self.roll) * self.up;
+        glam::Mat4::look_to_rh(self.eye, forward, up)
+    }
+
+    pub fn proj_matrix(&self) -> glam::Mat4 {
+        // Swapping the planes maps near to 1.0 and far to 0.0
+        let (near, far) = if self.reverse_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+        match self.projection {
+            Projection::Perspective => {
+                glam::Mat4::perspective_rh(self.fovy, self.aspect, near, far)
+            }
+            Projection::Orthographic => {
+                // Derived from the aspect every time, so resizing keeps
+                // the extents in proportion
+                let half_height = self.orbit_radius * (self.fovy * 0.5).tan();
+                let half_width = half_height * self.aspect;
+                glam::Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+
+    /// What `CameraBinding` uploads, maps world space to clip space.
+    pub fn view_proj(&self) -> glam::Mat4 {
+        self.proj_matrix() * self.view_matrix()
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(self.view_proj())
+    }
+
+    /// Moves the camera back along its current view direction until the
+    /// box fits on screen, and pulls the clip planes in around it.
+    pub fn fit_to(&mut self, aabb: Aabb) {
+        let (center, radius) = if aabb.is_valid() {
+            (
+                aabb.center(),
+                (aabb.size().length() * 0.5).max(MIN_FIT_RADIUS),
+            )
+        } else {
+            (glam::Vec3::ZERO, MIN_FIT_RADIUS)
+        };
+        let radius = radius * FIT_MARGIN;
+
+        // Fit the bounding sphere in whichever fov is narrower
+        let fovx = 2.0 * ((self.fovy * 0.5).tan() * self.aspect).atan();
+        let half_fov = self.fovy.min(fovx) * 0.5;
+        let distance = radius / half_fov.sin();
+
+        self.eye = center - self.forward.normalize() * distance;
+        self.orbit_radius = distance;
+        // The near plane can't be 0, so keep a sane ratio to the far plane
+        self.far = distance + radius;
+        self.near = (distance - radius).max(self.far * 0.0001);
+    }
+
+    /// Same as `fit_to`, for a box given by its corners.
+    pub fn frame_aabb(&mut self, min: glam::Vec3, max: glam::Vec3) {
+        self.fit_to(Aabb { min, max });
+    }
+
+    /// Vertical field of view in radians, clamped between `MIN_FOVY` and
+    /// `MAX_FOVY`.
+    pub fn set_fovy(&mut self, fovy: f32) {
+        self.fovy = fovy.clamp(MIN_FOVY, MAX_FOVY);
     }
 
-    pub fn calc_proj(&self) -> glam::Mat4 {
-        glam::Mat4::perspective_rh(self.fovy, self.aspect, self.near, self.far)
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    /// Width over height.
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// Needs to match the `DepthConfig` the pipelines were built with.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+    }
+
+    pub fn eye(&self) -> glam::Vec3 {
+        self.eye
+    }
+
+    /// The point orbit mode rotates around, `orbit_radius` in front of the
+    /// camera.
+    pub fn target(&self) -> glam::Vec3 {
+        self.eye + self.forward.normalize() * self.orbit_radius
     }
 
     pub fn yaw(&self) -> f32 {
@@ -172,4 +416,148 @@ impl Camera {
     pub fn pitch(&self) -> f32 {
         self.pitch
     }
+
+    pub fn roll(&self) -> f32 {
+        self.roll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_stops_short_of_vertical() {
+        let mut camera = Camera::look_at(
+            glam::Vec3::ZERO,
+            glam::Vec3::NEG_Z,
+            1.0,
+            1.0,
+            1.0,
+            0.1,
+            100.0,
+        );
+        camera.rotate_up(10.0);
+        assert_eq!(camera.pitch(), MAX_PITCH);
+        // Still facing the same way along the ground, so the view can't flip
+        let forward = (camera.target() - camera.eye()).normalize();
+        assert!(forward.y < 1.0);
+        assert!(forward.z < 0.0 && forward.x.abs() < 1e-6);
+        assert!(camera.view_matrix().is_finite());
+
+        camera.rotate_up(-20.0);
+        assert_eq!(camera.pitch(), MIN_PITCH);
+    }
+
+    #[test]
+    fn framing_distance_scales_with_the_box() {
+        let mut camera =
+            Camera::look_at(glam::Vec3::Z, glam::Vec3::ZERO, 1.0, 1.0, 1.0, 0.1, 100.0);
+        camera.frame_aabb(glam::Vec3::splat(-1.0), glam::Vec3::ONE);
+        let distance = camera.eye().length();
+        camera.frame_aabb(glam::Vec3::splat(-3.0), glam::Vec3::splat(3.0));
+        assert!((camera.eye().length() - distance * 3.0).abs() < 1e-4);
+        // Still looking at the box from the same side
+        assert!(camera.eye().normalize().abs_diff_eq(glam::Vec3::Z, 1e-6));
+    }
+
+    #[test]
+    fn up_follows_rotation_and_levels_out() {
+        let mut camera = Camera::look_at(
+            glam::Vec3::ZERO,
+            glam::Vec3::NEG_Z,
+            1.0,
+            1.0,
+            1.0,
+            0.1,
+            100.0,
+        );
+        camera.rotate_up(0.5);
+        camera.rotate_right(1.0);
+        camera.rotate_roll(0.3);
+        assert!(camera.up.dot(camera.forward).abs() < 1e-6);
+        assert!(camera.right.dot(camera.up).abs() < 1e-6);
+
+        camera.level();
+        assert_eq!(camera.roll(), 0.0);
+        assert!(camera.up.normalize().abs_diff_eq(glam::Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn fovy_stays_in_range() {
+        let mut camera = Camera::look_at(
+            glam::Vec3::ZERO,
+            glam::Vec3::NEG_Z,
+            1.0,
+            1.0,
+            1.0,
+            0.1,
+            100.0,
+        );
+        camera.set_fovy(0.0);
+        assert_eq!(camera.fovy(), MIN_FOVY);
+        camera.set_fovy(PI);
+        assert_eq!(camera.fovy(), MAX_FOVY);
+        camera.set_fovy(1.0);
+        assert_eq!(camera.fovy(), 1.0);
+    }
+
+    #[test]
+    fn spherical_axes_line_up() {
+        let cases = [
+            (0.0, 0.0, glam::Vec3::X),
+            (PI * 0.5, 0.0, glam::Vec3::Z),
+            (PI, 0.0, glam::Vec3::NEG_X),
+            (0.0, PI * 0.5, glam::Vec3::Y),
+            // Azimuth doesn't matter straight down
+            (1.0, -PI * 0.5, glam::Vec3::NEG_Y),
+        ];
+        for (azimuth, elevation, expected) in cases {
+            let point = spherical_to_cartesian(2.0, azimuth, elevation);
+            assert!(point.abs_diff_eq(expected * 2.0, 1e-6), "{}", point);
+        }
+    }
+
+    #[test]
+    fn target_is_in_the_middle_of_the_screen() {
+        let camera = Camera::look_at(
+            glam::vec3(3.0, 2.0, 5.0),
+            glam::vec3(-1.0, 0.5, 0.0),
+            16.0,
+            9.0,
+            1.0,
+            0.1,
+            100.0,
+        );
+        let clip = camera.view_proj().project_point3(camera.target());
+        let center = clip.truncate();
+        assert!(center.abs_diff_eq(glam::Vec2::ZERO, 1e-5), "{}", center);
+        assert!((0.0..=1.0).contains(&clip.z));
+    }
+
+    #[test]
+    fn orthographic_keeps_depth_out_of_x_and_y() {
+        let mut camera = Camera::look_at(
+            glam::Vec3::ZERO,
+            glam::Vec3::NEG_Z,
+            16.0,
+            9.0,
+            1.0,
+            0.1,
+            100.0,
+        );
+        camera.set_projection(Projection::Orthographic);
+        let view_proj = camera.view_proj();
+        let near = view_proj.project_point3(glam::vec3(0.5, 0.25, -1.0));
+        let far = view_proj.project_point3(glam::vec3(0.5, 0.25, -50.0));
+        assert!(near.truncate().abs_diff_eq(far.truncate(), 1e-6));
+        assert!(near.z < far.z);
+
+        // Perspective pulls the farther one towards the middle
+        camera.set_projection(Projection::Perspective);
+        let view_proj = camera.view_proj();
+        let near = view_proj.project_point3(glam::vec3(0.5, 0.25, -1.0));
+        let far = view_proj.project_point3(glam::vec3(0.5, 0.25, -50.0));
+        assert!(far.x < near.x);
+    }
 }
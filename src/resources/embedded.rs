@@ -0,0 +1,40 @@
+//! Files baked into the binary, so it still runs without `res/` next to
+//! it. Shaders don't need to be here, they're always compiled in.
+
+/// Keyed by the same relative path they'd be loaded from.
+const FILES: &[(&str, &[u8])] = &[("res/walking.glb", include_bytes!("../../res/walking.glb"))];
+
+fn get(path: &str) -> Option<&'static [u8]> {
+    FILES
+        .iter()
+        .find(|(file, _)| *file == path)
+        .map(|(_, bytes)| *bytes)
+}
+
+/// Files on disk always win, the embedded copy is only used if reading
+/// `path` failed.
+pub(super) fn or_embedded(path: &str, result: anyhow::Result<Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+    match (result, get(path)) {
+        (Err(_), Some(bytes)) => Ok(bytes.to_vec()),
+        (result, _) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_copy_fills_in_for_missing_files() {
+        let missing = || Err(anyhow::anyhow!("missing"));
+        let bytes = or_embedded("res/walking.glb", missing()).unwrap();
+        assert_eq!(bytes, include_bytes!("../../res/walking.glb"));
+        assert!(or_embedded("res/other.glb", missing()).is_err());
+    }
+
+    #[test]
+    fn files_on_disk_win() {
+        let bytes = or_embedded("res/walking.glb", Ok(vec![1, 2, 3])).unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,90 @@
+use super::aabb::Aabb;
+
+/// The volume a camera can see, as six planes facing inwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    /// `xyz` is the normal and `w` the distance, so a point is on the inside
+    /// when `dot(plane.xyz, p) + plane.w >= 0`.
+    planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    /// Pulls the planes out of the combined matrix (Gribb/Hartmann). Assumes
+    /// wgpu's 0 to 1 clip depth, which also holds for reversed Z since only
+    /// the meaning of the two depth planes swaps.
+    pub fn from_view_proj(view_proj: glam::Mat4) -> Self {
+        let r0 = view_proj.row(0);
+        let r1 = view_proj.row(1);
+        let r2 = view_proj.row(2);
+        let r3 = view_proj.row(3);
+
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|plane| {
+            // Normalizing isn't needed for the inside test, but makes the
+            // distances meaningful
+            plane / plane.truncate().length()
+        });
+
+        Self { planes }
+    }
+
+    /// Conservative, boxes near the corners can pass without actually being
+    /// visible. Invalid boxes are never contained.
+    pub fn contains_aabb(&self, aabb: Aabb) -> bool {
+        if !aabb.is_valid() {
+            return false;
+        }
+
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let normal = plane.truncate();
+            let corner = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// At the origin looking down -Z, 90 degrees wide.
+    fn frustum() -> Frustum {
+        let proj = glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let view = glam::Mat4::look_at_rh(glam::Vec3::ZERO, glam::Vec3::NEG_Z, glam::Vec3::Y);
+        Frustum::from_view_proj(proj * view)
+    }
+
+    fn cube(center: glam::Vec3, half_size: f32) -> Aabb {
+        Aabb {
+            min: center - half_size,
+            max: center + half_size,
+        }
+    }
+
+    #[test]
+    fn box_inside_is_contained() {
+        assert!(frustum().contains_aabb(cube(glam::vec3(0.0, 0.0, -10.0), 1.0)));
+    }
+
+    #[test]
+    fn box_outside_is_not_contained() {
+        let frustum = frustum();
+        // Behind, off to the side, and past the far plane
+        assert!(!frustum.contains_aabb(cube(glam::vec3(0.0, 0.0, 10.0), 1.0)));
+        assert!(!frustum.contains_aabb(cube(glam::vec3(30.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.contains_aabb(cube(glam::vec3(0.0, 0.0, -200.0), 1.0)));
+    }
+
+    #[test]
+    fn box_straddling_a_plane_is_contained() {
+        // Crosses the right plane, which is at x = 10 this far out
+        assert!(frustum().contains_aabb(cube(glam::vec3(10.0, 0.0, -10.0), 1.0)));
+        // Crosses the near plane
+        assert!(frustum().contains_aabb(cube(glam::Vec3::ZERO, 1.0)));
+    }
+
+    #[test]
+    fn invalid_box_is_not_contained() {
+        assert!(!frustum().contains_aabb(Aabb::EMPTY));
+    }
+}
@@ -0,0 +1,172 @@
+use wgpu::util::DeviceExt;
+
+use super::texture::Texture;
+use crate::pipelines::mipmap::MipmapPipeline;
+
+pub struct MaterialBinder {
+    layout: wgpu::BindGroupLayout,
+    flat_normal: Texture,
+    white: Texture,
+    mipmaps: MipmapPipeline,
+}
+
+impl MaterialBinder {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("MaterialBinder"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // The primitive's node transform. This is the only bind
+                // group that changes per primitive, so it lives here.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // Points straight up in tangent space, so materials without a
+        // normal map shade with the interpolated vertex normal.
+        let flat_normal = Texture::from_image_data(
+            device,
+            queue,
+            Some("MaterialBinder::flat_normal"),
+            1,
+            1,
+            wgpu::TextureFormat::Rgba8Unorm,
+            &[128, 128, 255, 255],
+        );
+
+        // Untextured materials multiply by one
+        let white = Texture::from_image_data(
+            device,
+            queue,
+            Some("MaterialBinder::white"),
+            1,
+            1,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &[255, 255, 255, 255],
+        );
+
+        Self {
+            layout,
+            flat_normal,
+            white,
+            mipmaps: MipmapPipeline::new(device),
+        }
+    }
+
+    /// `transform` places the primitive's vertices in the scene, before
+    /// any instance transform.
+    pub fn bind(
+        &self,
+        device: &wgpu::Device,
+        normal: Option<Texture>,
+        base_color: Option<Texture>,
+        transform: glam::Mat4,
+    ) -> MaterialBinding {
+        let normal_map = normal.as_ref().unwrap_or(&self.flat_normal);
+        let base_color_map = base_color.as_ref().unwrap_or(&self.white);
+        let transform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("MaterialBinding::transform_buffer"),
+            contents: bytemuck::bytes_of(&transform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("MaterialBinding::bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(normal_map.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(normal_map.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(base_color_map.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(base_color_map.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: transform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        MaterialBinding {
+            bind_group,
+            base_color,
+            _transform_buffer: transform_buffer,
+        }
+    }
+
+    /// For generating mipmaps on the textures passed to `bind`.
+    pub fn mipmaps(&self) -> &MipmapPipeline {
+        &self.mipmaps
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+}
+
+#[derive(Debug)]
+pub struct MaterialBinding {
+    bind_group: wgpu::BindGroup,
+    base_color: Option<Texture>,
+    /// Only kept alive for `bind_group`.
+    _transform_buffer: wgpu::Buffer,
+}
+
+impl MaterialBinding {
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// `None` if the material isn't textured.
+    pub fn base_color(&self) -> Option<&Texture> {
+        self.base_color.as_ref()
+    }
+}
@@ -1,23 +1,124 @@
+pub mod aabb;
 pub mod camera;
+pub mod frustum;
 pub mod bindings;
 pub mod model;
 pub mod texture;
 pub mod buffer;
+pub mod material;
+#[cfg(feature = "embed-assets")]
+mod embedded;
+#[cfg(target_arch = "wasm32")]
+mod web;
 
+/// Finds where a relative path lives, so the binary can be run from any
+/// folder. `ASSET_DIR` is tried first if it's set, then the executable's
+/// folder, then the working directory. Paths that don't exist anywhere
+/// stay relative to the working directory, which is where new files get
+/// saved.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn resolve_path(path: &str) -> String {
+    let asset_dir = std::env::var_os("ASSET_DIR").map(std::path::PathBuf::from);
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()));
+    resolve_in(path, asset_dir.into_iter().chain(exe_dir))
+}
+
+/// The first of `dirs` that has `path` in it, in order.
+#[cfg(not(target_arch = "wasm32"))]
+fn resolve_in(path: &str, dirs: impl IntoIterator<Item = std::path::PathBuf>) -> String {
+    let relative = std::path::Path::new(path);
+    if relative.is_absolute() {
+        return path.to_string();
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(relative))
+        .find(|candidate| candidate.exists())
+        .map_or_else(
+            || path.to_string(),
+            |found| found.to_string_lossy().into_owned(),
+        )
+}
+
+/// Everything's fetched relative to the page, so there's nothing to
+/// resolve.
+#[cfg(target_arch = "wasm32")]
+pub fn resolve_path(path: &str) -> String {
+    path.to_string()
+}
+
+/// Writes to a temporary file first and renames it over `path`, so a crash
+/// mid-write never leaves a truncated file behind.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn save_text(path: &str, contents: &str) -> anyhow::Result<()> {
+    let path = &resolve_path(path);
+    // Needs to be on the same filesystem for the rename to be atomic
+    let temp_path = format!("{}.tmp", path);
+    async_fs::write(&temp_path, contents).await?;
+    // Windows won't flush a read only handle
+    async_fs::OpenOptions::new()
+        .write(true)
+        .open(&temp_path)
+        .await?
+        .sync_all()
+        .await?;
+    async_fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+/// Saves to local storage in the browser, where `load_text` looks before
+/// fetching `path`.
+#[cfg(target_arch = "wasm32")]
 pub async fn save_text(path: &str, contents: &str) -> anyhow::Result<()> {
-    Ok(async_fs::write(path, contents).await?)
+    web::store(path, contents)
 }
 
+/// Keeps the previous file as a backup that `load_json` can fall back on.
 pub async fn save_json(path: &str, contents: impl serde::Serialize) -> anyhow::Result<()> {
     let text = serde_json::to_string_pretty(&contents)?;
+    // So the backup ends up next to the file it's a backup of
+    let path = &resolve_path(path);
+    // Local storage writes can't be left half done, so the browser doesn't
+    // need one
+    #[cfg(not(target_arch = "wasm32"))]
+    if async_fs::metadata(path).await.is_ok() {
+        async_fs::copy(path, backup_path(path)).await?;
+    }
     save_text(path, &text).await
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 pub async fn load_text(path: &str) -> anyhow::Result<String> {
-    Ok(async_fs::read_to_string(path).await?)
+    Ok(async_fs::read_to_string(resolve_path(path)).await?)
+}
+
+/// Anything saved with `save_text` wins over the server's copy.
+#[cfg(target_arch = "wasm32")]
+pub async fn load_text(path: &str) -> anyhow::Result<String> {
+    match web::stored(path)? {
+        Some(text) => Ok(text),
+        None => web::fetch_text(path).await,
+    }
 }
 
 pub async fn load_json<T>(path: &str) -> anyhow::Result<T>
+where
+    T: for<'a> serde::Deserialize<'a>,
+{
+    let data = match load_json_file(path).await {
+        Ok(data) => data,
+        Err(e) => {
+            let backup = backup_path(path);
+            let data = load_json_file(&backup).await.map_err(|_| e)?;
+            eprintln!("Unable to load {}, using {} instead", path, backup);
+            data
+        }
+    };
+    Ok(data)
+}
+
+async fn load_json_file<T>(path: &str) -> anyhow::Result<T>
 where
     T: for<'a> serde::Deserialize<'a>,
 {
@@ -26,6 +127,115 @@ where
     Ok(data)
 }
 
+fn backup_path(path: &str) -> String {
+    format!("{}.bak", path)
+}
+
+/// With the `embed-assets` feature, the binary's own copy gets used if
+/// there's no file at `path`.
 pub async fn load_binary(path: &str) -> anyhow::Result<Vec<u8>> {
-    Ok(async_fs::read(path).await?)
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = async_fs::read(resolve_path(path))
+        .await
+        .map_err(anyhow::Error::from);
+    // Fetched relative to the page
+    #[cfg(target_arch = "wasm32")]
+    let result = web::fetch_binary(path).await;
+    #[cfg(feature = "embed-assets")]
+    let result = embedded::or_embedded(path, result);
+    result
+}
+
+/// A fresh folder per test, so they can run in parallel.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("heightmap-navmesh-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// The web versions need a browser to fetch from
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use pollster::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn partial_write_falls_back_to_backup() {
+        let dir = temp_dir("backup");
+        let path = dir.join("config.json");
+        let path = path.to_str().unwrap();
+
+        save_json(path, serde_json::json!({ "speed": 1 }))
+            .block_on()
+            .unwrap();
+        save_json(path, serde_json::json!({ "speed": 2 }))
+            .block_on()
+            .unwrap();
+        // What a crash halfway through writing in place would leave behind
+        std::fs::write(path, "{ \"spe").unwrap();
+
+        let config: serde_json::Value = load_json(path).block_on().unwrap();
+        assert_eq!(config, serde_json::json!({ "speed": 1 }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_leaves_no_temp_file() {
+        let dir = temp_dir("temp");
+        let path = dir.join("config.json");
+        let path = path.to_str().unwrap();
+
+        save_text(path, "saved").block_on().unwrap();
+        assert_eq!(load_text(path).block_on().unwrap(), "saved");
+        assert!(!dir.join("config.json.tmp").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loads_text_binary_and_json() {
+        let dir = temp_dir("load");
+        std::fs::write(dir.join("a.txt"), "text").unwrap();
+        std::fs::write(dir.join("a.bin"), [0, 1, 255]).unwrap();
+        std::fs::write(dir.join("a.json"), "[1, 2]").unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        assert_eq!(load_text(&path("a.txt")).block_on().unwrap(), "text");
+        assert_eq!(load_binary(&path("a.bin")).block_on().unwrap(), [0, 1, 255]);
+        let numbers: Vec<u32> = load_json(&path("a.json")).block_on().unwrap();
+        assert_eq!(numbers, [1, 2]);
+        assert!(load_binary(&path("missing.bin")).block_on().is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_resolve_to_the_first_folder_that_has_them() {
+        let dir = temp_dir("resolve");
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        for folder in [&first, &second] {
+            std::fs::create_dir_all(folder.join("res")).unwrap();
+        }
+        std::fs::write(first.join("res/a.txt"), "").unwrap();
+        std::fs::write(second.join("res/a.txt"), "").unwrap();
+        std::fs::write(second.join("res/b.txt"), "").unwrap();
+        let dirs = || [first.clone(), second.clone()];
+        let expected = |dir: &std::path::Path, name| dir.join(name).to_str().unwrap().to_string();
+
+        assert_eq!(
+            resolve_in("res/a.txt", dirs()),
+            expected(&first, "res/a.txt")
+        );
+        assert_eq!(
+            resolve_in("res/b.txt", dirs()),
+            expected(&second, "res/b.txt")
+        );
+        // Missing files stay relative to the working directory
+        assert_eq!(resolve_in("res/c.txt", dirs()), "res/c.txt");
+        let absolute = expected(&second, "res/a.txt");
+        assert_eq!(resolve_in(&absolute, [first.clone()]), absolute);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
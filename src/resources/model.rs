@@ -1,10 +1,17 @@
-use std::mem::size_of;
+use std::{borrow::Cow, collections::HashSet, mem::size_of};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use bytemuck::{cast_slice, Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
-use super::load_binary;
+use super::{
+    aabb::Aabb,
+    camera::spherical_to_cartesian,
+    load_binary, load_text,
+    material::{MaterialBinder, MaterialBinding},
+    resolve_path,
+    texture::Texture,
+};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -12,6 +19,9 @@ pub struct Vertex {
     position: glam::Vec3,
     normal: glam::Vec3,
     tex_coord: glam::Vec2,
+    tangent: glam::Vec4,
+    /// `COLOR_0`, white if the mesh doesn't have one.
+    color: glam::Vec4,
 }
 
 impl Vertex {
@@ -22,13 +32,50 @@ impl Vertex {
             0 => Float32x3,
             1 => Float32x3,
             2 => Float32x2,
+            3 => Float32x4,
+            // After the optional buffers, so those don't need renumbering
+            9 => Float32x4,
+        ],
+    };
+
+    /// The tangent starts out zero, see [`compute_tangents`].
+    pub fn new(position: glam::Vec3, normal: glam::Vec3, tex_coord: glam::Vec2) -> Self {
+        Self {
+            position,
+            normal,
+            tex_coord,
+            tangent: glam::Vec4::ZERO,
+            color: glam::Vec4::ONE,
+        }
+    }
+
+    pub fn position(&self) -> glam::Vec3 {
+        self.position
+    }
+
+    pub fn normal(&self) -> glam::Vec3 {
+        self.normal
+    }
+
+    pub fn color(&self) -> glam::Vec4 {
+        self.color
+    }
+
+    /// Layout of the optional second UV set, which lives in its own buffer
+    /// so models with one set don't carry an unused attribute.
+    pub const TEX_COORD_1_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: size_of::<glam::Vec2>() as _,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![
+            4 => Float32x2,
         ],
     };
 }
 
+/// Position and normal offsets for the first two morph targets.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
-struct Morphs {
+pub struct Morphs {
     d0_position: glam::Vec3,
     d0_normal: glam::Vec3,
     d1_position: glam::Vec3,
@@ -36,14 +83,16 @@ struct Morphs {
 }
 
 impl Morphs {
+    /// Starts after `Vertex` and `TEX_COORD_1_LAYOUT`, so all three can be
+    /// bound together.
     pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
         array_stride: size_of::<Self>() as _,
         step_mode: wgpu::VertexStepMode::Vertex,
         attributes: &wgpu::vertex_attr_array![
-            0 => Float32x3,
-            1 => Float32x3,
-            2 => Float32x3,
-            4 => Float32x3,
+            5 => Float32x3,
+            6 => Float32x3,
+            7 => Float32x3,
+            8 => Float32x3,
         ],
     };
 }
@@ -57,124 +106,369 @@ pub struct Skin {
 
 pub struct Model {
     meshes: Vec<Mesh>,
+    aabb: Aabb,
+    transform: glam::Mat4,
 }
 
 impl Model {
     pub async fn load(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        material_binder: &MaterialBinder,
         path: &str,
     ) -> anyhow::Result<Self> {
+        // The files a .gltf refers to are relative to where it really is
+        let path = &resolve_path(path);
+        let is_obj = std::path::Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("obj"));
+        if is_obj {
+            return Self::load_obj(device, material_binder, path).await;
+        }
+
         let bytes = load_binary(path).await?;
-        let (document, buffers, images) = gltf::import_slice(&bytes)?;
-        Self::from_gltf(device, queue, &document, &buffers, &images)
+        let gltf = gltf::Gltf::from_slice(&bytes)?;
+        // A .gltf can reference .bin and image files relative to itself, or
+        // embed them as base64 data URIs
+        let base = std::path::Path::new(path).parent();
+        let buffers = gltf::import_buffers(&gltf.document, base, gltf.blob)
+            .with_context(|| format!("Unable to load the buffers {} refers to", path))?;
+        let images = gltf::import_images(&gltf.document, base, &buffers)
+            .with_context(|| format!("Unable to load the images {} refers to", path))?;
+        Self::from_gltf(
+            device,
+            queue,
+            material_binder,
+            &gltf.document,
+            &buffers,
+            &images,
+        )
+    }
+
+    /// Loads an OBJ as a single untextured primitive. Normals get computed
+    /// if the file doesn't have them for every face.
+    pub async fn load_obj(
+        device: &wgpu::Device,
+        material_binder: &MaterialBinder,
+        path: &str,
+    ) -> anyhow::Result<Self> {
+        let text = load_text(path).await?;
+        let (vertices, indices) =
+            obj_geometry(&text).with_context(|| format!("Unable to parse {}", path))?;
+        Self::from_vertices(device, material_binder, path, vertices, indices)
+    }
+
+    /// A flat square on the XZ plane, facing up and centered on the origin.
+    /// It's split into `subdivisions` by `subdivisions` quads, and the UVs
+    /// go from 0 to 1 across each one so tiling textures repeat per quad.
+    pub fn plane(
+        device: &wgpu::Device,
+        material_binder: &MaterialBinder,
+        size: f32,
+        subdivisions: u32,
+    ) -> anyhow::Result<Self> {
+        let (mut vertices, indices) = plane_geometry(size, subdivisions);
+        compute_tangents(&mut vertices, &indices);
+        Self::from_vertices(device, material_binder, "Plane", vertices, indices)
+    }
+
+    /// A UV sphere centered on the origin. `rings` splits it from pole to
+    /// pole and `sectors` around the equator, at least 2 and 3 of each.
+    /// U goes once around the equator and V from the top pole to the
+    /// bottom one.
+    pub fn sphere(
+        device: &wgpu::Device,
+        material_binder: &MaterialBinder,
+        radius: f32,
+        rings: u32,
+        sectors: u32,
+    ) -> anyhow::Result<Self> {
+        let (mut vertices, indices) = sphere_geometry(radius, rings, sectors);
+        compute_tangents(&mut vertices, &indices);
+        Self::from_vertices(device, material_binder, "Sphere", vertices, indices)
+    }
+
+    /// A cube centered on the origin, `size` along each edge. Each face has
+    /// its own vertices so the normals stay flat, and the whole 0 to 1 UV
+    /// range.
+    pub fn cube(
+        device: &wgpu::Device,
+        material_binder: &MaterialBinder,
+        size: f32,
+    ) -> anyhow::Result<Self> {
+        let (mut vertices, indices) = cube_geometry(size);
+        compute_tangents(&mut vertices, &indices);
+        Self::from_vertices(device, material_binder, "Cube", vertices, indices)
+    }
+
+    /// A single untextured primitive from a triangle list.
+    fn from_vertices(
+        device: &wgpu::Device,
+        material_binder: &MaterialBinder,
+        name: &str,
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+    ) -> anyhow::Result<Self> {
+        let positions = vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+        let aabb = Aabb::from_points(&positions);
+        check_buffer_size(
+            device,
+            cast_slice::<_, u8>(&vertices).len(),
+            "Vertex buffer",
+        )?;
+        check_buffer_size(device, cast_slice::<_, u8>(&indices).len(), "Index buffer")?;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", name)),
+            contents: cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", name)),
+            contents: cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let line_indices = create_line_indices(device, &indices);
+        let primitive = Primitive {
+            num_vertices: vertices.len() as u32,
+            num_indices: indices.len() as u32,
+            vertices,
+            vertex_buffer,
+            tex_coord_1_buffer: None,
+            morph_buffer: None,
+            index_buffer: Some(index_buffer),
+            index_format: wgpu::IndexFormat::Uint32,
+            line_indices,
+            material: material_binder.bind(device, None, None, glam::Mat4::IDENTITY),
+            aabb,
+            transform: glam::Mat4::IDENTITY,
+        };
+
+        Ok(Self {
+            meshes: vec![Mesh {
+                primitives: vec![primitive],
+            }],
+            aabb,
+            transform: glam::Mat4::IDENTITY,
+        })
     }
 
     pub fn from_gltf(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        material_binder: &MaterialBinder,
         document: &gltf::Document,
         buffers: &[gltf::buffer::Data],
         images: &[gltf::image::Data],
     ) -> anyhow::Result<Self> {
         let mut meshes = Vec::new();
+        let mut aabb = Aabb::EMPTY;
+        let transforms = Self::get_mesh_transforms(document);
         for mesh in document.meshes() {
+            // Only used to tell buffers apart in debuggers and errors
+            let name = mesh.name().unwrap_or("Unknown");
+            let transform = transforms[mesh.index()];
             let mut primitives = Vec::new();
             for prim in mesh.primitives() {
-                // We need to index format to render properly.
-                let indices = prim.indices().unwrap();
-                let index_format = match indices.data_type() {
-                    gltf::accessor::DataType::U16 => wgpu::IndexFormat::Uint16,
-                    gltf::accessor::DataType::U32 => wgpu::IndexFormat::Uint32,
-                    dt => bail!("Unsupported index type {:?}", dt),
-                };
-
                 // The index buffer usually doesn't have a stride,  so we can
-                // upload the data to the gpu directly.
-                let index_data = Self::get_data_for_accessor(&indices, buffers).unwrap();
-                let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Index Buffer"),
-                    contents: index_data,
-                    usage: wgpu::BufferUsages::INDEX,
-                });
-                let num_indices = indices.count() as u32;
+                // upload the data to the gpu directly. Primitives without
+                // indices draw their vertices in order.
+                let mut indices = match prim.indices() {
+                    Some(indices) => {
+                        let index_data = Self::get_data_for_accessor(&indices, buffers)
+                            .context("Index accessor is out of bounds")?;
+                        Some(match indices.data_type() {
+                            // wgpu has no 8 bit indices, so widen them
+                            gltf::accessor::DataType::U8 => (
+                                wgpu::IndexFormat::Uint16,
+                                Cow::Owned(widen_indices(&index_data)),
+                            ),
+                            gltf::accessor::DataType::U16 => {
+                                (wgpu::IndexFormat::Uint16, index_data)
+                            }
+                            gltf::accessor::DataType::U32 => {
+                                (wgpu::IndexFormat::Uint32, index_data)
+                            }
+                            dt => bail!("Unsupported index type {:?}", dt),
+                        })
+                    }
+                    None => None,
+                };
 
                 // Map each attribute to the ones we care about.
                 let mut positions = None;
                 let mut normals = None;
-                let mut tex_coords = None;
+                let mut tangents = None;
                 prim.attributes().for_each(|(s, a)| match s {
                     gltf::Semantic::Positions => positions = Some(a),
                     gltf::Semantic::Normals => normals = Some(a),
-                    gltf::Semantic::TexCoords(0) => tex_coords = Some(a),
+                    gltf::Semantic::Tangents => tangents = Some(a),
                     _ => (), // Ignore other attributes
                 });
 
-                let positions = positions.unwrap();
-                let normals = normals.unwrap();
-                let tex_coords = tex_coords.unwrap();
+                let positions =
+                    positions.context("model primitive is missing POSITION attribute")?;
 
                 // This shape-keys.glb model has vertex components separated
                 // we'll combine them so the GPU doesn't have to jump around
                 // when preparing for the vertex shader.
-                let pos_data: &[glam::Vec3] =
-                    cast_slice(Self::get_data_for_accessor(&positions, buffers).unwrap());
-                let norm_data: &[glam::Vec3] =
-                    cast_slice(Self::get_data_for_accessor(&normals, buffers).unwrap());
-                let tex_coord_data: &[glam::Vec2] =
-                    cast_slice(Self::get_data_for_accessor(&tex_coords, buffers).unwrap());
-                let vertices = (0..pos_data.len().min(norm_data.len()))
+                let pos_bytes = Self::get_data_for_accessor(&positions, buffers)
+                    .context("POSITION accessor is out of bounds")?;
+                let pos_data: &[glam::Vec3] = cast_slice(&pos_bytes);
+                let prim_aabb = Self::get_aabb_for_accessor(&positions)
+                    .unwrap_or_else(|| Aabb::from_points(pos_data));
+                aabb = aabb.union(prim_aabb.transform(transform));
+                let norm_bytes = match normals {
+                    Some(normals) => Some(
+                        Self::get_data_for_accessor(&normals, buffers)
+                            .context("NORMAL accessor is out of bounds")?,
+                    ),
+                    None => None,
+                };
+                let norm_data: Option<&[glam::Vec3]> = norm_bytes.as_deref().map(cast_slice);
+                // Untextured meshes still load, every vertex just gets a UV
+                // of zero.
+                let tex_coord_data = Self::get_tex_coords_0(&prim, buffers);
+                let color_data = Self::get_colors(&prim, buffers);
+                let num_vertices = match norm_data {
+                    Some(norm_data) => pos_data.len().min(norm_data.len()),
+                    None => pos_data.len(),
+                };
+                let mut vertices = (0..num_vertices)
                     .map(|i| Vertex {
                         position: pos_data[i],
-                        normal: norm_data[i],
-                        tex_coord: tex_coord_data[i],
+                        normal: norm_data.map(|n| n[i]).unwrap_or_default(),
+                        tex_coord: tex_coord_data.get(i).copied().unwrap_or_default(),
+                        tangent: glam::Vec4::ZERO,
+                        color: color_data.get(i).copied().unwrap_or(glam::Vec4::ONE),
                     })
                     .collect::<Vec<_>>();
+
+                let mut index_list = match &indices {
+                    Some((wgpu::IndexFormat::Uint16, data)) => cast_slice::<_, u16>(data)
+                        .iter()
+                        .map(|i| *i as u32)
+                        .collect(),
+                    Some((wgpu::IndexFormat::Uint32, data)) => cast_slice::<_, u32>(data).to_vec(),
+                    None => (0..vertices.len() as u32).collect::<Vec<_>>(),
+                };
+
+                // CAD exports often only have positions. Their faces get
+                // split apart and drawn in order, so anything else per
+                // vertex has to be split the same way.
+                let num_base_vertices = vertices.len();
+                let split = match norm_data {
+                    Some(_) => None,
+                    None => {
+                        let (flat, split) = compute_flat_normals(&vertices, &index_list);
+                        vertices = flat;
+                        index_list = (0..vertices.len() as u32).collect();
+                        indices = None;
+                        Some(split)
+                    }
+                };
+
+                // Normal mapping needs tangents. Use the exported ones if we
+                // have them, otherwise derive them from the UVs. Without UVs
+                // there's nothing to derive them from, so they stay zero and
+                // the shader falls back to the vertex normal.
+                match tangents {
+                    Some(tangents) => {
+                        if tangents.dimensions() != gltf::accessor::Dimensions::Vec4 {
+                            bail!(
+                                "TANGENT needs to be a Vec4, not {:?}",
+                                tangents.dimensions()
+                            );
+                        }
+                        let tangent_bytes = Self::get_data_for_accessor(&tangents, buffers)
+                            .context("TANGENT accessor is out of bounds")?;
+                        let tangent_data: &[glam::Vec4] = cast_slice(&tangent_bytes);
+                        vertices
+                            .iter_mut()
+                            .zip(tangent_data)
+                            .for_each(|(v, t)| v.tangent = *t);
+                    }
+                    None if !tex_coord_data.is_empty() => {
+                        compute_tangents(&mut vertices, &index_list)
+                    }
+                    None => (),
+                }
+
+                check_buffer_size(
+                    device,
+                    cast_slice::<_, u8>(&vertices).len(),
+                    "Vertex buffer",
+                )?;
+                if let Some((_, data)) = &indices {
+                    check_buffer_size(device, data.len(), "Index buffer")?;
+                }
+
+                let index_buffer = indices.as_ref().map(|(_, data)| {
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("{} Index Buffer", name)),
+                        contents: data,
+                        usage: wgpu::BufferUsages::INDEX,
+                    })
+                });
+                let index_format = indices
+                    .as_ref()
+                    .map(|(format, _)| *format)
+                    .unwrap_or(wgpu::IndexFormat::Uint32);
+                let num_indices = match indices {
+                    Some(_) => index_list.len() as u32,
+                    None => 0,
+                };
+                let num_vertices = vertices.len() as u32;
+                let line_indices = create_line_indices(device, &index_list);
+
                 let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Vertex Buffer"),
+                    label: Some(&format!("{} Vertex Buffer", name)),
                     contents: cast_slice(&vertices),
                     usage: wgpu::BufferUsages::VERTEX,
                 });
 
+                let tex_coord_1_buffer = Self::get_tex_coords_1(&prim, buffers, num_base_vertices)?
+                    .map(|tex_coords_1| {
+                        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some("TexCoord1 Buffer"),
+                            contents: cast_slice(&split_like(tex_coords_1, split.as_deref())),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        })
+                    });
+
                 // We need to do a similar thing to the morph data that we did
-                // with the vertex data.
-                let mut morphs = prim.morph_targets();
-                let m0 = morphs.next();
-                let m1 = morphs.next();
-
-                let morph_buffer = match (m0, m1) {
-                    (Some(m0), Some(m1)) => {
-                        let mp0 = m0.positions().unwrap();
-                        let mn0 = m0.normals().unwrap();
-
-                        let mp1 = m1.positions().unwrap();
-                        let mn1 = m1.normals().unwrap();
-
-                        let mp0_data: &[glam::Vec3] =
-                            cast_slice(Self::get_data_for_accessor(&mp0, buffers).unwrap());
-                        let mn0_data: &[glam::Vec3] =
-                            cast_slice(Self::get_data_for_accessor(&mn0, buffers).unwrap());
-                        let mp1_data: &[glam::Vec3] =
-                            cast_slice(Self::get_data_for_accessor(&mp1, buffers).unwrap());
-                        let mn1_data: &[glam::Vec3] =
-                            cast_slice(Self::get_data_for_accessor(&mn1, buffers).unwrap());
-                        let len = mp0_data
-                            .len()
-                            .min(mp1_data.len())
-                            .min(mn0_data.len())
-                            .min(mn1_data.len());
+                // with the vertex data. Only the first two targets are used,
+                // and a missing one or missing attribute just doesn't move
+                // anything.
+                let mut targets = prim.morph_targets();
+                let morph_buffer = match (targets.next(), targets.next()) {
+                    (Some(m0), m1) => {
+                        let len = num_base_vertices;
+                        let read = |accessor: Option<gltf::Accessor>, name: &str| {
+                            Self::get_morph_offsets(accessor, buffers, len, name)
+                        };
+                        let d0_positions = read(m0.positions(), "Morph target 0 POSITION")?;
+                        let d0_normals = read(m0.normals(), "Morph target 0 NORMAL")?;
+                        let d1_positions = read(
+                            m1.as_ref().and_then(|m1| m1.positions()),
+                            "Morph target 1 POSITION",
+                        )?;
+                        let d1_normals = read(
+                            m1.as_ref().and_then(|m1| m1.normals()),
+                            "Morph target 1 NORMAL",
+                        )?;
                         let morphs = (0..len)
                             .map(|i| Morphs {
-                                d0_position: mp0_data[i],
-                                d0_normal: mn0_data[i],
-                                d1_position: mp1_data[i],
-                                d1_normal: mn1_data[i],
+                                d0_position: d0_positions[i],
+                                d0_normal: d0_normals[i],
+                                d1_position: d1_positions[i],
+                                d1_normal: d1_normals[i],
                             })
                             .collect::<Vec<_>>();
                         let morph_buffer =
                             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                                 label: Some("Morphs"),
-                                contents: cast_slice(&morphs),
+                                contents: cast_slice(&split_like(morphs, split.as_deref())),
                                 usage: wgpu::BufferUsages::VERTEX,
                             });
                         Some(morph_buffer)
@@ -182,43 +476,273 @@ impl Model {
                     _ => None,
                 };
 
+                let material = prim.material();
+                let load_texture = |texture: gltf::Texture, format| {
+                    let image = &images[texture.source().index()];
+                    Texture::from_gltf_image(device, queue, material.name(), image, format)
+                        .map(|t| t.with_mipmaps(device, queue, material_binder.mipmaps()))
+                };
+                let normal = material
+                    .normal_texture()
+                    .map(|info| load_texture(info.texture(), wgpu::TextureFormat::Rgba8Unorm))
+                    .transpose()?;
+                // Colors are authored in sRGB, unlike normals
+                let base_color = material
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .map(|info| load_texture(info.texture(), wgpu::TextureFormat::Rgba8UnormSrgb))
+                    .transpose()?;
+                let material = material_binder.bind(device, normal, base_color, transform);
+
                 primitives.push(Primitive {
+                    vertices,
                     vertex_buffer,
+                    tex_coord_1_buffer,
                     morph_buffer,
                     index_buffer,
                     index_format,
+                    line_indices,
                     num_indices,
+                    num_vertices,
+                    material,
+                    aabb: prim_aabb,
+                    transform,
                 })
             }
-            meshes.push(Mesh {
-                name: mesh
-                    .name()
-                    .map(|s| s.to_owned())
-                    .unwrap_or_else(|| "Unknown".into()),
-                primitives,
-            })
+            meshes.push(Mesh { primitives })
         }
 
-        Ok(Self { meshes })
+        Ok(Self {
+            meshes,
+            aabb,
+            transform: transforms.first().copied().unwrap_or(glam::Mat4::IDENTITY),
+        })
     }
 
     pub fn meshes(&self) -> &[Mesh] {
         &self.meshes
     }
 
-    /// Gets slice of the buffer for this accessor ignoring stride
+    /// Every primitive of every mesh, for pipelines that don't care which
+    /// mesh they came from.
+    pub fn primitives(&self) -> impl Iterator<Item = &Primitive> {
+        self.meshes.iter().flat_map(|m| m.primitives())
+    }
+
+    /// Bounds of every primitive in the model, each moved by its
+    /// [`Primitive::transform`]. Not valid if the model has no vertices.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    /// Where the first mesh sits in the scene, including every parent
+    /// node's transform. Each primitive carries its own mesh's transform
+    /// too, see [`Primitive::transform`].
+    pub fn transform(&self) -> glam::Mat4 {
+        self.transform
+    }
+
+    /// World matrix of each mesh, by mesh index, from the first node that
+    /// references it in the default scene, searching depth first. Meshes
+    /// no node in the scene references get identity.
+    fn get_mesh_transforms(document: &gltf::Document) -> Vec<glam::Mat4> {
+        fn search(node: gltf::Node, parent: glam::Mat4, transforms: &mut [Option<glam::Mat4>]) {
+            let world = parent * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+            if let Some(mesh) = node.mesh() {
+                transforms[mesh.index()].get_or_insert(world);
+            }
+            for child in node.children() {
+                search(child, world, transforms);
+            }
+        }
+
+        let mut transforms = vec![None; document.meshes().len()];
+        if let Some(scene) = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            for node in scene.nodes() {
+                search(node, glam::Mat4::IDENTITY, &mut transforms);
+            }
+        }
+        transforms
+            .into_iter()
+            .map(|transform| transform.unwrap_or(glam::Mat4::IDENTITY))
+            .collect()
+    }
+
+    /// The spec requires `min` and `max` on POSITION accessors, but not
+    /// every exporter writes them, so this is `None` if they're missing or
+    /// malformed.
+    fn get_aabb_for_accessor(accessor: &gltf::Accessor) -> Option<Aabb> {
+        let read = |value: gltf::json::Value| -> Option<glam::Vec3> {
+            match value.as_array()?.as_slice() {
+                [x, y, z] => Some(glam::vec3(
+                    x.as_f64()? as f32,
+                    y.as_f64()? as f32,
+                    z.as_f64()? as f32,
+                )),
+                _ => None,
+            }
+        };
+        let aabb = Aabb {
+            min: read(accessor.min()?)?,
+            max: read(accessor.max()?)?,
+        };
+        aabb.is_valid().then_some(aabb)
+    }
+
+    /// `TEXCOORD_0` as floats, or nothing if the primitive isn't textured.
+    /// Normalized u8/u16 UVs get converted.
+    fn get_tex_coords_0(prim: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Vec<glam::Vec2> {
+        let reader = prim.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        reader
+            .read_tex_coords(0)
+            .map(|tex_coords| tex_coords.into_f32().map(glam::Vec2::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// `TEXCOORD_1` as floats, one per vertex, or `None` if the primitive
+    /// doesn't have a second UV set. Normalized u8/u16 UVs get converted.
+    fn get_tex_coords_1(
+        prim: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+        num_vertices: usize,
+    ) -> anyhow::Result<Option<Vec<glam::Vec2>>> {
+        let reader = prim.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        let Some(tex_coords) = reader.read_tex_coords(1) else {
+            return Ok(None);
+        };
+        let mut data = tex_coords
+            .into_f32()
+            .map(glam::Vec2::from)
+            .collect::<Vec<_>>();
+        // A short one would make draws read past the end of the buffer
+        if data.len() < num_vertices {
+            bail!(
+                "TEXCOORD_1 has {} elements, but there are {} vertices",
+                data.len(),
+                num_vertices
+            );
+        }
+        data.truncate(num_vertices);
+        Ok(Some(data))
+    }
+
+    /// One offset per vertex from a morph target attribute, all zero if the
+    /// target doesn't have it. Every morph vertex needs a matching base
+    /// vertex and vice versa, or the draw reads past the end, so a short
+    /// one is an error and extra ones are dropped.
+    fn get_morph_offsets(
+        accessor: Option<gltf::Accessor>,
+        buffers: &[gltf::buffer::Data],
+        num_vertices: usize,
+        name: &str,
+    ) -> anyhow::Result<Vec<glam::Vec3>> {
+        let Some(accessor) = accessor else {
+            return Ok(vec![glam::Vec3::ZERO; num_vertices]);
+        };
+        let bytes = Self::get_data_for_accessor(&accessor, buffers)
+            .with_context(|| format!("{} accessor is out of bounds", name))?;
+        let data: &[glam::Vec3] = cast_slice(&bytes);
+        if data.len() < num_vertices {
+            bail!(
+                "{} has {} elements, but there are {} vertices",
+                name,
+                data.len(),
+                num_vertices
+            );
+        }
+        Ok(data[..num_vertices].to_vec())
+    }
+
+    /// `COLOR_0` as float RGBA, or nothing if the primitive doesn't have
+    /// it. RGB colors get an alpha of 1, and normalized u8/u16 ones get
+    /// converted.
+    fn get_colors(prim: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Vec<glam::Vec4> {
+        let reader = prim.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        reader
+            .read_colors(0)
+            .map(|colors| colors.into_rgba_f32().map(glam::Vec4::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the tightly packed data for this accessor. Tightly packed views
+    /// are borrowed straight from the buffer, interleaved views and sparse
+    /// accessors get copied into an owned, dense buffer.
     fn get_data_for_accessor<'a>(
         a: &gltf::Accessor<'a>,
         buffers: &'a [gltf::buffer::Data],
-    ) -> Option<&'a [u8]> {
-        let view = a.view()?;
-        Some(&buffers[view.buffer().index()].0[view.offset()..view.offset() + view.length()])
+    ) -> Option<Cow<'a, [u8]>> {
+        let element_size = a.size();
+
+        // Sparse accessors don't need a buffer view, in which case the
+        // values not in the sparse set are all zero.
+        let dense = match a.view() {
+            Some(view) => {
+                let buffer = &buffers.get(view.buffer().index())?.0;
+                let start = view.offset() + a.offset();
+                match view.stride() {
+                    // Interleaved with other attributes, gather each element
+                    Some(stride) if stride != element_size => {
+                        let mut packed = Vec::with_capacity(a.count() * element_size);
+                        for i in 0..a.count() {
+                            let offset = start + i * stride;
+                            packed.extend_from_slice(buffer.get(offset..offset + element_size)?);
+                        }
+                        Cow::Owned(packed)
+                    }
+                    _ => Cow::Borrowed(buffer.get(start..start + a.count() * element_size)?),
+                }
+            }
+            None => Cow::Owned(vec![0; a.count() * element_size]),
+        };
+
+        let sparse = match a.sparse() {
+            Some(sparse) => sparse,
+            None => return Some(dense),
+        };
+
+        let mut data = dense.into_owned();
+        data.resize(data.len().max(a.count() * element_size), 0);
+
+        let indices = sparse.indices();
+        let index_size = indices.index_type().size();
+        let index_start = indices.view().offset() + indices.offset() as usize;
+        let index_data = buffers
+            .get(indices.view().buffer().index())?
+            .0
+            .get(index_start..)?;
+
+        let values = sparse.values();
+        let value_start = values.view().offset() + values.offset() as usize;
+        let value_data = buffers
+            .get(values.view().buffer().index())?
+            .0
+            .get(value_start..)?;
+
+        for i in 0..sparse.count() as usize {
+            let index_bytes = index_data.get(i * index_size..(i + 1) * index_size)?;
+            let index = match indices.index_type() {
+                gltf::accessor::sparse::IndexType::U8 => index_bytes[0] as usize,
+                gltf::accessor::sparse::IndexType::U16 => {
+                    u16::from_le_bytes([index_bytes[0], index_bytes[1]]) as usize
+                }
+                gltf::accessor::sparse::IndexType::U32 => {
+                    u32::from_le_bytes(index_bytes.try_into().ok()?) as usize
+                }
+            };
+            let value = value_data.get(i * element_size..(i + 1) * element_size)?;
+            data.get_mut(index * element_size..(index + 1) * element_size)?
+                .copy_from_slice(value);
+        }
+
+        Some(Cow::Owned(data))
     }
 }
 
 #[derive(Debug)]
 pub struct Mesh {
-    name: String,
     primitives: Vec<Primitive>,
 }
 
@@ -230,27 +754,1254 @@ impl Mesh {
 
 #[derive(Debug)]
 pub struct Primitive {
+    vertices: Vec<Vertex>,
     vertex_buffer: wgpu::Buffer,
+    tex_coord_1_buffer: Option<wgpu::Buffer>,
     morph_buffer: Option<wgpu::Buffer>,
-    index_buffer: wgpu::Buffer,
+    index_buffer: Option<wgpu::Buffer>,
     index_format: wgpu::IndexFormat,
+    /// See [`Primitive::line_indices`].
+    line_indices: Option<(wgpu::Buffer, u32)>,
     num_indices: u32,
+    num_vertices: u32,
+    material: MaterialBinding,
+    aabb: Aabb,
+    transform: glam::Mat4,
 }
 
 impl Primitive {
-    pub fn index_buffer(&self) -> &wgpu::Buffer {
-        &self.index_buffer
+    /// `None` if the vertices should be drawn in order with
+    /// [`Primitive::num_vertices`].
+    pub fn index_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.index_buffer.as_ref()
+    }
+
+    /// Copy of what was uploaded to the vertex buffer.
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
     }
 
     pub fn vertex_buffer(&self) -> &wgpu::Buffer {
         &self.vertex_buffer
     }
 
+    /// The `TEXCOORD_1` set if the primitive has one. See
+    /// [`Vertex::TEX_COORD_1_LAYOUT`].
+    pub fn tex_coord_1_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.tex_coord_1_buffer.as_ref()
+    }
+
+    /// Offsets for the first two morph targets, if the primitive has them.
+    /// See [`Morphs::LAYOUT`].
+    pub fn morph_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.morph_buffer.as_ref()
+    }
+
+    /// Zero if the primitive isn't indexed.
     pub fn num_indices(&self) -> u32 {
         self.num_indices
     }
 
+    pub fn num_vertices(&self) -> u32 {
+        self.num_vertices
+    }
+
+    /// Only meaningful if there's an index buffer.
     pub fn index_format(&self) -> wgpu::IndexFormat {
         self.index_format
     }
+
+    /// Every edge as a `Uint32` line list, and how many indices there are.
+    /// Only made when the device can't draw triangles as lines itself.
+    pub fn line_indices(&self) -> Option<(&wgpu::Buffer, u32)> {
+        self.line_indices
+            .as_ref()
+            .map(|(buffer, count)| (buffer, *count))
+    }
+
+    pub fn material(&self) -> &MaterialBinding {
+        &self.material
+    }
+
+    /// Bounds of the undisplaced vertices, before `transform`.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    /// World matrix of the node the primitive's mesh hangs off. Shaders
+    /// get it through the material bind group, see [`MaterialBinder::bind`].
+    pub fn transform(&self) -> glam::Mat4 {
+        self.transform
+    }
+}
+
+/// Turns `u8` indices into little endian `u16` ones, since wgpu can't
+/// draw with 8 bit indices.
+fn widen_indices(indices: &[u8]) -> Vec<u8> {
+    indices
+        .iter()
+        .flat_map(|i| (*i as u16).to_le_bytes())
+        .collect()
+}
+
+/// Fallback for wireframes without `POLYGON_MODE_LINE`, `None` if the
+/// device has it.
+fn create_line_indices(device: &wgpu::Device, indices: &[u32]) -> Option<(wgpu::Buffer, u32)> {
+    if device
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
+    {
+        return None;
+    }
+    let lines = triangles_to_lines(indices);
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Line Index Buffer"),
+        contents: cast_slice(&lines),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+    Some((buffer, lines.len() as u32))
+}
+
+/// Turns a triangle list into a line list of its edges. Edges shared by
+/// neighboring triangles only show up once, and a trailing partial
+/// triangle is ignored.
+pub fn triangles_to_lines(indices: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    let mut lines = Vec::new();
+    for tri in indices.chunks_exact(3) {
+        for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+            if seen.insert((a.min(b), a.max(b))) {
+                lines.extend_from_slice(&[a, b]);
+            }
+        }
+    }
+    lines
+}
+
+/// Vertices and triangle list indices for [`Model::plane`]. There are
+/// `(subdivisions + 1)^2` vertices and `subdivisions^2 * 6` indices, with
+/// `subdivisions` raised to at least 1.
+fn plane_geometry(size: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let n = subdivisions.max(1);
+    let row = n + 1;
+    let step = size / n as f32;
+    let offset = size * 0.5;
+
+    let vertices = (0..row * row)
+        .map(|i| {
+            let (x, z) = ((i % row) as f32, (i / row) as f32);
+            Vertex::new(
+                glam::vec3(x * step - offset, 0.0, z * step - offset),
+                glam::Vec3::Y,
+                glam::vec2(x, z),
+            )
+        })
+        .collect();
+    // Counter clockwise seen from above
+    let indices = (0..n * n)
+        .flat_map(|i| {
+            let corner = i / n * row + i % n;
+            [
+                corner,
+                corner + row,
+                corner + 1,
+                corner + 1,
+                corner + row,
+                corner + row + 1,
+            ]
+        })
+        .collect();
+    (vertices, indices)
+}
+
+/// Vertices and triangle list indices for [`Model::sphere`]. The seam and
+/// poles get duplicate vertices so every one can have its own UV.
+fn sphere_geometry(radius: f32, rings: u32, sectors: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let rings = rings.max(2);
+    let sectors = sectors.max(3);
+    let row = sectors + 1;
+
+    let vertices = (0..(rings + 1) * row)
+        .map(|i| {
+            let tex_coord = glam::vec2(
+                (i % row) as f32 / sectors as f32,
+                (i / row) as f32 / rings as f32,
+            );
+            let azimuth = tex_coord.x * std::f32::consts::TAU;
+            let elevation = (0.5 - tex_coord.y) * std::f32::consts::PI;
+            let normal = spherical_to_cartesian(1.0, azimuth, elevation);
+            Vertex::new(normal * radius, normal, tex_coord)
+        })
+        .collect();
+    // Counter clockwise from outside. The triangles touching the poles
+    // would have no area, so those get left out.
+    let indices = (0..rings * sectors)
+        .flat_map(|i| {
+            let ring = i / sectors;
+            let corner = ring * row + i % sectors;
+            let below = corner + row;
+            let top = (ring > 0).then_some([corner, corner + 1, below]);
+            let bottom = (ring < rings - 1).then_some([corner + 1, below + 1, below]);
+            top.into_iter().chain(bottom).flatten()
+        })
+        .collect();
+    (vertices, indices)
+}
+
+/// Vertices and triangle list indices for [`Model::cube`], 4 vertices and
+/// 2 triangles per face.
+fn cube_geometry(size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let half = size * 0.5;
+    // Each face's normal, and the directions U and V increase in. U cross
+    // V gives the normal, which keeps the winding counter clockwise.
+    let faces = [
+        (glam::Vec3::X, glam::Vec3::NEG_Z, glam::Vec3::Y),
+        (glam::Vec3::NEG_X, glam::Vec3::Z, glam::Vec3::Y),
+        (glam::Vec3::Y, glam::Vec3::X, glam::Vec3::NEG_Z),
+        (glam::Vec3::NEG_Y, glam::Vec3::X, glam::Vec3::Z),
+        (glam::Vec3::Z, glam::Vec3::X, glam::Vec3::Y),
+        (glam::Vec3::NEG_Z, glam::Vec3::NEG_X, glam::Vec3::Y),
+    ];
+
+    let mut vertices = Vec::with_capacity(faces.len() * 4);
+    let mut indices = Vec::with_capacity(faces.len() * 6);
+    for (normal, u, v) in faces {
+        let first = vertices.len() as u32;
+        for corner in [
+            glam::vec2(0.0, 0.0),
+            glam::vec2(1.0, 0.0),
+            glam::vec2(1.0, 1.0),
+            glam::vec2(0.0, 1.0),
+        ] {
+            let offset = u * (corner.x * 2.0 - 1.0) + v * (corner.y * 2.0 - 1.0);
+            // Texture V goes down, while `v` points up
+            let tex_coord = glam::vec2(corner.x, 1.0 - corner.y);
+            vertices.push(Vertex::new((normal + offset) * half, normal, tex_coord));
+        }
+        indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+    }
+    (vertices, indices)
+}
+
+/// Vertices and triangle list indices for [`Model::load_obj`], with every
+/// object in the file merged together. Materials are ignored.
+fn obj_geometry(text: &str) -> anyhow::Result<(Vec<Vertex>, Vec<u32>)> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj_buf(&mut text.as_bytes(), &options, |_| {
+        Err(tobj::LoadError::OpenFileFailed)
+    })?;
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for model in models {
+        let mesh = model.mesh;
+        let count = mesh.positions.len() / 3;
+        let mut model_vertices = (0..count)
+            .map(|i| {
+                let normal = mesh.normals.get(i * 3..i * 3 + 3);
+                // OBJ puts v = 0 at the bottom, wgpu puts it at the top
+                let tex_coord = mesh.texcoords.get(i * 2..i * 2 + 2);
+                Vertex::new(
+                    glam::Vec3::from_slice(&mesh.positions[i * 3..]),
+                    normal.map(glam::Vec3::from_slice).unwrap_or_default(),
+                    tex_coord
+                        .map(|uv| glam::vec2(uv[0], 1.0 - uv[1]))
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if mesh.normals.len() < mesh.positions.len() {
+            compute_smooth_normals(&mut model_vertices, &mesh.indices);
+        }
+        if !mesh.texcoords.is_empty() {
+            compute_tangents(&mut model_vertices, &mesh.indices);
+        }
+
+        let first = vertices.len() as u32;
+        vertices.extend(model_vertices);
+        indices.extend(mesh.indices.iter().map(|i| first + i));
+    }
+    Ok((vertices, indices))
+}
+
+/// Splits the triangle list apart so every face has three vertices of its
+/// own, all with the face's normal. The result is meant to be drawn in
+/// order, without indices. Also returns which of `vertices` each new one
+/// came from, for splitting anything else that lines up with them, see
+/// [`split_like`]. Triangles with an index out of range are dropped, and
+/// degenerate ones get a zero normal.
+pub fn compute_flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat = Vec::with_capacity(indices.len());
+    let mut split = Vec::with_capacity(indices.len());
+    for tri in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        if i0.max(i1).max(i2) >= vertices.len() {
+            continue;
+        }
+        let e1 = vertices[i1].position - vertices[i0].position;
+        let e2 = vertices[i2].position - vertices[i0].position;
+        let normal = e1.cross(e2).normalize_or_zero();
+        for i in tri {
+            flat.push(Vertex {
+                normal,
+                ..vertices[*i as usize]
+            });
+            split.push(*i);
+        }
+    }
+    (flat, split)
+}
+
+/// Lays `data` out like vertices split by [`compute_flat_normals`], or
+/// leaves it alone if there's no `split`.
+fn split_like<T: Copy>(data: Vec<T>, split: Option<&[u32]>) -> Vec<T> {
+    match split {
+        Some(split) => split.iter().map(|i| data[*i as usize]).collect(),
+        None => data,
+    }
+}
+
+/// Gives each vertex the area weighted average of the normals of the faces
+/// it's part of, so meshes with shared vertices come out smooth. Unlike
+/// [`compute_flat_normals`] the vertices stay as they are. Vertices that
+/// aren't part of any proper triangle get a zero normal.
+pub fn compute_smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![glam::Vec3::ZERO; vertices.len()];
+
+    for tri in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        if i0.max(i1).max(i2) >= vertices.len() {
+            continue;
+        }
+
+        // The cross product's length is twice the area, which gives us the
+        // weighting for free. Degenerate triangles add nothing.
+        let e1 = vertices[i1].position - vertices[i0].position;
+        let e2 = vertices[i2].position - vertices[i0].position;
+        let n = e1.cross(e2);
+
+        for i in [i0, i1, i2] {
+            normals[i] += n;
+        }
+    }
+
+    for (v, n) in vertices.iter_mut().zip(normals) {
+        v.normal = n.normalize_or_zero();
+    }
+}
+
+/// Computes per-vertex tangents from the triangle UVs. The handedness of the
+/// bitangent is stored in `w`.
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![glam::Vec3::ZERO; vertices.len()];
+
+    for tri in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        if i0.max(i1).max(i2) >= vertices.len() {
+            continue;
+        }
+        let (v0, v1, v2) = (vertices[i0], vertices[i1], vertices[i2]);
+
+        let e1 = v1.position - v0.position;
+        let e2 = v2.position - v0.position;
+        let duv1 = v1.tex_coord - v0.tex_coord;
+        let duv2 = v2.tex_coord - v0.tex_coord;
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        if det.abs() <= f32::EPSILON {
+            // Degenerate UVs, this triangle can't tell us anything.
+            continue;
+        }
+        let r = 1.0 / det;
+        let t = (e1 * duv2.y - e2 * duv1.y) * r;
+        let b = (e2 * duv1.x - e1 * duv2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += t;
+            bitangents[i] += b;
+        }
+    }
+
+    for (i, v) in vertices.iter_mut().enumerate() {
+        let n = v.normal;
+        // Gram-Schmidt orthogonalize against the normal
+        let t = (tangents[i] - n * n.dot(tangents[i])).normalize_or_zero();
+        let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        v.tangent = t.extend(w);
+    }
+}
+
+/// wgpu panics on buffers bigger than the device allows, so catch them while
+/// we can still report which model was too big.
+fn check_buffer_size(device: &wgpu::Device, size: usize, what: &str) -> anyhow::Result<()> {
+    let max = device.limits().max_buffer_size;
+    if size as u64 > max {
+        bail!(
+            "{} needs {} bytes, but the GPU only allows {}",
+            what,
+            size,
+            max
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt;
+
+    use super::*;
+
+    /// A document plus its single buffer, built from `json` and `buffer`.
+    fn gltf(json: serde_json::Value, buffer: Vec<u8>) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).unwrap();
+        (gltf.document, vec![gltf::buffer::Data(buffer)])
+    }
+
+    fn bytes(floats: &[f32]) -> Vec<u8> {
+        cast_slice(floats).to_vec()
+    }
+
+    #[test]
+    fn interleaved_accessors_get_gathered() {
+        // Position then normal for each of two vertices
+        let buffer = bytes(&[1.0, 2.0, 3.0, 0.0, 1.0, 0.0, 4.0, 5.0, 6.0, 0.0, 0.0, 1.0]);
+        let (document, buffers) = gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 48, "byteStride": 24 },
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 48 }
+                ],
+                "accessors": [
+                    { "bufferView": 0, "count": 2, "componentType": 5126, "type": "VEC3" },
+                    {
+                        "bufferView": 0, "byteOffset": 12, "count": 2,
+                        "componentType": 5126, "type": "VEC3"
+                    },
+                    { "bufferView": 1, "count": 4, "componentType": 5126, "type": "VEC3" }
+                ]
+            }),
+            buffer,
+        );
+        let mut accessors = document.accessors();
+
+        let positions = Model::get_data_for_accessor(&accessors.next().unwrap(), &buffers).unwrap();
+        assert_eq!(
+            cast_slice::<_, f32>(&positions),
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+        let normals = Model::get_data_for_accessor(&accessors.next().unwrap(), &buffers).unwrap();
+        assert_eq!(
+            cast_slice::<_, f32>(&normals),
+            [0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+        // Tightly packed data doesn't need copying
+        let packed = Model::get_data_for_accessor(&accessors.next().unwrap(), &buffers).unwrap();
+        assert!(matches!(packed, Cow::Borrowed(_)));
+    }
+
+    /// Four VEC3s, where the second and fourth get replaced by (1, 2, 3)
+    /// and (4, 5, 6). Without `base` the rest are zero.
+    fn sparse_document(base: bool) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        // Indices padded to 4 bytes, then the values, then the base
+        let mut buffer = vec![1, 3, 0, 0];
+        buffer.extend(bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        buffer.extend(bytes(&[1.0; 12]));
+        let mut accessor = serde_json::json!({
+            "count": 4,
+            "componentType": 5126,
+            "type": "VEC3",
+            "sparse": {
+                "count": 2,
+                "indices": { "bufferView": 0, "componentType": 5121 },
+                "values": { "bufferView": 1 }
+            }
+        });
+        if base {
+            accessor["bufferView"] = 2.into();
+        }
+        gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 2 },
+                    { "buffer": 0, "byteOffset": 4, "byteLength": 24 },
+                    { "buffer": 0, "byteOffset": 28, "byteLength": 48 }
+                ],
+                "accessors": [accessor]
+            }),
+            buffer,
+        )
+    }
+
+    /// A triangle with float UVs in TEXCOORD_0 and normalized u16 ones in
+    /// TEXCOORD_1, which has `tex_coords_1` elements.
+    fn two_uv_document(tex_coords_1: usize) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut buffer = bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        buffer.extend(bytes(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+        for uv in [0u16, 0, 65535, 0, 0, 32768] {
+            buffer.extend(uv.to_le_bytes());
+        }
+        gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                    { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+                    { "buffer": 0, "byteOffset": 60, "byteLength": 12 }
+                ],
+                "accessors": [
+                    {
+                        "bufferView": 0, "count": 3, "componentType": 5126, "type": "VEC3",
+                        "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+                    },
+                    { "bufferView": 1, "count": 3, "componentType": 5126, "type": "VEC2" },
+                    {
+                        "bufferView": 2, "count": tex_coords_1, "componentType": 5123,
+                        "normalized": true, "type": "VEC2"
+                    }
+                ],
+                "meshes": [{
+                    "primitives": [{
+                        "attributes": { "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 }
+                    }]
+                }]
+            }),
+            buffer,
+        )
+    }
+
+    fn first_primitive(document: &gltf::Document) -> gltf::Primitive<'_> {
+        document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap()
+    }
+
+    #[test]
+    fn both_uv_sets_load() {
+        let (document, buffers) = two_uv_document(3);
+        let prim = first_primitive(&document);
+
+        let tex_coords_0 = Model::get_tex_coords_0(&prim, &buffers);
+        assert_eq!(
+            tex_coords_0,
+            [glam::Vec2::ZERO, glam::Vec2::X, glam::Vec2::Y]
+        );
+
+        let tex_coords_1 = Model::get_tex_coords_1(&prim, &buffers, 3)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tex_coords_1[..2], [glam::Vec2::ZERO, glam::Vec2::X]);
+        assert!(tex_coords_1[2].abs_diff_eq(glam::vec2(0.0, 0.5), 1e-4));
+    }
+
+    #[test]
+    fn short_uv_set_is_an_error() {
+        let (document, buffers) = two_uv_document(2);
+        let prim = first_primitive(&document);
+        assert!(Model::get_tex_coords_1(&prim, &buffers, 3).is_err());
+    }
+
+    /// Uploads a test document. Needs a device from `test_device`.
+    fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
+    ) -> Model {
+        let material_binder = MaterialBinder::new(device, queue);
+        Model::from_gltf(device, queue, &material_binder, document, buffers, images).unwrap()
+    }
+
+    #[test]
+    fn primitives_without_indices_draw_every_vertex() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let (document, buffers) = two_uv_document(3);
+        let model = load(&device, &queue, &document, &buffers, &[]);
+        let prim = model.primitives().next().unwrap();
+        assert!(prim.index_buffer().is_none());
+        assert_eq!(prim.num_indices(), 0);
+        assert_eq!(prim.num_vertices(), 3);
+    }
+
+    /// An indexed triangle facing +z, with nothing but positions.
+    fn positions_only_document() -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let (json, buffer) = positions_only_json();
+        gltf(json, buffer)
+    }
+
+    /// What `positions_only_document` is made from.
+    fn positions_only_json() -> (serde_json::Value, Vec<u8>) {
+        let mut buffer = bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        buffer.extend([0, 1, 2, 0]);
+        (
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                    { "buffer": 0, "byteOffset": 36, "byteLength": 3 }
+                ],
+                "accessors": [
+                    {
+                        "bufferView": 0, "count": 3, "componentType": 5126, "type": "VEC3",
+                        "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+                    },
+                    { "bufferView": 1, "count": 3, "componentType": 5121, "type": "SCALAR" }
+                ],
+                "meshes": [{
+                    "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }]
+                }]
+            }),
+            buffer,
+        )
+    }
+
+    #[test]
+    fn split_gltf_loads_like_the_packed_one() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let dir = crate::resources::temp_dir("split-gltf");
+        let (mut json, buffer) = positions_only_json();
+        json["buffers"][0]["uri"] = "triangle.bin".into();
+        std::fs::write(dir.join("triangle.gltf"), json.to_string()).unwrap();
+        std::fs::write(dir.join("triangle.bin"), &buffer).unwrap();
+
+        let material_binder = MaterialBinder::new(&device, &queue);
+        let path = dir.join("triangle.gltf");
+        let split = Model::load(&device, &queue, &material_binder, path.to_str().unwrap())
+            .block_on()
+            .unwrap();
+        let (document, buffers) = positions_only_document();
+        let packed = load(&device, &queue, &document, &buffers, &[]);
+
+        let positions = |model: &Model| {
+            model
+                .primitives()
+                .flat_map(|prim| prim.vertices().iter().map(|v| v.position))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&split), positions(&packed));
+        assert_eq!(positions(&split).len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn primitives_without_uvs_get_no_tangents() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let (document, buffers) = positions_only_document();
+        let model = load(&device, &queue, &document, &buffers, &[]);
+        let prim = model.primitives().next().unwrap();
+        // Split into flat faces, which draw in order
+        assert!(prim.index_buffer().is_none());
+        assert_eq!(prim.num_vertices(), 3);
+        for v in prim.vertices() {
+            assert_eq!(v.tex_coord, glam::Vec2::ZERO);
+            assert_eq!(v.tangent, glam::Vec4::ZERO);
+            // Still lit, from the computed normals
+            assert!(v.normal.abs_diff_eq(glam::Vec3::Z, 1e-6));
+        }
+    }
+
+    /// One mesh with two primitives sharing a triangle. The first has a
+    /// white 4x2 base color texture, the second is untextured.
+    fn two_material_document() -> (
+        gltf::Document,
+        Vec<gltf::buffer::Data>,
+        Vec<gltf::image::Data>,
+    ) {
+        let (json, buffer) = two_material_json();
+        let (document, buffers) = gltf(json, buffer);
+        let image = gltf::image::Data {
+            pixels: vec![255; 4 * 2 * 4],
+            format: gltf::image::Format::R8G8B8A8,
+            width: 4,
+            height: 2,
+        };
+        (document, buffers, vec![image])
+    }
+
+    /// What `two_material_document` is made from, minus the image.
+    fn two_material_json() -> (serde_json::Value, Vec<u8>) {
+        let buffer = bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        (
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }],
+                "accessors": [{
+                    "bufferView": 0, "count": 3, "componentType": 5126, "type": "VEC3",
+                    "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+                }],
+                "images": [{ "uri": "base_color.png" }],
+                "textures": [{ "source": 0 }],
+                "materials": [
+                    { "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } },
+                    {}
+                ],
+                "meshes": [{
+                    "primitives": [
+                        { "attributes": { "POSITION": 0 }, "material": 0 },
+                        { "attributes": { "POSITION": 0 }, "material": 1 }
+                    ]
+                }]
+            }),
+            buffer,
+        )
+    }
+
+    #[test]
+    fn split_gltf_finds_its_bin_and_images() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let dir = crate::resources::temp_dir("split-gltf-images");
+        let (mut json, buffer) = two_material_json();
+        json["buffers"][0]["uri"] = "triangle.bin".into();
+        std::fs::write(dir.join("triangle.gltf"), json.to_string()).unwrap();
+        std::fs::write(dir.join("triangle.bin"), &buffer).unwrap();
+        image::RgbaImage::new(4, 2)
+            .save(dir.join("base_color.png"))
+            .unwrap();
+
+        let material_binder = MaterialBinder::new(&device, &queue);
+        let path = dir.join("triangle.gltf");
+        let model = Model::load(&device, &queue, &material_binder, path.to_str().unwrap())
+            .block_on()
+            .unwrap();
+        let primitives = model.meshes()[0].primitives();
+        assert_eq!(primitives[0].num_vertices(), 3);
+        let size = primitives[0].material().base_color().unwrap().size();
+        assert_eq!((size.width, size.height), (4, 2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn each_primitive_gets_its_own_material() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let (document, buffers, images) = two_material_document();
+        let model = load(&device, &queue, &document, &buffers, &images);
+        let primitives = model.meshes()[0].primitives();
+        assert_eq!(primitives.len(), 2);
+        for prim in primitives {
+            assert_eq!(prim.num_vertices(), 3);
+        }
+        assert!(primitives[0].material().base_color().is_some());
+        assert!(primitives[1].material().base_color().is_none());
+    }
+
+    #[test]
+    fn base_color_keeps_its_size_and_gets_mipmapped() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let (document, buffers, images) = two_material_document();
+        let model = load(&device, &queue, &document, &buffers, &images);
+        let texture = model.meshes()[0].primitives()[0]
+            .material()
+            .base_color()
+            .unwrap();
+        let size = texture.size();
+        assert_eq!((size.width, size.height), (4, 2));
+        // 4x2, 2x1 and 1x1
+        assert_eq!(texture.mip_level_count(), 3);
+        assert_eq!(texture.format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+    }
+
+    #[test]
+    fn sparse_accessor_without_view_starts_from_zero() {
+        let (document, buffers) = sparse_document(false);
+        let accessor = document.accessors().next().unwrap();
+        let data = Model::get_data_for_accessor(&accessor, &buffers).unwrap();
+        let expected = [0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 4.0, 5.0, 6.0];
+        assert_eq!(cast_slice::<_, f32>(&data), expected);
+    }
+
+    #[test]
+    fn sparse_accessor_replaces_base_values() {
+        let (document, buffers) = sparse_document(true);
+        let accessor = document.accessors().next().unwrap();
+        let data = Model::get_data_for_accessor(&accessor, &buffers).unwrap();
+        let expected = [1.0, 1.0, 1.0, 1.0, 2.0, 3.0, 1.0, 1.0, 1.0, 4.0, 5.0, 6.0];
+        assert_eq!(cast_slice::<_, f32>(&data), expected);
+    }
+
+    #[test]
+    fn sparse_offset_past_the_buffer_is_none() {
+        let buffer = vec![0; 8];
+        let (document, buffers) = gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 4 },
+                    { "buffer": 0, "byteOffset": 4, "byteLength": 4 }
+                ],
+                "accessors": [{
+                    "count": 1,
+                    "componentType": 5126,
+                    "type": "SCALAR",
+                    "sparse": {
+                        "count": 1,
+                        "indices": { "bufferView": 0, "componentType": 5121 },
+                        "values": { "bufferView": 1, "byteOffset": 1024 }
+                    }
+                }]
+            }),
+            buffer,
+        );
+        let accessor = document.accessors().next().unwrap();
+        assert!(Model::get_data_for_accessor(&accessor, &buffers).is_none());
+    }
+
+    #[test]
+    fn normalized_colors_get_converted() {
+        // Two positions, then two u8 RGBA colors, then two u16 RGB ones
+        let mut buffer = bytes(&[0.0; 6]);
+        buffer.extend([255, 0, 51, 255, 0, 255, 0, 102]);
+        for c in [65535u16, 0, 0, 0, 0, 65535] {
+            buffer.extend(c.to_le_bytes());
+        }
+        let (document, buffers) = gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 0, "byteLength": 24 },
+                    { "buffer": 0, "byteOffset": 24, "byteLength": 8 },
+                    { "buffer": 0, "byteOffset": 32, "byteLength": 12 }
+                ],
+                "accessors": [
+                    {
+                        "bufferView": 0, "count": 2, "componentType": 5126, "type": "VEC3",
+                        "min": [0.0, 0.0, 0.0], "max": [0.0, 0.0, 0.0]
+                    },
+                    {
+                        "bufferView": 1, "count": 2, "componentType": 5121,
+                        "normalized": true, "type": "VEC4"
+                    },
+                    {
+                        "bufferView": 2, "count": 2, "componentType": 5123,
+                        "normalized": true, "type": "VEC3"
+                    }
+                ],
+                "meshes": [{
+                    "primitives": [
+                        { "attributes": { "POSITION": 0, "COLOR_0": 1 } },
+                        { "attributes": { "POSITION": 0, "COLOR_0": 2 } },
+                        { "attributes": { "POSITION": 0 } }
+                    ]
+                }]
+            }),
+            buffer,
+        );
+        let mesh = document.meshes().next().unwrap();
+        let mut primitives = mesh.primitives();
+
+        let rgba = Model::get_colors(&primitives.next().unwrap(), &buffers);
+        assert!(rgba[0].abs_diff_eq(glam::vec4(1.0, 0.0, 0.2, 1.0), 1e-6));
+        assert!(rgba[1].abs_diff_eq(glam::vec4(0.0, 1.0, 0.0, 0.4), 1e-6));
+        // Missing alpha is opaque
+        let rgb = Model::get_colors(&primitives.next().unwrap(), &buffers);
+        let opaque = |c: glam::Vec4| c + glam::Vec4::W;
+        assert_eq!(rgb, [opaque(glam::Vec4::X), opaque(glam::Vec4::Z)]);
+        // Missing colors are left for the vertices to default
+        assert!(Model::get_colors(&primitives.next().unwrap(), &buffers).is_empty());
+    }
+
+    /// Two meshes sharing the triangle from `positions_only_json`. The
+    /// first hangs off a TRS node through a matrix node, the second off a
+    /// node of its own. Returns the world matrix each should end up with.
+    fn two_node_document() -> (gltf::Document, Vec<gltf::buffer::Data>, [glam::Mat4; 2]) {
+        let rotation = glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let child = glam::Mat4::from_translation(glam::vec3(0.0, 0.0, 5.0));
+        let (mut json, buffer) = positions_only_json();
+        let mesh = json["meshes"][0].clone();
+        json["meshes"] = serde_json::json!([mesh.clone(), mesh]);
+        json["nodes"] = serde_json::json!([
+            {
+                "translation": [1.0, 2.0, 3.0],
+                "rotation": rotation.to_array(),
+                "scale": [2.0, 2.0, 2.0],
+                "children": [1]
+            },
+            { "matrix": child.to_cols_array(), "mesh": 0 },
+            { "translation": [0.0, -4.0, 0.0], "mesh": 1 }
+        ]);
+        json["scenes"] = serde_json::json!([{ "nodes": [0, 2] }]);
+        json["scene"] = 0.into();
+        let (document, buffers) = gltf(json, buffer);
+
+        let parent = glam::Mat4::from_scale_rotation_translation(
+            glam::Vec3::splat(2.0),
+            rotation,
+            glam::vec3(1.0, 2.0, 3.0),
+        );
+        let second = glam::Mat4::from_translation(glam::vec3(0.0, -4.0, 0.0));
+        (document, buffers, [parent * child, second])
+    }
+
+    #[test]
+    fn node_transforms_compose_down_to_the_mesh() {
+        let (document, _, expected) = two_node_document();
+        let transforms = Model::get_mesh_transforms(&document);
+        assert_eq!(transforms.len(), 2);
+        for (transform, expected) in transforms.iter().zip(expected) {
+            assert!(transform.abs_diff_eq(expected, 1e-5));
+        }
+        // Pushed out 10 along z, which the rotation turns into x
+        let origin = transforms[0].transform_point3(glam::Vec3::ZERO);
+        assert!(origin.abs_diff_eq(glam::vec3(11.0, 2.0, 3.0), 1e-5));
+    }
+
+    #[test]
+    fn meshes_without_a_node_stay_put() {
+        let (json, buffer) = positions_only_json();
+        let (document, _) = gltf(json, buffer);
+        assert_eq!(
+            Model::get_mesh_transforms(&document),
+            [glam::Mat4::IDENTITY]
+        );
+    }
+
+    #[test]
+    fn each_primitive_gets_its_own_node_transform() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let (document, buffers, expected) = two_node_document();
+        let model = load(&device, &queue, &document, &buffers, &[]);
+        for (mesh, expected) in model.meshes().iter().zip(expected) {
+            assert!(mesh.primitives()[0].transform().abs_diff_eq(expected, 1e-5));
+        }
+        assert!(model.transform().abs_diff_eq(expected[0], 1e-5));
+
+        // The bounds cover both placed triangles
+        let bounds = model
+            .primitives()
+            .map(|prim| prim.aabb().transform(prim.transform()))
+            .fold(Aabb::EMPTY, Aabb::union);
+        assert!(model.aabb().min.abs_diff_eq(bounds.min, 1e-5));
+        assert!(model.aabb().max.abs_diff_eq(bounds.max, 1e-5));
+        assert!(model.aabb().max.x > 10.0 && model.aabb().min.y < -3.0);
+    }
+
+    #[test]
+    fn aabb_comes_from_min_and_max() {
+        let buffer = bytes(&[0.0; 3]);
+        let (document, _) = gltf(
+            serde_json::json!({
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": buffer.len() }],
+                "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 12 }],
+                "accessors": [
+                    {
+                        "bufferView": 0, "count": 1, "componentType": 5126, "type": "VEC3",
+                        "min": [-1.0, -2.0, -3.0], "max": [1.0, 2.0, 3.0]
+                    },
+                    { "bufferView": 0, "count": 1, "componentType": 5126, "type": "VEC3" },
+                    {
+                        "bufferView": 0, "count": 1, "componentType": 5126, "type": "VEC3",
+                        "min": [1.0, 1.0, 1.0], "max": [0.0, 0.0, 0.0]
+                    }
+                ]
+            }),
+            buffer,
+        );
+        let aabbs = document
+            .accessors()
+            .map(|a| Model::get_aabb_for_accessor(&a))
+            .collect::<Vec<_>>();
+        let aabb = aabbs[0].unwrap();
+        assert_eq!(aabb.min, glam::vec3(-1.0, -2.0, -3.0));
+        assert_eq!(aabb.max, glam::vec3(1.0, 2.0, 3.0));
+        // Missing or backwards bounds get computed from the vertices instead
+        assert!(aabbs[1].is_none());
+        assert!(aabbs[2].is_none());
+    }
+
+    #[test]
+    fn spherical_cube_is_centered_on_the_origin() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let material_binder = MaterialBinder::new(&device, &queue);
+        let model = Model::load(&device, &queue, &material_binder, "res/spherical-cube.glb")
+            .block_on()
+            .unwrap();
+        let aabb = model.aabb();
+        assert!(aabb.is_valid());
+        // Roughly symmetric, allowing for how finely it's tessellated
+        let tolerance = aabb.size().max_element() * 0.01;
+        assert!(aabb.min.abs_diff_eq(-aabb.max, tolerance), "{:?}", aabb);
+    }
+
+    #[test]
+    fn morph_targets_need_an_offset_per_vertex() {
+        let (document, buffers) = two_uv_document(3);
+        // Three positions, read as morph offsets
+        let offsets = || document.accessors().next();
+
+        let exact = Model::get_morph_offsets(offsets(), &buffers, 3, "POSITION").unwrap();
+        assert_eq!(exact, [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y]);
+        let extra = Model::get_morph_offsets(offsets(), &buffers, 2, "POSITION").unwrap();
+        assert_eq!(extra, [glam::Vec3::ZERO, glam::Vec3::X]);
+        let short = Model::get_morph_offsets(offsets(), &buffers, 4, "POSITION");
+        assert!(short.unwrap_err().to_string().contains("4 vertices"));
+
+        let missing = Model::get_morph_offsets(None, &buffers, 2, "NORMAL").unwrap();
+        assert_eq!(missing, [glam::Vec3::ZERO; 2]);
+    }
+
+    fn triangle(tex_coords: [glam::Vec2; 3]) -> Vec<Vertex> {
+        [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y]
+            .into_iter()
+            .zip(tex_coords)
+            .map(|(position, tex_coord)| Vertex::new(position, glam::Vec3::Z, tex_coord))
+            .collect()
+    }
+
+    #[test]
+    fn single_triangle_gets_its_face_normal() {
+        let mut vertices = triangle([glam::Vec2::ZERO; 3]);
+        for v in &mut vertices {
+            v.normal = glam::Vec3::ZERO;
+        }
+        compute_smooth_normals(&mut vertices, &[0, 1, 2]);
+        for v in vertices {
+            assert!(v.normal.abs_diff_eq(glam::Vec3::Z, 1e-6));
+        }
+    }
+
+    #[test]
+    fn flat_faces_get_their_own_vertices() {
+        // Two triangles folded 90 degrees along the edge from 0 to 1, and
+        // a degenerate one
+        let vertices = [
+            glam::Vec3::ZERO,
+            glam::Vec3::X,
+            glam::Vec3::Y,
+            glam::Vec3::Z,
+            glam::Vec3::X * 2.0,
+        ]
+        .map(|position| Vertex::new(position, glam::Vec3::ZERO, glam::Vec2::ZERO));
+        let indices = [0, 1, 2, 1, 0, 3, 0, 1, 4];
+        let (flat, split) = compute_flat_normals(&vertices, &indices);
+        assert_eq!(split, indices);
+        assert_eq!(flat.len(), 9);
+        let faces = [glam::Vec3::Z, glam::Vec3::Y, glam::Vec3::ZERO];
+        for (face, normal) in flat.chunks_exact(3).zip(faces) {
+            for v in face {
+                assert!(v.normal.abs_diff_eq(normal, 1e-6));
+            }
+        }
+        for (v, i) in flat.iter().zip(split) {
+            assert_eq!(v.position, vertices[i as usize].position);
+        }
+    }
+
+    #[test]
+    fn flat_normals_drop_triangles_out_of_range() {
+        let vertices = triangle([glam::Vec2::ZERO; 3]);
+        let (flat, split) = compute_flat_normals(&vertices, &[0, 1, 2, 0, 1, 3, 0]);
+        assert_eq!(split, [0, 1, 2]);
+        assert_eq!(flat.len(), 3);
+    }
+
+    #[test]
+    fn split_data_follows_the_vertices() {
+        assert_eq!(
+            split_like(vec!['a', 'b', 'c'], Some(&[2, 0, 2])),
+            ['c', 'a', 'c']
+        );
+        assert_eq!(split_like(vec!['a', 'b'], None), ['a', 'b']);
+    }
+
+    #[test]
+    fn shared_vertices_average_their_faces() {
+        // Two triangles folded 90 degrees along the edge from 0 to 1
+        let mut vertices = [
+            glam::Vec3::ZERO,
+            glam::Vec3::X,
+            glam::Vec3::Y,
+            glam::Vec3::Z,
+        ]
+        .map(|position| Vertex::new(position, glam::Vec3::ZERO, glam::Vec2::ZERO));
+        compute_smooth_normals(&mut vertices, &[0, 1, 2, 1, 0, 3]);
+        let between = glam::vec3(0.0, 1.0, 1.0).normalize();
+        assert!(vertices[0].normal.abs_diff_eq(between, 1e-6));
+        assert!(vertices[2].normal.abs_diff_eq(glam::Vec3::Z, 1e-6));
+        assert!(vertices[3].normal.abs_diff_eq(glam::Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn degenerate_triangles_leave_normals_zero() {
+        let mut vertices = [glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::X * 2.0]
+            .map(|position| Vertex::new(position, glam::Vec3::Y, glam::Vec2::ZERO));
+        compute_smooth_normals(&mut vertices, &[0, 1, 2]);
+        for v in vertices {
+            assert_eq!(v.normal, glam::Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn tangents_follow_u() {
+        let mut vertices = triangle([glam::Vec2::ZERO, glam::Vec2::X, glam::Vec2::Y]);
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+        for v in vertices {
+            assert!(v.tangent.abs_diff_eq(glam::vec4(1.0, 0.0, 0.0, 1.0), 1e-6));
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_handedness() {
+        let mut vertices = triangle([glam::Vec2::ZERO, glam::Vec2::X, glam::Vec2::NEG_Y]);
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+        for v in vertices {
+            assert!(v.tangent.abs_diff_eq(glam::vec4(1.0, 0.0, 0.0, -1.0), 1e-6));
+        }
+    }
+
+    #[test]
+    fn degenerate_uvs_leave_tangents_zero() {
+        let mut vertices = triangle([glam::Vec2::ZERO; 3]);
+        compute_tangents(&mut vertices, &[0, 1, 2]);
+        for v in vertices {
+            assert_eq!(v.tangent.truncate(), glam::Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn obj_quads_get_triangulated() {
+        let text = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+        let (vertices, indices) = obj_geometry(text).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        for v in &vertices {
+            assert_eq!(v.normal, glam::Vec3::Z);
+            assert_eq!(v.tex_coord.y, 1.0 - v.position.y);
+            assert_eq!(v.tangent.truncate(), glam::Vec3::X);
+        }
+    }
+
+    #[test]
+    fn obj_without_normals_gets_them_computed() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 0 -1\nf 1 2 3\n";
+        let (vertices, indices) = obj_geometry(text).unwrap();
+        assert_eq!(indices, [0, 1, 2]);
+        for v in vertices {
+            assert_eq!(v.normal, glam::Vec3::Y);
+        }
+    }
+
+    #[test]
+    fn obj_objects_get_merged() {
+        let text = "\
+o first
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+o second
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f 4 5 6
+";
+        let (vertices, indices) = obj_geometry(text).unwrap();
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(vertices[3].position, glam::Vec3::Z);
+    }
+
+    #[test]
+    fn plane_has_a_vertex_per_grid_point() {
+        let (vertices, indices) = plane_geometry(2.0, 4);
+        assert_eq!((vertices.len(), indices.len()), (25, 96));
+        assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+        for v in &vertices {
+            assert_eq!(v.position.y, 0.0);
+            assert!(v.position.x.abs() <= 1.0 && v.position.z.abs() <= 1.0);
+        }
+        // Zero subdivisions is still one quad
+        let (vertices, indices) = plane_geometry(2.0, 0);
+        assert_eq!((vertices.len(), indices.len()), (4, 6));
+    }
+
+    #[test]
+    fn sphere_vertices_sit_on_the_radius() {
+        let (vertices, indices) = sphere_geometry(2.5, 6, 8);
+        assert_eq!(vertices.len(), 7 * 9);
+        for v in &vertices {
+            assert!((v.position.length() - 2.5).abs() < 1e-5);
+            assert!(v.normal.abs_diff_eq(v.position / 2.5, 1e-5));
+        }
+        // Every triangle faces outwards and has some area
+        for tri in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[tri[i] as usize].position);
+            let normal = (b - a).cross(c - a);
+            assert!(normal.dot(a + b + c) > 0.0);
+        }
+    }
+
+    #[test]
+    fn cube_vertices_sit_on_the_faces() {
+        let (vertices, indices) = cube_geometry(2.0);
+        assert_eq!((vertices.len(), indices.len()), (24, 36));
+        for v in vertices {
+            assert_eq!(v.position.dot(v.normal), 1.0);
+            assert_eq!(v.position.abs().max_element(), 1.0);
+        }
+    }
+
+    #[test]
+    fn u8_indices_widen_to_u16() {
+        let widened = widen_indices(&[0, 1, 2, 255]);
+        assert_eq!(widened, [0, 0, 1, 0, 2, 0, 255, 0]);
+        assert!(widen_indices(&[]).is_empty());
+    }
+
+    #[test]
+    fn shared_edges_become_one_line() {
+        // Two triangles making a quad, sharing the 0-2 edge
+        let lines = triangles_to_lines(&[0, 1, 2, 0, 2, 3, 4]);
+        assert_eq!(lines, [0, 1, 1, 2, 2, 0, 2, 3, 3, 0]);
+        assert!(triangles_to_lines(&[0, 1]).is_empty());
+    }
 }
@@ -1,12 +1,37 @@
+use anyhow::{bail, Context};
+use wgpu::util::DeviceExt;
+
+use super::load_binary;
+use crate::pipelines::mipmap::MipmapPipeline;
+
+#[derive(Debug)]
 pub struct Texture {
     texture: wgpu::Texture,
     view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
     format: wgpu::TextureFormat,
 }
 
 impl Texture {
-    pub fn depth_texture(device: &wgpu::Device, width: u32, height: u32) -> Self {
-        let format = wgpu::TextureFormat::Depth32Float;
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// `sample_count` needs to match the color target it's used with.
+    pub fn depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let format = Self::DEPTH_FORMAT;
+        assert!(
+            format
+                .guaranteed_format_features(device.features())
+                .flags
+                .sample_count_supported(sample_count),
+            "{:?} doesn't support {} samples",
+            format,
+            sample_count,
+        );
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("depth_texture"),
             size: wgpu::Extent3d {
@@ -15,21 +40,565 @@ impl Texture {
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        Self { texture, view, format }
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
+    /// Offscreen color target that later passes can sample from.
+    pub fn color_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color_target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // Copyable so it can be read back with `read_to_image`
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
+    /// Multisampled color target. It can't be sampled, so it has to be
+    /// resolved into a regular color target first.
+    pub fn msaa_color_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa_color_target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
+    /// Uploads tightly packed RGBA8 pixels into a new texture with a
+    /// linear sampler.
+    pub fn from_image_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        data: &[u8],
+    ) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+            data,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
+    /// Converts a decoded glTF image to RGBA8 and uploads it.
+    pub fn from_gltf_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        image: &gltf::image::Data,
+        format: wgpu::TextureFormat,
+    ) -> anyhow::Result<Self> {
+        let channels = match image.format {
+            gltf::image::Format::R8 => 1,
+            gltf::image::Format::R8G8 => 2,
+            gltf::image::Format::R8G8B8 => 3,
+            gltf::image::Format::R8G8B8A8 => 4,
+            f => bail!("Unsupported image format {:?}", f),
+        };
+
+        // Missing channels are filled in with 0, alpha with 255.
+        let rgba = image
+            .pixels
+            .chunks_exact(channels)
+            .flat_map(|p| {
+                let mut pixel = [0, 0, 0, 255];
+                pixel[..channels].copy_from_slice(p);
+                pixel
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self::from_image_data(
+            device,
+            queue,
+            label,
+            image.width,
+            image.height,
+            format,
+            &rgba,
+        ))
+    }
+
+    /// Loads a PNG or JPEG as sRGB color data.
+    pub async fn from_file(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+    ) -> anyhow::Result<Self> {
+        let bytes = load_binary(path).await?;
+        let image = image::load_from_memory(&bytes)
+            .with_context(|| format!("Unable to decode {}", path))?
+            .to_rgba8();
+        Ok(Self::from_image_data(
+            device,
+            queue,
+            Some(path),
+            image.width(),
+            image.height(),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &image,
+        ))
+    }
+
+    /// Loads six PNG or JPEG faces into a cube texture. Faces go in wgpu's
+    /// layer order: +X, -X, +Y, -Y, +Z, -Z. They all need to be the same
+    /// square size.
+    pub async fn cubemap_from_files(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[String; 6],
+    ) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for path in paths {
+            let bytes = load_binary(path).await?;
+            let image = image::load_from_memory(&bytes)
+                .with_context(|| format!("Unable to decode {}", path))?
+                .to_rgba8();
+            if image.width() != image.height() {
+                bail!("Cubemap face {} isn't square", path);
+            }
+            match size {
+                None => size = Some(image.width()),
+                Some(size) if size != image.width() => {
+                    bail!("Cubemap face {} is a different size than the others", path)
+                }
+                Some(_) => (),
+            }
+            // Layers are laid out one after the other
+            data.extend_from_slice(&image);
+        }
+        let size = size.unwrap_or(1);
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("cubemap"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+            &data,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("cubemap"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+        })
+    }
+
+    /// Copies the texture into one with a full mip chain and fills in the
+    /// smaller levels. The sampler gets switched to linear mipmap filtering.
+    pub fn with_mipmaps(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mipmaps: &MipmapPipeline,
+    ) -> Self {
+        let size = self.texture.size();
+        let mip_level_count = size.max_mips(wgpu::TextureDimension::D2);
+        if mip_level_count <= self.texture.mip_level_count() {
+            return self;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("mipmapped_texture"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_texture(
+            self.texture.as_image_copy(),
+            texture.as_image_copy(),
+            size,
+        );
+        mipmaps.generate(device, &mut encoder, &texture);
+        queue.submit([encoder.finish()]);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmapped_texture"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            format: self.format,
+        }
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.texture.size()
     }
 
     pub fn format(&self) -> wgpu::TextureFormat {
         self.format
     }
 
+    /// 1 unless it went through `with_mipmaps`.
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// More than 1 for MSAA targets.
+    pub fn sample_count(&self) -> u32 {
+        self.texture.sample_count()
+    }
+
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Copies the first mip level back to the CPU and blocks until it's
+    /// done. Only 8 bit RGBA and BGRA formats are supported, which covers
+    /// every surface format, and BGRA gets swizzled to RGBA.
+    pub fn read_to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<image::RgbaImage> {
+        self.read_layer_to_image(device, queue, 0)
+    }
+
+    /// `read_to_image` for one layer of an array texture or cubemap.
+    /// wgpu's GL backend can't copy out of cubemaps, and leaves the
+    /// result all zeros.
+    pub fn read_layer_to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layer: u32,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let bgra = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => bail!("Unable to read back {:?} textures", format),
+        };
+
+        let size = self.size();
+        let bytes_per_row = size.width * 4;
+        let padded_bytes_per_row = padded_bytes_per_row(bytes_per_row);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture::read_to_image"),
+            size: (padded_bytes_per_row * size.height) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                origin: wgpu::Origin3d {
+                    z: layer,
+                    ..Default::default()
+                },
+                ..self.texture.as_image_copy()
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..size
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .context("Texture readback was cancelled")?
+            .context("Unable to map texture readback buffer")?;
+
+        let mut pixels = unpad_rows(
+            &slice.get_mapped_range(),
+            bytes_per_row,
+            padded_bytes_per_row,
+            size.height,
+        );
+        buffer.unmap();
+
+        if bgra {
+            pixels.chunks_exact_mut(4).for_each(|p| p.swap(0, 2));
+        }
+
+        image::RgbaImage::from_raw(size.width, size.height, pixels)
+            .context("Texture readback has the wrong size")
+    }
+}
+
+/// Texture to buffer copies need every row to start on a multiple of
+/// `COPY_BYTES_PER_ROW_ALIGNMENT`.
+fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    bytes_per_row.div_ceil(align) * align
+}
+
+/// Drops the padding `padded_bytes_per_row` added to the end of each row.
+fn unpad_rows(data: &[u8], bytes_per_row: u32, padded_bytes_per_row: u32, height: u32) -> Vec<u8> {
+    data.chunks(padded_bytes_per_row as usize)
+        .take(height as usize)
+        .flat_map(|row| &row[..bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn loads_a_png_at_its_size() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let dir = crate::resources::temp_dir("checkerboard");
+        let path = dir.join("checkerboard.png");
+        // Not a power of two either way
+        let checkerboard = image::RgbaImage::from_fn(6, 3, |x, y| {
+            let c = if (x + y) % 2 == 0 { 0 } else { 255 };
+            image::Rgba([c, c, c, 255])
+        });
+        checkerboard.save(&path).unwrap();
+
+        let texture = Texture::from_file(&device, &queue, path.to_str().unwrap())
+            .block_on()
+            .unwrap();
+        let size = texture.size();
+        assert_eq!((size.width, size.height), (6, 3));
+        assert_eq!(texture.format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+        let pixels = texture.read_to_image(&device, &queue).unwrap();
+        assert_eq!(pixels, checkerboard);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cubemap_faces_keep_their_order() {
+        let Some((backend, device, queue)) = crate::game::test_backend_device() else {
+            return;
+        };
+        if backend == wgpu::Backend::Gl {
+            eprintln!("Skipping, wgpu's GL backend can't read cubemaps back");
+            return;
+        }
+        let dir = crate::resources::temp_dir("cubemap");
+        // A different color for each of +X, -X, +Y, -Y, +Z and -Z
+        let colors = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 0, 255],
+            [0, 255, 255, 255],
+            [255, 0, 255, 255],
+        ];
+        let paths = std::array::from_fn(|i| {
+            let path = dir.join(format!("face{}.png", i));
+            image::RgbaImage::from_pixel(2, 2, image::Rgba(colors[i]))
+                .save(&path)
+                .unwrap();
+            path.to_str().unwrap().to_owned()
+        });
+
+        let cubemap = Texture::cubemap_from_files(&device, &queue, &paths)
+            .block_on()
+            .unwrap();
+        assert_eq!(cubemap.size().depth_or_array_layers, 6);
+        for (layer, color) in colors.into_iter().enumerate() {
+            let face = cubemap
+                .read_layer_to_image(&device, &queue, layer as u32)
+                .unwrap();
+            assert_eq!(face, image::RgbaImage::from_pixel(2, 2, image::Rgba(color)));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mipmaps_go_down_to_one_texel() {
+        let Some((device, queue)) = crate::game::test_device() else {
+            return;
+        };
+        let texture = Texture::from_image_data(
+            &device,
+            &queue,
+            None,
+            256,
+            256,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &vec![128; 256 * 256 * 4],
+        );
+        assert_eq!(texture.mip_level_count(), 1);
+        let texture = texture.with_mipmaps(&device, &queue, &MipmapPipeline::new(&device));
+        // 256, 128, 64, 32, 16, 8, 4, 2 and 1
+        assert_eq!(texture.mip_level_count(), 9);
+        assert_eq!(texture.size().width, 256);
+    }
+
+    #[test]
+    fn depth_matches_the_msaa_sample_count() {
+        let Some((device, _)) = crate::game::test_device() else {
+            return;
+        };
+        let depth = Texture::depth_texture(&device, 64, 32, 4);
+        assert_eq!(depth.sample_count(), 4);
+        assert_eq!(depth.format(), Texture::DEPTH_FORMAT);
+        assert_eq!(Texture::depth_texture(&device, 64, 32, 1).sample_count(), 1);
+    }
+
+    #[test]
+    fn rows_get_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(4), 256);
+        assert_eq!(padded_bytes_per_row(256), 256);
+        assert_eq!(padded_bytes_per_row(257), 512);
+    }
+
+    #[test]
+    fn unpadding_keeps_only_the_pixels() {
+        // Two rows of 3 bytes, each padded out to 5
+        let data = [1, 2, 3, 0, 0, 4, 5, 6, 0, 0];
+        assert_eq!(unpad_rows(&data, 3, 5, 2), [1, 2, 3, 4, 5, 6]);
+        // The last row's padding can be missing
+        assert_eq!(unpad_rows(&data[..8], 3, 5, 2), [1, 2, 3, 4, 5, 6]);
+    }
 }
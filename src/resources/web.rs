@@ -0,0 +1,65 @@
+//! Browsers don't have a filesystem, so files get fetched relative to the
+//! page instead, and saved files go in local storage.
+
+use anyhow::{anyhow, Context};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// `JsValue` doesn't implement `Error`, so this is the best we can do.
+fn js_error(e: JsValue) -> anyhow::Error {
+    anyhow!("{:?}", e)
+}
+
+async fn fetch(path: &str) -> anyhow::Result<web_sys::Response> {
+    let window = web_sys::window().context("No window to fetch from")?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(path))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    // A 404 still resolves, it just isn't ok
+    if !response.ok() {
+        anyhow::bail!(
+            "Unable to fetch {}: {} {}",
+            path,
+            response.status(),
+            response.status_text()
+        );
+    }
+    Ok(response)
+}
+
+pub(super) async fn fetch_text(path: &str) -> anyhow::Result<String> {
+    let response = fetch(path).await?;
+    let text = JsFuture::from(response.text().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    text.as_string()
+        .with_context(|| format!("{} isn't text", path))
+}
+
+pub(super) async fn fetch_binary(path: &str) -> anyhow::Result<Vec<u8>> {
+    let response = fetch(path).await?;
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+fn local_storage() -> anyhow::Result<web_sys::Storage> {
+    web_sys::window()
+        .context("No window to store things in")?
+        .local_storage()
+        .map_err(js_error)?
+        .context("Local storage is disabled")
+}
+
+/// Keyed by path, so `stored` finds it under the same name it was saved
+/// with.
+pub(super) fn store(path: &str, contents: &str) -> anyhow::Result<()> {
+    local_storage()?.set_item(path, contents).map_err(js_error)
+}
+
+pub(super) fn stored(path: &str) -> anyhow::Result<Option<String>> {
+    local_storage()?.get_item(path).map_err(js_error)
+}
@@ -0,0 +1,176 @@
+use instant::{Duration, Instant};
+
+/// Averages frame times over a fixed window, so displayed numbers don't
+/// flicker every frame.
+#[derive(Debug)]
+pub struct FrameTimer {
+    window: f32,
+    elapsed: f32,
+    frames: u32,
+    average: Option<f32>,
+}
+
+impl FrameTimer {
+    /// `window` is in seconds.
+    pub fn new(window: f32) -> Self {
+        Self {
+            window,
+            elapsed: 0.0,
+            frames: 0,
+            average: None,
+        }
+    }
+
+    /// Call once per frame. Returns true when a new average is ready.
+    pub fn tick(&mut self, dt: f32) -> bool {
+        self.elapsed += dt;
+        self.frames += 1;
+        if self.elapsed < self.window {
+            return false;
+        }
+
+        self.average = average_frame_time(self.elapsed, self.frames);
+        self.elapsed = 0.0;
+        self.frames = 0;
+        true
+    }
+
+    /// Seconds per frame, `None` until the first window has passed.
+    pub fn frame_time(&self) -> Option<f32> {
+        self.average
+    }
+
+    pub fn fps(&self) -> Option<f32> {
+        self.average.map(|t| 1.0 / t)
+    }
+}
+
+/// Hands back a value once it's changed and then stayed the same for a
+/// whole interval, so something that keeps changing isn't saved over and
+/// over. Checking is cheap to call every frame, it only looks at the value
+/// once per interval.
+#[derive(Debug)]
+pub struct Debounce<T> {
+    interval: Duration,
+    last_check: Option<Instant>,
+    pending: Option<T>,
+    settled: T,
+}
+
+impl<T: PartialEq> Debounce<T> {
+    /// `settled` is the starting value, it doesn't get handed back.
+    pub fn new(interval: Duration, settled: T) -> Self {
+        Self {
+            interval,
+            last_check: None,
+            pending: None,
+            settled,
+        }
+    }
+
+    /// Whether `settle` wants a new value yet.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_check
+            .is_none_or(|last_check| now - last_check >= self.interval)
+    }
+
+    /// Returns the value once it's been the same for two checks in a row.
+    pub fn settle(&mut self, now: Instant, value: T) -> Option<&T> {
+        self.last_check = Some(now);
+        if value == self.settled {
+            self.pending = None;
+            return None;
+        }
+        if self.pending.as_ref() != Some(&value) {
+            self.pending = Some(value);
+            return None;
+        }
+        self.settled = self.pending.take()?;
+        Some(&self.settled)
+    }
+}
+
+/// `None` if there's nothing to average, or no time passed.
+pub fn average_frame_time(elapsed: f32, frames: u32) -> Option<f32> {
+    (frames > 0 && elapsed > 0.0).then(|| elapsed / frames as f32)
+}
+
+/// How much longer to wait before starting the next frame, given how long
+/// ago the current one started. A `max_fps` of 0 never waits.
+pub fn time_until_next_frame(since_frame_start: Duration, max_fps: u32) -> Duration {
+    if max_fps == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(1.0 / max_fps as f64).saturating_sub(since_frame_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_out_the_rest_of_the_frame() {
+        let wait = time_until_next_frame(Duration::from_millis(4), 100);
+        assert_eq!(wait, Duration::from_millis(6));
+    }
+
+    #[test]
+    fn slow_frames_and_uncapped_fps_dont_wait() {
+        assert_eq!(
+            time_until_next_frame(Duration::from_millis(20), 100),
+            Duration::ZERO
+        );
+        assert_eq!(time_until_next_frame(Duration::ZERO, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn frame_timer_averages_each_window() {
+        let mut timer = FrameTimer::new(0.5);
+        // 0.1 + 0.1 + 0.1 + 0.3 crosses the window on the fourth frame
+        for dt in [0.1, 0.1, 0.1] {
+            assert!(!timer.tick(dt));
+        }
+        assert_eq!(timer.frame_time(), None);
+        assert!(timer.tick(0.3));
+        assert!((timer.frame_time().unwrap() - 0.15).abs() < 1e-6);
+        assert!((timer.fps().unwrap() - 1.0 / 0.15).abs() < 1e-3);
+
+        // The next window starts from scratch
+        assert!(timer.tick(0.5));
+        assert!((timer.frame_time().unwrap() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nothing_to_average() {
+        assert_eq!(average_frame_time(1.0, 0), None);
+        assert_eq!(average_frame_time(0.0, 10), None);
+        assert_eq!(average_frame_time(1.0, 4), Some(0.25));
+    }
+
+    #[test]
+    fn debounce_waits_for_the_value_to_settle() {
+        let interval = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut debounce = Debounce::new(interval, 0);
+        assert!(debounce.is_due(start));
+
+        assert_eq!(debounce.settle(start, 1), None);
+        assert!(!debounce.is_due(start + interval / 2));
+        // Still changing, so the count starts over
+        assert_eq!(debounce.settle(start + interval, 2), None);
+        assert_eq!(debounce.settle(start + interval * 2, 2), Some(&2));
+        // Settled values aren't handed back again
+        assert_eq!(debounce.settle(start + interval * 3, 2), None);
+    }
+
+    #[test]
+    fn debounce_drops_changes_that_get_undone() {
+        let start = Instant::now();
+        let mut debounce = Debounce::new(Duration::from_secs(1), 0);
+        assert_eq!(debounce.settle(start, 1), None);
+        assert_eq!(debounce.settle(start, 0), None);
+        // Having seen 1 before the undo doesn't count towards settling
+        assert_eq!(debounce.settle(start, 1), None);
+        assert_eq!(debounce.settle(start, 1), Some(&1));
+    }
+}
@@ -0,0 +1,267 @@
+use winit::{
+    event::{ElementState, WindowEvent},
+    window::Window,
+};
+
+use crate::{
+    game::{PresentMode, MAX_EXPOSURE, MAX_SENSITIVITY, MIN_EXPOSURE, MIN_SENSITIVITY},
+    pipelines::{
+        fur::{FurParams, MAX_LAYERS, MIN_LAYERS},
+        post_process::Tonemap,
+    },
+};
+
+/// Immediate mode overlay drawn on top of the post processed frame.
+pub struct Ui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+}
+
+impl Ui {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        window: &Window,
+    ) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, surface_format, None, 1);
+        Self {
+            context,
+            state,
+            renderer,
+        }
+    }
+
+    /// Returns true if egui wants the event for itself, in which case the
+    /// game shouldn't react to it. See [`captures`].
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        let response = self.state.on_window_event(window, event);
+        captures(event, response.consumed)
+    }
+
+    /// Runs `build` to lay out the ui and draws the result onto `view`.
+    /// The view is loaded, not cleared.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        window: &Window,
+        size: [u32; 2],
+        build: impl FnOnce(&egui::Context),
+    ) {
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, build);
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        let paint_jobs = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let screen = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: size,
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        // The returned buffers only come from paint callbacks, which we
+        // don't use.
+        self.renderer
+            .update_buffers(device, queue, encoder, &paint_jobs, &screen);
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Ui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        store: wgpu::StoreOp::Store,
+                        load: wgpu::LoadOp::Load,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.renderer.render(&mut pass, &paint_jobs, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+/// Whether the game should skip `event`, given whether egui `consumed` it.
+/// egui only consumes clicks while the pointer is over the ui, and keys
+/// while a text field has focus. Releases always get through, so nothing
+/// stays held in the game because it was let go over the ui.
+pub fn captures(event: &WindowEvent, consumed: bool) -> bool {
+    match event {
+        WindowEvent::MouseInput {
+            state: ElementState::Released,
+            ..
+        } => false,
+        WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Released => false,
+        _ => consumed,
+    }
+}
+
+/// Copy of the settings the controls panel can change. The game fills this
+/// in before drawing the ui and applies whatever changed afterwards.
+pub struct Controls {
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    pub show_grid: bool,
+    pub clear_color: [f32; 4],
+    pub mouse_sensitivity: f32,
+    pub move_speed: f32,
+    pub present_mode: PresentMode,
+    /// The modes the surface supports, to pick `present_mode` from.
+    pub present_modes: Vec<PresentMode>,
+    pub fur: FurParams,
+    pub fur_layers: u32,
+    pub light_direction: [f32; 3],
+    pub light_color: [f32; 3],
+    pub ambient_color: [f32; 3],
+    /// Set when the reload button was clicked.
+    pub reload_model: bool,
+}
+
+impl Controls {
+    pub fn show(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Controls").show(ctx, |ui| {
+            ui.add(
+                egui::Slider::new(&mut self.exposure, MIN_EXPOSURE..=MAX_EXPOSURE)
+                    .logarithmic(true)
+                    .text("Exposure"),
+            );
+            egui::ComboBox::from_label("Tonemap")
+                .selected_text(format!("{:?}", self.tonemap))
+                .show_ui(ui, |ui| {
+                    for tonemap in [Tonemap::None, Tonemap::Reinhard, Tonemap::Aces] {
+                        ui.selectable_value(&mut self.tonemap, tonemap, format!("{:?}", tonemap));
+                    }
+                });
+            ui.checkbox(&mut self.show_grid, "Show grid");
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgba_unmultiplied(&mut self.clear_color);
+                ui.label("Clear color");
+            });
+            ui.add(
+                egui::Slider::new(
+                    &mut self.mouse_sensitivity,
+                    MIN_SENSITIVITY..=MAX_SENSITIVITY,
+                )
+                .text("Mouse sensitivity"),
+            );
+            ui.add(
+                egui::Slider::new(&mut self.move_speed, 0.01..=10.0)
+                    .logarithmic(true)
+                    .text("Move speed"),
+            );
+            egui::ComboBox::from_label("Present mode")
+                .selected_text(format!("{:?}", self.present_mode))
+                .show_ui(ui, |ui| {
+                    for mode in &self.present_modes {
+                        ui.selectable_value(&mut self.present_mode, *mode, format!("{:?}", mode));
+                    }
+                });
+
+            egui::CollapsingHeader::new("Fur").show(ui, |ui| self.show_fur(ui));
+            egui::CollapsingHeader::new("Light").show(ui, |ui| self.show_light(ui));
+
+            self.reload_model = ui.button("Reload model").clicked();
+        });
+    }
+
+    fn show_fur(&mut self, ui: &mut egui::Ui) {
+        let fur = &mut self.fur;
+        ui.add(egui::Slider::new(&mut self.fur_layers, MIN_LAYERS..=MAX_LAYERS).text("Layers"));
+        ui.add(egui::Slider::new(&mut fur.length, 0.0..=0.5).text("Length"));
+        ui.add(
+            egui::Slider::new(&mut fur.density, 1.0..=1000.0)
+                .logarithmic(true)
+                .text("Density"),
+        );
+        ui.add(egui::Slider::new(&mut fur.thickness, 0.0..=1.0).text("Thickness"));
+        let mut gravity = fur.gravity.truncate().to_array();
+        vec3_drag(ui, "Gravity", &mut gravity);
+        fur.gravity = glam::Vec3::from(gravity).extend(fur.gravity.w);
+        color_edit(ui, "Root color", &mut fur.root_color);
+        color_edit(ui, "Tip color", &mut fur.tip_color);
+    }
+
+    fn show_light(&mut self, ui: &mut egui::Ui) {
+        vec3_drag(ui, "Direction", &mut self.light_direction);
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut self.light_color);
+            ui.label("Color");
+        });
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut self.ambient_color);
+            ui.label("Ambient");
+        });
+    }
+}
+
+fn vec3_drag(ui: &mut egui::Ui, label: &str, values: &mut [f32; 3]) {
+    ui.horizontal(|ui| {
+        for value in values {
+            ui.add(egui::DragValue::new(value).speed(0.01));
+        }
+        ui.label(label);
+    });
+}
+
+/// Edits the rgb part of a color, leaving alpha alone.
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut glam::Vec4) {
+    let mut rgb = color.truncate().to_array();
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut rgb);
+        ui.label(label);
+    });
+    *color = glam::Vec3::from(rgb).extend(color.w);
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{DeviceId, MouseButton};
+
+    use super::*;
+
+    fn click(state: ElementState) -> WindowEvent {
+        WindowEvent::MouseInput {
+            // SAFETY: only compared against, never handed to winit
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button: MouseButton::Left,
+        }
+    }
+
+    #[test]
+    fn presses_follow_egui() {
+        assert!(captures(&click(ElementState::Pressed), true));
+        assert!(!captures(&click(ElementState::Pressed), false));
+        assert!(!captures(&WindowEvent::Focused(true), false));
+    }
+
+    #[test]
+    fn releases_always_reach_the_game() {
+        assert!(!captures(&click(ElementState::Released), true));
+    }
+}